@@ -0,0 +1,258 @@
+extern crate nalgebra as na;
+
+use std::io::Write;
+use std::path::Path;
+
+use multibody_dynamics::multibody::MultiBody;
+use na::{Isometry3, SMatrix, SVector, Vector2};
+use serde::Deserialize;
+
+use crate::utils::comp_tcm_tot;
+use crate::Config;
+
+fn default_steps() -> usize {
+    13
+}
+
+fn default_singular_tolerance() -> f64 {
+    0.01
+}
+
+/// Maximum number of joint configurations in a sweep.
+const MAX_CONFIGURATIONS: usize = 1_000_000;
+
+/// Sweep of joint configurations over which the thruster configuration matrix is analysed. The swept joints take
+/// `steps` angles evenly spread over `range`, the others stay at `joint_angles`, and the results are saved in
+/// aiauv_allocation.csv instead of running the simulation.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AllocationAnalysisConfig {
+    /// Swept joints (1-based).
+    joints: Vec<usize>,
+    /// Lower and upper swept joint angle [rad].
+    range: Vector2<f64>,
+    #[serde(default = "default_steps")]
+    steps: usize,
+    #[serde(default)]
+    joint_angles: SVector<f64, 8>,
+    /// Ratio of the smallest to the largest singular value of the base wrench map below which a shape is singular.
+    #[serde(default = "default_singular_tolerance")]
+    singular_tolerance: f64,
+}
+
+/// Allocation capability of `tcm_tot` in one joint configuration. The base quantities are those of the map from
+/// the thrusts to the base wrench, and the volume is that of the polytope of base wrenches within the thrust
+/// limits, NaN without limits.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationMetrics {
+    pub rank: usize,
+    pub condition_number: f64,
+    pub min_singular_value: f64,
+    pub base_rank: usize,
+    pub base_condition_number: f64,
+    pub base_min_singular_value: f64,
+    pub base_wrench_volume: f64,
+}
+
+impl AllocationAnalysisConfig {
+    pub fn validate(&self, num_joints: usize) -> Result<(), String> {
+        if self.joints.is_empty() {
+            return Err("The allocation analysis needs at least one swept joint".to_string());
+        }
+        if self
+            .joints
+            .iter()
+            .any(|&joint| joint == 0 || joint > num_joints)
+        {
+            return Err(format!(
+                "The swept joints must be between 1 and {num_joints}"
+            ));
+        }
+        if self.range[0] > self.range[1] || self.steps == 0 {
+            return Err("The sweep needs an increasing range and at least one step".to_string());
+        }
+        if self.singular_tolerance <= 0.0 || self.singular_tolerance >= 1.0 {
+            return Err("The singular tolerance must be between 0 and 1".to_string());
+        }
+        if self
+            .steps
+            .checked_pow(self.joints.len() as u32)
+            .is_none_or(|n| n > MAX_CONFIGURATIONS)
+        {
+            return Err(format!(
+                "The sweep exceeds {MAX_CONFIGURATIONS} configurations"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Joint angles of the `n`th configuration of the sweep, the first swept joint varying fastest.
+    fn joint_angles(&self, mut n: usize) -> SVector<f64, 8> {
+        let mut theta = self.joint_angles;
+        for &joint in &self.joints {
+            let step = (self.range[1] - self.range[0]) / (self.steps - 1).max(1) as f64;
+            theta[joint - 1] = self.range[0] + (n % self.steps) as f64 * step;
+            n /= self.steps;
+        }
+        theta
+    }
+
+    /// Sweeps the joint configurations, saves the metrics to `path` and reports the singular shapes.
+    pub fn run(
+        &self,
+        cfg: &Config,
+        multibody: &MultiBody<9, 14>,
+        path: &Path,
+    ) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(
+            file,
+            "theta_1,theta_2,theta_3,theta_4,theta_5,theta_6,theta_7,theta_8,rank,condition_number,\
+             min_singular_value,base_rank,base_condition_number,base_min_singular_value,base_wrench_volume,singular"
+        )?;
+
+        let num_configurations = self.steps.pow(self.joints.len() as u32);
+        let mut singular = Vec::new();
+        let mut worst: Option<(SVector<f64, 8>, AllocationMetrics)> = None;
+        for n in 0..num_configurations {
+            let theta = self.joint_angles(n);
+            // with the base frame at the origin
+            let conf =
+                multibody.minimal_to_homogenous_configuration(&Isometry3::identity(), &theta);
+            let tcm_tot = comp_tcm_tot(cfg, &multibody.compute_jacobians(&conf));
+            let metrics = allocation_metrics(&tcm_tot, cfg.thrust_limits.as_ref());
+            let is_singular = metrics.base_rank < 6
+                || metrics.base_condition_number * self.singular_tolerance > 1.0;
+            if is_singular {
+                singular.push(theta);
+            }
+            if worst
+                .is_none_or(|(_, w)| metrics.base_min_singular_value < w.base_min_singular_value)
+            {
+                worst = Some((theta, metrics));
+            }
+            let angles: Vec<String> = theta.iter().map(|x| x.to_string()).collect();
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{},{}",
+                angles.join(","),
+                metrics.rank,
+                metrics.condition_number,
+                metrics.min_singular_value,
+                metrics.base_rank,
+                metrics.base_condition_number,
+                metrics.base_min_singular_value,
+                metrics.base_wrench_volume,
+                is_singular as u8
+            )?;
+        }
+
+        println!(
+            "Allocation analysis of {num_configurations} joint configurations saved in: {path:?}"
+        );
+        if let Some((theta, metrics)) = worst {
+            println!(
+                "Weakest base wrench authority: minimum singular value {:.4}, condition number {:.1} at joint angles {:.3?}",
+                metrics.base_min_singular_value,
+                metrics.base_condition_number,
+                theta.as_slice()
+            );
+        }
+        println!("{} singular shapes", singular.len());
+        for theta in singular.iter().take(10) {
+            println!("  {:.3?}", theta.as_slice());
+        }
+        if singular.len() > 10 {
+            println!("  ...");
+        }
+        Ok(())
+    }
+}
+
+pub fn allocation_metrics(
+    tcm_tot: &SMatrix<f64, 14, 20>,
+    thrust_limits: Option<&Vector2<f64>>,
+) -> AllocationMetrics {
+    let base = tcm_tot.fixed_view::<6, 12>(0, 0).into_owned();
+    let sigma = tcm_tot.singular_values();
+    let base_sigma = base.singular_values();
+    let rank = |sigma: &[f64]| {
+        let tolerance = 1e-9 * sigma.iter().fold(1.0, |max: f64, &s| max.max(s));
+        sigma.iter().filter(|&&s| s > tolerance).count()
+    };
+    let (max, min) = (sigma.max(), sigma.min());
+    let (base_max, base_min) = (base_sigma.max(), base_sigma.min());
+    AllocationMetrics {
+        rank: rank(sigma.as_slice()),
+        condition_number: max / min,
+        min_singular_value: min,
+        base_rank: rank(base_sigma.as_slice()),
+        base_condition_number: base_max / base_min,
+        base_min_singular_value: base_min,
+        base_wrench_volume: thrust_limits.map_or(f64::NAN, |limits| {
+            zonotope_volume(&base, limits[1] - limits[0])
+        }),
+    }
+}
+
+/// Volume of the zonotope spanned by the columns of `generators` scaled by `length`, i.e. the image of the box of
+/// thrusts: the sum of |det| over all sets of 6 columns, times length^6.
+fn zonotope_volume(generators: &SMatrix<f64, 6, 12>, length: f64) -> f64 {
+    let mut volume = 0.0;
+    for subset in 0u32..1 << 12 {
+        if subset.count_ones() != 6 {
+            continue;
+        }
+        let mut columns = SMatrix::<f64, 6, 6>::zeros();
+        for (k, j) in (0..12).filter(|j| subset & (1 << j) != 0).enumerate() {
+            columns.set_column(k, &generators.column(j));
+        }
+        volume += columns.determinant().abs();
+    }
+    volume * length.powi(6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+    use na::stack;
+
+    #[test]
+    fn test_allocation_metrics() {
+        // two unit thrusters along each axis span a box of side 2 for thrusts in [0, 1]
+        let generators = stack![
+            SMatrix::<f64, 6, 6>::identity(),
+            SMatrix::<f64, 6, 6>::identity()
+        ];
+        assert!((zonotope_volume(&generators, 1.0) - 64.0).abs() < 1e-9);
+
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let analysis: AllocationAnalysisConfig =
+            serde_yaml::from_str("{joints: [2, 4], range: [-1.0, 1.0], steps: 5}").unwrap();
+        analysis.validate(8).unwrap();
+        for invalid in [
+            "{joints: [], range: [-1.0, 1.0]}",
+            "{joints: [2], range: [-1.0, 1.0], singular_tolerance: 1.0}",
+        ] {
+            let analysis: AllocationAnalysisConfig = serde_yaml::from_str(invalid).unwrap();
+            assert!(analysis.validate(8).is_err());
+        }
+        assert_eq!(analysis.joint_angles(7)[1], 0.0);
+        assert_eq!(analysis.joint_angles(7)[3], -0.5);
+
+        // the straight shape controls all the base DOFs, and the polytope grows with the thrust range
+        let conf = multibody.minimal_to_homogenous_configuration(
+            &Isometry3::identity(),
+            &SVector::<f64, 8>::zeros(),
+        );
+        let tcm_tot = comp_tcm_tot(&cfg, &multibody.compute_jacobians(&conf));
+        let metrics = allocation_metrics(&tcm_tot, Some(&Vector2::new(-1.0, 1.0)));
+        assert!(metrics.rank == 14 && metrics.base_rank == 6 && metrics.base_wrench_volume > 0.0);
+        let wider = allocation_metrics(&tcm_tot, Some(&Vector2::new(-2.0, 2.0)));
+        assert!((wider.base_wrench_volume / metrics.base_wrench_volume - 64.0).abs() < 1e-9);
+        assert!(allocation_metrics(&tcm_tot, None)
+            .base_wrench_volume
+            .is_nan());
+    }
+}
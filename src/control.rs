@@ -0,0 +1,548 @@
+extern crate nalgebra as na;
+
+use multibody_dynamics::math_functions::skew;
+use multibody_dynamics::multibody::MultiBody;
+use na::{
+    stack, Isometry3, Matrix6, SMatrix, SVector, Translation3, UnitQuaternion, Vector3, Vector6,
+};
+use serde::Deserialize;
+
+use crate::estimator::NavState;
+use crate::mpc::MpcConfig;
+use crate::pid::PidConfig;
+use crate::reference::Reference;
+use crate::robust::{AdaptiveConfig, SuperTwistingConfig};
+use crate::utils::link_damping;
+use crate::Config;
+
+/// Controller computing the generalized forces on the base and the joints, which are then allocated to the
+/// thrusters and joint motors.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum ControllerConfig {
+    /// PID control of the base pose and the joint angles.
+    Pid(Box<PidConfig>),
+    /// Pose control of a point on a link, using the base and the joints as redundant DOFs.
+    TaskSpace(Box<TaskSpaceConfig>),
+    /// Inverse dynamics feedforward along the reference trajectory, with PD feedback.
+    ComputedTorque(Box<ComputedTorqueConfig>),
+    /// Model predictive control, commanding the thrusters and joint motors directly at its own sample time.
+    Mpc(Box<MpcConfig>),
+    /// Super-twisting sliding-mode control along the reference trajectory.
+    SuperTwisting(Box<SuperTwistingConfig>),
+    /// Inverse dynamics with online estimates of the damping and restoring forces along the reference trajectory.
+    Adaptive(Box<AdaptiveConfig>),
+}
+
+impl ControllerConfig {
+    /// Name of the controller in the output file names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControllerConfig::Pid(_) => "pid",
+            ControllerConfig::TaskSpace(_) => "task_space",
+            ControllerConfig::ComputedTorque(_) => "computed_torque",
+            ControllerConfig::Mpc(_) => "mpc",
+            ControllerConfig::SuperTwisting(_) => "super_twisting",
+            ControllerConfig::Adaptive(_) => "adaptive",
+        }
+    }
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        ControllerConfig::Pid(Box::default())
+    }
+}
+
+fn default_task_k_p() -> Vector6<f64> {
+    Vector6::repeat(2.25)
+}
+
+fn default_task_k_d() -> Vector6<f64> {
+    Vector6::repeat(3.0)
+}
+
+fn default_posture_k_p() -> f64 {
+    4.0
+}
+
+fn default_posture_k_d() -> f64 {
+    4.0
+}
+
+fn default_base_k_d() -> f64 {
+    2.0
+}
+
+fn default_wrench_limits() -> Vector6<f64> {
+    Vector6::repeat(100.0)
+}
+
+fn default_joint_torque_limit() -> f64 {
+    80.0
+}
+
+/// Operational space control of the pose of a tool point on a link. The inverse dynamics give the generalized
+/// forces for a desired tool acceleration, and the remaining DOFs are driven towards a joint posture and
+/// a resting base in the dynamically consistent null space of the tool Jacobian. Gains are in 1/s^2 and 1/s.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TaskSpaceConfig {
+    /// Link carrying the tool (1-based).
+    link: usize,
+    /// Tool point, expressed in the link frame.
+    #[serde(default)]
+    position: Vector3<f64>,
+    /// Desired tool position in the world frame.
+    position_d: Vector3<f64>,
+    /// Desired tool attitude relative to the world frame.
+    #[serde(default)]
+    roll_pitch_yaw_d: Vector3<f64>,
+    #[serde(default = "default_task_k_p")]
+    k_p: Vector6<f64>,
+    #[serde(default = "default_task_k_d")]
+    k_d: Vector6<f64>,
+    /// Joint angles the redundant DOFs are driven towards.
+    #[serde(default)]
+    posture: SVector<f64, 8>,
+    #[serde(default = "default_posture_k_p")]
+    posture_k_p: f64,
+    #[serde(default = "default_posture_k_d")]
+    posture_k_d: f64,
+    /// Damping of the base velocities in the null space.
+    #[serde(default = "default_base_k_d")]
+    base_k_d: f64,
+    #[serde(default = "default_wrench_limits")]
+    wrench_limits: Vector6<f64>,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+}
+
+impl TaskSpaceConfig {
+    pub fn validate(&self, num_links: usize) -> Result<(), String> {
+        if self.link == 0 || self.link > num_links {
+            return Err(format!(
+                "The task-space link must be between 1 and {}",
+                num_links
+            ));
+        }
+        if self.wrench_limits.min() <= 0.0 || self.joint_torque_limit <= 0.0 {
+            return Err("The task-space force limits must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Computes the base wrench and joint torques within the limits.
+    pub fn control(
+        &self,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        conf: &[Isometry3<f64>],
+        jacs: &[SMatrix<f64, 6, 14>],
+        nav: &NavState,
+    ) -> SVector<f64, 14> {
+        let (tau_task, tau_posture) = self.generalized_forces(multibody, cfg, conf, jacs, nav);
+        let limits = stack![self.wrench_limits; SVector::<f64, 8>::repeat(self.joint_torque_limit)];
+        limit_forces(&tau_task, &tau_posture, &limits)
+    }
+
+    /// Computes the generalized forces of the task, tau = J^T Lambda (a_d - J_dot zeta) + h, and of the
+    /// posture, N^T D a_0, where a_d is the desired tool acceleration, h the bias forces and a_0 the posture
+    /// acceleration. Scaling a_0 by the diagonal D of the mass matrix rather than the full matrix keeps the
+    /// self-motion of the null space stable.
+    fn generalized_forces(
+        &self,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        conf: &[Isometry3<f64>],
+        jacs: &[SMatrix<f64, 6, 14>],
+        nav: &NavState,
+    ) -> (SVector<f64, 14>, SVector<f64, 14>) {
+        let k = self.link - 1;
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+
+        // Jacobian of the tool point, in axes parallel to the link frame
+        let mut to_tool = Matrix6::identity();
+        to_tool
+            .fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&(-skew(&self.position)));
+        let jac = to_tool * jacs[k];
+        let jac_dot = to_tool * multibody.compute_jacobian_derivatives(jacs, conf, &zeta)[k];
+
+        // The link poses are relative to the base
+        let base = Isometry3::from_parts(nav.pos.into(), nav.quat);
+        let tool = base
+            * multibody.compute_body_configurations(conf)[k]
+            * Translation3::from(self.position);
+        let quat_d = UnitQuaternion::from_euler_angles(
+            self.roll_pitch_yaw_d[0],
+            self.roll_pitch_yaw_d[1],
+            self.roll_pitch_yaw_d[2],
+        );
+        let quat_e = quat_d.inverse() * tool.rotation;
+        let pos_e = tool.rotation.inverse() * (tool.translation.vector - self.position_d);
+        let att_e = quat_e.w.signum() * quat_e.vector();
+        let accel_d =
+            -self.k_p.component_mul(&stack![pos_e; att_e]) - self.k_d.component_mul(&(jac * zeta));
+
+        let mass = multibody.compute_mass_matrix(conf);
+        let mass_inv = mass.try_inverse().unwrap();
+        let lambda = (jac * mass_inv * jac.transpose()).try_inverse().unwrap();
+        let jac_bar = mass_inv * jac.transpose() * lambda;
+        let null = SMatrix::<f64, 14, 14>::identity() - jac.transpose() * jac_bar.transpose();
+
+        let posture_accel = stack![
+            -self.base_k_d * nav.nu_b;
+            -self.posture_k_p * (nav.theta - self.posture) - self.posture_k_d * nav.theta_dot
+        ];
+
+        (
+            jac.transpose() * lambda * (accel_d - jac_dot * zeta)
+                + inverse_dynamics(multibody, cfg, conf, &zeta, &zeta, &SVector::zeros()),
+            null * mass.diagonal().component_mul(&posture_accel),
+        )
+    }
+}
+
+fn default_computed_torque_k_p() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        70.0, 70.0, 70.0, 100.0, 500.0, 500.0, 125.0, 125.0, 250.0, 250.0, 250.0, 250.0, 125.0,
+        125.0,
+    ])
+}
+
+fn default_computed_torque_k_d() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        20.0, 20.0, 20.0, 10.0, 30.0, 30.0, 10.0, 10.0, 20.0, 20.0, 20.0, 10.0, 10.0, 10.0,
+    ])
+}
+
+/// Computed-torque control of the base pose and joint angles along the reference trajectory. The inverse
+/// dynamics at the reference velocities and accelerations give the feedforward forces, including the added
+/// mass, damping and restoring forces, and the PD feedback acts on the base pose error in the base frame
+/// and the joint angle errors. The default gains are the proportional and derivative gains of the PID
+/// controller.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ComputedTorqueConfig {
+    #[serde(default = "default_computed_torque_k_p")]
+    k_p: SVector<f64, 14>,
+    #[serde(default = "default_computed_torque_k_d")]
+    k_d: SVector<f64, 14>,
+    #[serde(default)]
+    attitude_error: AttitudeError,
+    #[serde(default = "default_wrench_limits")]
+    wrench_limits: Vector6<f64>,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+}
+
+impl ComputedTorqueConfig {
+    /// Computes the saturated base wrench and joint torques.
+    pub fn control(
+        &self,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        conf: &[Isometry3<f64>],
+        nav: &NavState,
+        reference: &Reference,
+    ) -> SVector<f64, 14> {
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let (err, zeta_d, zeta_dot_d) = tracking_errors(nav, reference, self.attitude_error);
+
+        // Feedforward along the reference velocities, leaving the damping of the velocity errors to the
+        // hydrodynamics and the feedback
+        let tau = inverse_dynamics(multibody, cfg, conf, &zeta_d, &zeta_d, &zeta_dot_d)
+            - self.k_p.component_mul(&err)
+            - self.k_d.component_mul(&(zeta - zeta_d));
+        saturate(&tau, &self.wrench_limits, self.joint_torque_limit)
+    }
+}
+
+/// Formulation of the attitude error of the base relative to the reference attitude, for the error rotation
+/// R_e = R_d^T R by the angle phi about the axis k. The axis has the same coordinates in the base and the
+/// reference frame. All formulations reduce to phi/2 k for small errors, so that the gains carry over.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum AttitudeError {
+    /// Vector part of the error quaternion, sin(phi/2) k, with the sign of its scalar part so that the base
+    /// turns the short way around.
+    #[default]
+    Quaternion,
+    /// Logarithm of the error quaternion, phi/2 k with phi in [0, pi], growing linearly with the angle.
+    LogMap,
+    /// Skew-symmetric part of the error rotation matrix, 1/4 vee(R_e - R_e^T) = sin(phi)/2 k. Needs no
+    /// quaternions, but its gain fades beyond 90 degrees and vanishes when upside down.
+    RotationMatrix,
+}
+
+impl AttitudeError {
+    pub fn error(&self, quat: &UnitQuaternion<f64>, quat_d: &UnitQuaternion<f64>) -> Vector3<f64> {
+        let quat_e = quat_d.inverse() * quat;
+        match self {
+            AttitudeError::Quaternion => {
+                if quat_e.w < 0.0 {
+                    -quat_e.vector()
+                } else {
+                    quat_e.vector().into()
+                }
+            }
+            AttitudeError::LogMap => 0.5 * quat_e.scaled_axis(),
+            AttitudeError::RotationMatrix => {
+                let rot_e = quat_e.to_rotation_matrix();
+                let skew = rot_e.matrix() - rot_e.matrix().transpose();
+                0.25 * Vector3::new(skew[(2, 1)], skew[(0, 2)], skew[(1, 0)])
+            }
+        }
+    }
+}
+
+/// Computes the errors of the base pose and joint angles relative to the reference, and the reference
+/// velocities and accelerations. The position error and the reference velocities are expressed in the base
+/// frame, consistently with the attitude error.
+pub fn tracking_errors(
+    nav: &NavState,
+    reference: &Reference,
+    attitude_error: AttitudeError,
+) -> (SVector<f64, 14>, SVector<f64, 14>, SVector<f64, 14>) {
+    let err = stack![
+        nav.quat.inverse() * (nav.pos - reference.pos);
+        attitude_error.error(&nav.quat, &reference.quat);
+        nav.theta - reference.theta
+    ];
+    // The reference velocity turns with the base: d/dt (R^T v_d) = -omega x R^T v_d
+    let vel_d = nav.quat.inverse() * reference.vel;
+    let omega = nav.nu_b.fixed_rows::<3>(3);
+    let zeta_d = stack![vel_d; Vector3::zeros(); reference.theta_dot];
+    let zeta_dot_d = stack![-omega.cross(&vel_d); Vector3::zeros(); reference.theta_ddot];
+    (err, zeta_d, zeta_dot_d)
+}
+
+/// Clamps the base wrench and the joint torques to their limits.
+pub fn saturate(
+    tau: &SVector<f64, 14>,
+    wrench_limits: &Vector6<f64>,
+    joint_torque_limit: f64,
+) -> SVector<f64, 14> {
+    SVector::from_fn(|i, _| {
+        let limit = if i < 6 {
+            wrench_limits[i]
+        } else {
+            joint_torque_limit
+        };
+        tau[i].clamp(-limit, limit)
+    })
+}
+
+/// Computes the generalized forces M zeta_r_dot + C(zeta) zeta_r + D(zeta) + g from the inverse dynamics,
+/// assuming the water is at rest. With zeta_r = zeta and zero acceleration these are the bias forces.
+pub fn inverse_dynamics(
+    multibody: &MultiBody<9, 14>,
+    cfg: &Config,
+    conf: &[Isometry3<f64>],
+    zeta: &SVector<f64, 14>,
+    zeta_r: &SVector<f64, 14>,
+    zeta_r_dot: &SVector<f64, 14>,
+) -> SVector<f64, 14> {
+    // The inverse dynamics take the forces needed to overcome the damping, rather than the damping forces
+    let damping = |nu: &[Vector6<f64>], _: &[Vector6<f64>]| -> SMatrix<f64, 6, 9> {
+        let mut out = SMatrix::<f64, 6, 9>::zeros();
+        for (i, nu_i) in nu.iter().enumerate().take(9) {
+            out.column_mut(i).copy_from(&-link_damping(cfg, i, nu_i));
+        }
+        out
+    };
+    multibody.generalized_newton_euler(conf, zeta, zeta_r, zeta_r_dot, damping, &SVector::zeros())
+}
+
+/// Limits the sum of the task and posture forces to -limits..=limits. The task forces are scaled down if
+/// they exceed the limits on their own, which keeps their direction, and the posture forces are then scaled
+/// down to fit within what is left.
+fn limit_forces(
+    tau_task: &SVector<f64, 14>,
+    tau_posture: &SVector<f64, 14>,
+    limits: &SVector<f64, 14>,
+) -> SVector<f64, 14> {
+    let task_scale = tau_task
+        .iter()
+        .zip(limits.iter())
+        .fold(1.0_f64, |scale, (tau_i, limit)| {
+            scale.min(limit / tau_i.abs())
+        });
+    let tau_task = task_scale * tau_task;
+
+    // largest scaling of the posture forces with -limit <= task + scale * posture <= limit
+    let mut scale = 1.0_f64;
+    for i in 0..14 {
+        let (task, posture, limit) = (tau_task[i], tau_posture[i], limits[i]);
+        if posture != 0.0 {
+            let (lower, upper) = ((-limit - task) / posture, (limit - task) / posture);
+            scale = scale.min(lower.max(upper));
+        }
+    }
+    tau_task + scale.max(0.0) * tau_posture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+    use na::vector;
+
+    #[test]
+    fn test_task_space_inverse_dynamics() {
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let task: TaskSpaceConfig = serde_yaml::from_str(
+            "{link: 9, position: [0.3, 0.0, 0.0], position_d: [3.0, 0.5, 0.2], roll_pitch_yaw_d: [0.1, 0.0, 0.3]}",
+        )
+        .unwrap();
+
+        let nav = NavState {
+            pos: Vector3::new(0.2, -0.1, 0.3),
+            quat: UnitQuaternion::from_euler_angles(0.1, -0.2, 0.3),
+            nu_b: Vector6::new(0.3, 0.1, -0.1, 0.05, 0.1, -0.2),
+            theta: vector![0.3, -0.2, 0.1, 0.4, -0.3, 0.2, 0.1, -0.1],
+            theta_dot: vector![0.1, 0.2, -0.1, 0.0, 0.3, -0.2, 0.1, 0.1],
+        };
+        let base = Isometry3::from_parts(Translation3::from(nav.pos), nav.quat);
+        let conf = multibody.minimal_to_homogenous_configuration(&base, &nav.theta);
+        let jacs = multibody.compute_jacobians(&conf);
+        let (tau_task, tau_posture) = task.generalized_forces(&multibody, &cfg, &conf, &jacs, &nav);
+        let tau = tau_task + tau_posture;
+
+        // the forward dynamics driven by tau give the desired tool acceleration
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let damping = |_: &[Isometry3<f64>], nu: &[Vector6<f64>]| -> SMatrix<f64, 6, 9> {
+            SMatrix::from_fn(|r, i| link_damping(&cfg, i, &nu[i])[r])
+        };
+        let accel = multibody.forward_dynamics_ab(
+            &conf,
+            &zeta,
+            damping,
+            &[Vector6::zeros(); 9],
+            &tau,
+            &Vector3::zeros(),
+            &Vector3::zeros(),
+        );
+
+        let mut to_tool = Matrix6::identity();
+        to_tool
+            .fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&(-skew(&task.position)));
+        let jac_dot = multibody.compute_jacobian_derivatives(&jacs, &conf, &zeta);
+        let tool_accel = to_tool * (jacs[8] * accel + jac_dot[8] * zeta);
+
+        let tool = base
+            * multibody.compute_body_configurations(&conf)[8]
+            * Translation3::from(task.position);
+        let quat_e = UnitQuaternion::from_euler_angles(0.1, 0.0, 0.3).inverse() * tool.rotation;
+        let err = stack![
+            tool.rotation.inverse() * (tool.translation.vector - task.position_d);
+            quat_e.w.signum() * quat_e.vector()
+        ];
+        let accel_d = -2.25 * err - 3.0 * (to_tool * jacs[8] * zeta);
+        assert!((tool_accel - accel_d).norm() < 1e-6 * accel_d.norm());
+    }
+
+    #[test]
+    fn test_force_limits() {
+        let limits = SVector::<f64, 14>::repeat(100.0);
+        let mut tau_task = SVector::<f64, 14>::zeros();
+        let mut tau_posture = SVector::<f64, 14>::zeros();
+
+        // a task beyond the limit is scaled down along with the rest of the task, whatever the posture
+        tau_task[0] = 150.0;
+        tau_task[1] = 60.0;
+        tau_posture[0] = -10.0;
+        let tau = limit_forces(&tau_task, &tau_posture, &limits);
+        assert!((tau[0] - 90.0).abs() < 1e-12 && (tau[1] - 40.0).abs() < 1e-12);
+
+        // the posture is scaled down on the side it points to, the task is kept
+        tau_task[0] = 80.0;
+        tau_posture[0] = 40.0;
+        tau_posture[1] = -200.0;
+        let tau = limit_forces(&tau_task, &tau_posture, &limits);
+        assert!((tau[0] - 100.0).abs() < 1e-12 && (tau[1] + 40.0).abs() < 1e-12);
+        assert!(tau
+            .iter()
+            .zip(limits.iter())
+            .all(|(tau_i, limit)| tau_i.abs() <= *limit));
+    }
+
+    #[test]
+    fn test_computed_torque_on_reference() {
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let controller: ComputedTorqueConfig = serde_yaml::from_str(
+            "{wrench_limits: [1.0e6, 1.0e6, 1.0e6, 1.0e6, 1.0e6, 1.0e6], joint_torque_limit: 1.0e6}",
+        )
+        .unwrap();
+        let reference: ReferenceConfig = serde_yaml::from_str(
+            "
+            position: [1.0, 0.5, 2.0]
+            roll_pitch_yaw: [0.1, -0.1, 0.5]
+            velocity: [0.3, 0.1, 0.0]
+            joint_amplitudes: [0.3, 0.1, 0.3, 0.1, 0.3, 0.1, 0.3, 0.1]
+            joint_frequency: 0.5
+            joint_phases: [0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5]
+            ",
+        )
+        .unwrap();
+
+        // on the reference, the inverse dynamics alone give the reference accelerations
+        let reference = reference.at(1.3);
+        let nav = NavState {
+            pos: reference.pos,
+            quat: reference.quat,
+            nu_b: stack![reference.quat.inverse() * reference.vel; Vector3::zeros()],
+            theta: reference.theta,
+            theta_dot: reference.theta_dot,
+        };
+        let base = Isometry3::from_parts(Translation3::from(nav.pos), nav.quat);
+        let conf = multibody.minimal_to_homogenous_configuration(&base, &nav.theta);
+        let tau = controller.control(&multibody, &cfg, &conf, &nav, &reference);
+
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let damping = |_: &[Isometry3<f64>], nu: &[Vector6<f64>]| -> SMatrix<f64, 6, 9> {
+            SMatrix::from_fn(|r, i| link_damping(&cfg, i, &nu[i])[r])
+        };
+        let accel = multibody.forward_dynamics_ab(
+            &conf,
+            &zeta,
+            damping,
+            &[Vector6::zeros(); 9],
+            &tau,
+            &Vector3::zeros(),
+            &Vector3::zeros(),
+        );
+        let accel_d = stack![Vector6::zeros(); reference.theta_ddot];
+        assert!((accel - accel_d).norm() < 1e-6 * accel_d.norm());
+    }
+
+    #[test]
+    fn test_attitude_errors() {
+        let formulations = [
+            AttitudeError::Quaternion,
+            AttitudeError::LogMap,
+            AttitudeError::RotationMatrix,
+        ];
+        let quat_d = UnitQuaternion::from_euler_angles(0.1, -0.2, 0.3);
+
+        // all agree for small errors
+        let quat = quat_d * UnitQuaternion::from_scaled_axis(Vector3::new(0.01, -0.02, 0.005));
+        for formulation in formulations {
+            let err = formulation.error(&quat, &quat_d);
+            assert!((err - Vector3::new(0.005, -0.01, 0.0025)).norm() < 1e-5);
+        }
+
+        // a yaw error of 200 degrees is corrected the short way, regardless of the sign of the quaternion
+        let quat = quat_d * UnitQuaternion::from_euler_angles(0.0, 0.0, 200f64.to_radians());
+        let negated = UnitQuaternion::new_unchecked(-quat.into_inner());
+        for formulation in formulations {
+            let err = formulation.error(&quat, &quat_d);
+            assert!(err[2] < 0.0 && err.fixed_rows::<2>(0).norm() < 1e-9);
+            assert!((formulation.error(&negated, &quat_d) - err).norm() < 1e-9);
+        }
+        let err = AttitudeError::LogMap.error(&quat, &quat_d);
+        assert!((err[2] + 80f64.to_radians()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,293 @@
+extern crate nalgebra as na;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use multibody_dynamics::multibody::{Axis, JointType};
+use na::SVector;
+use serde::Deserialize;
+
+use crate::gait::ordered_joints;
+use crate::reference::Reference;
+use crate::Config;
+
+/// Number of oscillator states: the phase, amplitude, amplitude rate, offset and offset rate of each joint.
+pub const NUM_STATES: usize = 5 * 8;
+
+pub type CpgState = SVector<f64, NUM_STATES>;
+
+fn default_coupling() -> f64 {
+    4.0
+}
+
+fn default_convergence_rate() -> f64 {
+    4.0
+}
+
+/// Change of the oscillator inputs at `time` [s]. Omitted inputs keep their values.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CpgCommand {
+    time: f64,
+    frequency: Option<f64>,
+    amplitude: Option<f64>,
+    bias: Option<f64>,
+}
+
+/// Central pattern generator of coupled phase oscillators, one per revolute joint, integrated alongside the plant.
+/// The oscillator of joint i has a phase phi_i, an amplitude r_i and an offset x_i, and bends the body by
+/// x_i + r_i sin(phi_i), with
+///   phi_i_dot = 2 pi frequency + sum_j coupling sin(phi_j - phi_i - lag_ij),
+///   r_i_ddot = a (a / 4 (R_i - r_i) - r_i_dot), x_i_ddot = a (a / 4 (X_i - x_i) - x_i_dot),
+/// where a is the convergence rate and R_i and X_i the target amplitude and offset. Neighbouring oscillators of the
+/// same axis lock phase_lag apart from the head towards the tail, and the vertical oscillators a quarter period
+/// behind the lateral ones. The amplitudes and offsets settle critically damped, so that the gait changes smoothly
+/// with the inputs.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CpgConfig {
+    /// Frequency [Hz] of the oscillators.
+    frequency: f64,
+    /// Target amplitude [rad] of the lateral joints.
+    amplitude: f64,
+    /// Target amplitude [rad] of the vertical joints.
+    #[serde(default)]
+    vertical_amplitude: f64,
+    /// Target offset [rad] of the lateral joints, bending the body to one side to turn.
+    #[serde(default)]
+    bias: f64,
+    /// Phase lag [rad] between neighbouring joints of the same axis, from the head towards the tail.
+    phase_lag: f64,
+    /// Coupling weight [rad/s] between neighbouring oscillators.
+    #[serde(default = "default_coupling")]
+    coupling: f64,
+    /// Convergence rate [1/s] of the amplitudes and offsets.
+    #[serde(default = "default_convergence_rate")]
+    convergence_rate: f64,
+    /// Input changes during the run, in order of time.
+    #[serde(default)]
+    commands: Vec<CpgCommand>,
+    /// Switches off the thrusters, so that the vehicle is propelled by the joint motion alone.
+    #[serde(default)]
+    thrusters_off: bool,
+}
+
+impl CpgConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let frequencies = self.commands.iter().filter_map(|c| c.frequency);
+        let amplitudes = self.commands.iter().filter_map(|c| c.amplitude);
+        if std::iter::once(self.frequency)
+            .chain(frequencies)
+            .chain(amplitudes)
+            .chain([self.amplitude, self.vertical_amplitude])
+            .any(|x| x < 0.0)
+        {
+            return Err(
+                "The oscillator frequencies and amplitudes must be non-negative".to_string(),
+            );
+        }
+        if self.coupling <= 0.0 || self.convergence_rate <= 0.0 {
+            return Err(
+                "The oscillator coupling and convergence rate must be positive".to_string(),
+            );
+        }
+        if self.commands.windows(2).any(|c| c[1].time < c[0].time) {
+            return Err("The oscillator commands must be in order of time".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn thrusters_off(&self) -> bool {
+        self.thrusters_off
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Lateral,
+    Vertical,
+    Straight,
+}
+
+#[derive(Debug, Clone)]
+struct Oscillator {
+    joint: usize,
+    /// Sign turning the bending of the body into the joint angle.
+    sign: f64,
+    kind: Kind,
+}
+
+/// Oscillator network with the current inputs.
+#[derive(Debug, Clone)]
+pub struct Cpg {
+    cfg: CpgConfig,
+    oscillators: Vec<Oscillator>,
+    /// Couplings (i, j, lag) locking the phase of joint j to lag behind that of joint i.
+    couplings: Vec<(usize, usize, f64)>,
+    frequency: f64,
+    amplitude: f64,
+    bias: f64,
+    next_command: usize,
+}
+
+impl Cpg {
+    pub fn new(cfg: &CpgConfig, config: &Config) -> Self {
+        let lateral = ordered_joints(config, |j| matches!(j, JointType::Revolute(Axis::Z)));
+        let vertical = ordered_joints(config, |j| matches!(j, JointType::Revolute(Axis::Y)));
+        let straight = ordered_joints(config, |j| matches!(j, JointType::Revolute(Axis::X)));
+
+        let mut couplings = Vec::new();
+        for chain in [&lateral, &vertical] {
+            for pair in chain.windows(2) {
+                couplings.push((pair[0].0, pair[1].0, cfg.phase_lag));
+            }
+        }
+        for (l, v) in lateral.iter().zip(&vertical) {
+            couplings.push((l.0, v.0, FRAC_PI_2));
+        }
+        let oscillators = [
+            (lateral, Kind::Lateral),
+            (vertical, Kind::Vertical),
+            (straight, Kind::Straight),
+        ]
+        .into_iter()
+        .flat_map(|(joints, kind)| {
+            joints
+                .into_iter()
+                .map(move |(joint, sign)| Oscillator { joint, sign, kind })
+        })
+        .collect();
+
+        Cpg {
+            cfg: cfg.clone(),
+            oscillators,
+            couplings,
+            frequency: cfg.frequency,
+            amplitude: cfg.amplitude,
+            bias: cfg.bias,
+            next_command: 0,
+        }
+    }
+
+    /// Oscillator states starting from the straight body, with the phases already locked.
+    pub fn initial_state(&self) -> CpgState {
+        let mut states = CpgState::zeros();
+        for _ in 0..self.oscillators.len() {
+            for &(i, j, lag) in &self.couplings {
+                states[5 * j] = states[5 * i] - lag;
+            }
+        }
+        states
+    }
+
+    /// Applies the commands due at the sample time `t`.
+    pub fn update(&mut self, t: f64) {
+        while let Some(command) = self.cfg.commands.get(self.next_command) {
+            if command.time > t {
+                break;
+            }
+            self.frequency = command.frequency.unwrap_or(self.frequency);
+            self.amplitude = command.amplitude.unwrap_or(self.amplitude);
+            self.bias = command.bias.unwrap_or(self.bias);
+            self.next_command += 1;
+        }
+    }
+
+    fn targets(&self, kind: Kind) -> (f64, f64) {
+        match kind {
+            Kind::Lateral => (self.amplitude, self.bias),
+            Kind::Vertical => (self.cfg.vertical_amplitude, 0.0),
+            Kind::Straight => (0.0, 0.0),
+        }
+    }
+
+    pub fn derivatives(&self, states: &CpgState) -> CpgState {
+        let a = self.cfg.convergence_rate;
+        let mut states_dot = CpgState::zeros();
+        for oscillator in &self.oscillators {
+            let k = 5 * oscillator.joint;
+            let (amplitude, offset) = self.targets(oscillator.kind);
+            states_dot[k] = 2.0 * PI * self.frequency;
+            states_dot[k + 1] = states[k + 2];
+            states_dot[k + 2] = a * (a / 4.0 * (amplitude - states[k + 1]) - states[k + 2]);
+            states_dot[k + 3] = states[k + 4];
+            states_dot[k + 4] = a * (a / 4.0 * (offset - states[k + 3]) - states[k + 4]);
+        }
+        for &(i, j, lag) in &self.couplings {
+            let (phi_i, phi_j) = (states[5 * i], states[5 * j]);
+            states_dot[5 * i] += self.cfg.coupling * (phi_j - phi_i + lag).sin();
+            states_dot[5 * j] += self.cfg.coupling * (phi_i - phi_j - lag).sin();
+        }
+        states_dot
+    }
+
+    /// Replaces the joint angles, rates and accelerations of `reference` with the oscillator outputs `lead` [s]
+    /// after the time of `states`, advancing the phases at their current rates. The phase accelerations from the
+    /// coupling are neglected.
+    pub fn apply(&self, states: &CpgState, lead: f64, reference: Reference) -> Reference {
+        let states_dot = self.derivatives(states);
+        let mut theta = SVector::<f64, 8>::zeros();
+        let mut theta_dot = SVector::<f64, 8>::zeros();
+        let mut theta_ddot = SVector::<f64, 8>::zeros();
+        for oscillator in &self.oscillators {
+            let k = 5 * oscillator.joint;
+            let (phi, phi_dot) = (states[k] + lead * states_dot[k], states_dot[k]);
+            let (r, r_dot, r_ddot) = (states[k + 1], states[k + 2], states_dot[k + 2]);
+            let (x, x_dot, x_ddot) = (states[k + 3], states[k + 4], states_dot[k + 4]);
+            let (sin, cos) = phi.sin_cos();
+            let i = oscillator.joint;
+            theta[i] = oscillator.sign * (x + r * sin);
+            theta_dot[i] = oscillator.sign * (x_dot + r_dot * sin + r * phi_dot * cos);
+            theta_ddot[i] = oscillator.sign
+                * (x_ddot + r_ddot * sin + 2.0 * r_dot * phi_dot * cos - r * phi_dot.powi(2) * sin);
+        }
+        Reference {
+            theta,
+            theta_dot,
+            theta_ddot,
+            ..reference
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use crate::test_config;
+
+    #[test]
+    fn test_oscillators() {
+        let config = test_config();
+        let cfg: CpgConfig = serde_yaml::from_str(
+            "{frequency: 0.5, amplitude: 0.4, phase_lag: 0.8, commands: [{time: 10.0, amplitude: 0.2, bias: 0.1}]}",
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+        let mut cpg = Cpg::new(&cfg, &config);
+
+        // perturbed phases lock again, and the amplitudes rise smoothly from the straight body
+        let mut states = cpg.initial_state();
+        states[5 * 6] += 1.0;
+        let dt = 1e-3;
+        let mut previous = cpg.apply(&states, 0.0, ReferenceConfig::default().at(0.0));
+        for n in 0..20000 {
+            let t = n as f64 * dt;
+            cpg.update(t);
+            states += dt * cpg.derivatives(&states);
+            let reference = cpg.apply(&states, 0.0, ReferenceConfig::default().at(t));
+            assert!((reference.theta - previous.theta).amax() < 0.01);
+            previous = reference;
+            if n == 9999 {
+                // head to tail, the lateral joints lag 0.8 rad behind each other at the full amplitude
+                for (i, j) in [(6, 4), (4, 1), (1, 3)] {
+                    let lag = (states[5 * i] - states[5 * j] - 0.8).sin();
+                    assert!(lag.abs() < 1e-3);
+                }
+                assert!((states[5 * 6 + 1] - 0.4).abs() < 1e-3);
+            }
+        }
+        // the commanded amplitude and bias are reached, with the rear joints bending the opposite way
+        assert!((states[5 * 6 + 1] - 0.2).abs() < 1e-3 && (states[5 * 3 + 3] - 0.1).abs() < 1e-3);
+        let reference = cpg.apply(&states, 0.0, ReferenceConfig::default().at(20.0));
+        let phi = states[5 * 3];
+        assert!((reference.theta[3] + 0.1 + 0.2 * phi.sin()).abs() < 1e-3);
+        assert!(reference.theta[0] == 0.0);
+    }
+}
@@ -0,0 +1,335 @@
+extern crate nalgebra as na;
+use std::f64::consts::{LN_2, PI};
+
+use na::{Vector2, Vector3};
+use serde::Deserialize;
+
+fn default_shear_exponent() -> f64 {
+    1.0 / 7.0
+}
+
+fn default_drag_coeff() -> f64 {
+    1.2
+}
+
+/// Ocean current field, evaluated at the center of each link. The world z-axis points down.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum CurrentConfig {
+    /// Velocities on a regular grid, loaded from a CSV file with the columns x, y, z, u, v, w.
+    Grid { file: String },
+    /// Current given in closed form.
+    #[serde(untagged)]
+    Analytic(AnalyticCurrent),
+}
+
+/// Ocean current given in closed form.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum AnalyticCurrent {
+    /// Uniform current.
+    Uniform { velocity: Vector3<f64> },
+    /// Power-law shear profile, decaying from `velocity` at the surface to zero at the seabed.
+    Shear {
+        velocity: Vector3<f64>,
+        surface_z: f64,
+        water_depth: f64,
+        #[serde(default = "default_shear_exponent")]
+        exponent: f64,
+    },
+    /// Lamb–Oseen vortex about a vertical axis through `center` (x, y). Positive circulation [m^2/s]
+    /// rotates from the x-axis towards the y-axis.
+    Vortex {
+        center: Vector2<f64>,
+        circulation: f64,
+        core_radius: f64,
+    },
+    /// Uniform `velocity` (horizontal) passing a vertical cylinder at `center` (x, y): potential flow around
+    /// the cylinder and a Gaussian velocity deficit in its wake.
+    CylinderWake {
+        velocity: Vector2<f64>,
+        center: Vector2<f64>,
+        radius: f64,
+        #[serde(default = "default_drag_coeff")]
+        drag_coeff: f64,
+    },
+}
+
+/// Current velocities sampled on a regular rectilinear grid.
+#[derive(Debug, Clone)]
+pub struct CurrentGrid {
+    axes: [Vec<f64>; 3],
+    /// Velocities stored with the x index varying fastest.
+    velocities: Vec<Vector3<f64>>,
+}
+
+impl CurrentConfig {
+    /// Checks the parameters of the analytic fields. Grids are checked when they are loaded.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            CurrentConfig::Analytic(current) => current.validate(),
+            CurrentConfig::Grid { .. } => Ok(()),
+        }
+    }
+}
+
+impl AnalyticCurrent {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            AnalyticCurrent::Shear { water_depth, .. } if *water_depth <= 0.0 => {
+                Err("The water depth of the shear current must be positive".to_string())
+            }
+            AnalyticCurrent::Vortex { core_radius, .. } if *core_radius <= 0.0 => {
+                Err("The vortex core radius must be positive".to_string())
+            }
+            AnalyticCurrent::CylinderWake {
+                radius, drag_coeff, ..
+            } if *radius <= 0.0 || *drag_coeff < 0.0 => Err(
+                "The cylinder radius must be positive and its drag coefficient non-negative"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Computes the current velocity at the world position `pos`, expressed in the world frame.
+    pub fn velocity(&self, pos: &Vector3<f64>) -> Vector3<f64> {
+        match self {
+            AnalyticCurrent::Uniform { velocity } => *velocity,
+            AnalyticCurrent::Shear {
+                velocity,
+                surface_z,
+                water_depth,
+                exponent,
+            } => {
+                let height_above_seabed = (water_depth - (pos[2] - surface_z)).max(0.0);
+                velocity * (height_above_seabed / water_depth).min(1.0).powf(*exponent)
+            }
+            AnalyticCurrent::Vortex {
+                center,
+                circulation,
+                core_radius,
+            } => {
+                let d = pos.xy() - center;
+                let r2 = d.norm_squared();
+                if r2 < 1e-12 {
+                    return Vector3::zeros();
+                }
+                let v_theta =
+                    circulation / (2.0 * PI * r2) * (1.0 - (-r2 / core_radius.powi(2)).exp());
+                Vector3::new(-d[1] * v_theta, d[0] * v_theta, 0.0)
+            }
+            AnalyticCurrent::CylinderWake {
+                velocity,
+                center,
+                radius,
+                drag_coeff,
+            } => cylinder_wake(velocity, center, *radius, *drag_coeff, pos),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CurrentField {
+    Analytic(AnalyticCurrent),
+    Grid(CurrentGrid),
+}
+
+impl CurrentField {
+    pub fn new(cfg: &CurrentConfig) -> Result<Self, String> {
+        match cfg {
+            CurrentConfig::Grid { file } => {
+                let contents = std::fs::read_to_string(file)
+                    .map_err(|e| format!("Could not read current grid {}: {}", file, e))?;
+                Ok(CurrentField::Grid(CurrentGrid::parse_csv(&contents)?))
+            }
+            CurrentConfig::Analytic(current) => Ok(CurrentField::Analytic(current.clone())),
+        }
+    }
+
+    /// Computes the current velocity at the world position `pos`, expressed in the world frame.
+    pub fn velocity(&self, pos: &Vector3<f64>) -> Vector3<f64> {
+        match self {
+            CurrentField::Grid(grid) => grid.interpolate(pos),
+            CurrentField::Analytic(current) => current.velocity(pos),
+        }
+    }
+}
+
+fn cylinder_wake(
+    velocity: &Vector2<f64>,
+    center: &Vector2<f64>,
+    radius: f64,
+    drag_coeff: f64,
+    pos: &Vector3<f64>,
+) -> Vector3<f64> {
+    let speed = velocity.norm();
+    if speed < 1e-12 {
+        return Vector3::zeros();
+    }
+    // coordinates along and across the free stream
+    let e_x = velocity / speed;
+    let e_y = Vector2::new(-e_x[1], e_x[0]);
+    let d = pos.xy() - center;
+    let (x, y) = (d.dot(&e_x), d.dot(&e_y));
+    let r2 = x * x + y * y;
+    if r2 <= radius.powi(2) {
+        return Vector3::zeros();
+    }
+
+    // potential flow around the cylinder
+    let a2 = radius.powi(2) / r2.powi(2);
+    let mut u = speed * (1.0 - a2 * (x * x - y * y));
+    let v = -speed * a2 * 2.0 * x * y;
+
+    // plane wake velocity deficit (Schlichting) behind the cylinder
+    if x > radius {
+        let diameter = 2.0 * radius;
+        let half_width = 0.25 * (drag_coeff * diameter * x).sqrt();
+        let deficit = 0.98 * speed * (drag_coeff * diameter / x).sqrt();
+        u -= deficit.min(speed) * (-LN_2 * (y / half_width).powi(2)).exp();
+    }
+
+    let vel = u * e_x + v * e_y;
+    Vector3::new(vel[0], vel[1], 0.0)
+}
+
+impl CurrentGrid {
+    /// Parses rows of x, y, z, u, v, w. Empty lines, comments (#) and a header line are skipped, and the grid
+    /// must have at least one row of finite values.
+    pub fn parse_csv(contents: &str) -> Result<Self, String> {
+        let mut rows = Vec::new();
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values: Result<Vec<f64>, _> =
+                line.split(',').map(|v| v.trim().parse::<f64>()).collect();
+            match values {
+                Ok(values) if values.iter().any(|v| !v.is_finite()) => {
+                    return Err(format!("Non-finite value on line {}", line_no + 1))
+                }
+                Ok(values) if values.len() == 6 => rows.push(values),
+                Ok(_) => return Err(format!("Expected 6 columns on line {}", line_no + 1)),
+                Err(_) if rows.is_empty() => continue, // header
+                Err(e) => return Err(format!("Invalid number on line {}: {}", line_no + 1, e)),
+            }
+        }
+
+        if rows.is_empty() {
+            return Err("The current grid has no points".to_string());
+        }
+
+        let axis = |k: usize| -> Vec<f64> {
+            let mut values: Vec<f64> = rows.iter().map(|row| row[k]).collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            values.dedup();
+            values
+        };
+        let axes = [axis(0), axis(1), axis(2)];
+        let (nx, ny, nz) = (axes[0].len(), axes[1].len(), axes[2].len());
+        if nx * ny * nz != rows.len() {
+            return Err(format!(
+                "The current grid must be regular: {} x {} x {} points but {} rows",
+                nx,
+                ny,
+                nz,
+                rows.len()
+            ));
+        }
+
+        let mut velocities = vec![Vector3::zeros(); rows.len()];
+        for row in &rows {
+            let idx = |k: usize| axes[k].partition_point(|v| *v < row[k]);
+            velocities[idx(0) + nx * (idx(1) + ny * idx(2))] = Vector3::new(row[3], row[4], row[5]);
+        }
+        Ok(CurrentGrid { axes, velocities })
+    }
+
+    /// Trilinear interpolation, holding the values at the grid boundary outside the grid.
+    pub fn interpolate(&self, pos: &Vector3<f64>) -> Vector3<f64> {
+        let mut lower = [0; 3];
+        let mut weight = [0.0; 3];
+        for k in 0..3 {
+            let axis = &self.axes[k];
+            if axis.len() == 1 || pos[k] <= axis[0] {
+                continue;
+            }
+            if pos[k] >= axis[axis.len() - 1] {
+                lower[k] = axis.len() - 1;
+                continue;
+            }
+            let j = axis.partition_point(|v| *v <= pos[k]) - 1;
+            lower[k] = j;
+            weight[k] = (pos[k] - axis[j]) / (axis[j + 1] - axis[j]);
+        }
+
+        let (nx, ny) = (self.axes[0].len(), self.axes[1].len());
+        let mut vel = Vector3::zeros();
+        for corner in 0..8 {
+            let mut w = 1.0;
+            let mut idx = [0; 3];
+            for k in 0..3 {
+                let upper = (corner >> k) & 1 == 1;
+                w *= if upper { weight[k] } else { 1.0 - weight[k] };
+                idx[k] = (lower[k] + upper as usize).min(self.axes[k].len() - 1);
+            }
+            if w > 0.0 {
+                vel += w * self.velocities[idx[0] + nx * (idx[1] + ny * idx[2])];
+            }
+        }
+        vel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_interpolation() {
+        let mut csv = String::from("x, y, z, u, v, w\n");
+        for z in [0.0, 2.0] {
+            for y in [0.0, 1.0] {
+                for x in [0.0, 1.0, 2.0] {
+                    // linear field is reproduced exactly by trilinear interpolation
+                    csv += &format!("{}, {}, {}, {}, {}, 0.0\n", x, y, z, x + z, 2.0 * y);
+                }
+            }
+        }
+        let grid = CurrentGrid::parse_csv(&csv).unwrap();
+        let vel = grid.interpolate(&Vector3::new(1.5, 0.25, 0.5));
+        assert!((vel - Vector3::new(2.0, 0.5, 0.0)).norm() < 1e-12);
+        // held constant outside the grid
+        let vel = grid.interpolate(&Vector3::new(5.0, -1.0, 0.0));
+        assert!((vel - Vector3::new(2.0, 0.0, 0.0)).norm() < 1e-12);
+
+        assert!(CurrentGrid::parse_csv("0, 0, 0, 1, 0, 0\n1, 0, 0, 1, 0\n").is_err());
+        assert!(CurrentGrid::parse_csv("x, y, z, u, v, w\n").is_err());
+        assert!(CurrentGrid::parse_csv("0, 0, NaN, 1, 0, 0\n").is_err());
+
+        let shear: CurrentConfig = serde_yaml::from_str(
+            "{type: Shear, velocity: [0.5, 0.0, 0.0], surface_z: 0.0, water_depth: 0.0}",
+        )
+        .unwrap();
+        assert!(shear.validate().is_err());
+        let grid: CurrentConfig = serde_yaml::from_str("{type: Grid, file: current.csv}").unwrap();
+        assert!(matches!(grid, CurrentConfig::Grid { .. }));
+    }
+
+    #[test]
+    fn test_cylinder_wake() {
+        let field = CurrentField::Analytic(AnalyticCurrent::CylinderWake {
+            velocity: Vector2::new(1.0, 0.0),
+            center: Vector2::zeros(),
+            radius: 0.5,
+            drag_coeff: 1.2,
+        });
+        // stagnation point upstream, acceleration at the sides, deficit downstream
+        assert!(field.velocity(&Vector3::new(-0.5001, 0.0, 0.0)).norm() < 1e-3);
+        assert!(field.velocity(&Vector3::new(0.0, 0.5001, 0.0))[0] > 1.9);
+        assert!(field.velocity(&Vector3::new(5.0, 0.0, 0.0))[0] < 0.7);
+        assert!((field.velocity(&Vector3::new(-100.0, 3.0, 0.0))[0] - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,258 @@
+extern crate nalgebra as na;
+use na::Vector3;
+use serde::Deserialize;
+
+/// Electrical power drawn by a thruster as a function of its thrust, given as a lookup table.
+/// Linear interpolation is used between the samples and the end values are held outside the table.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PowerCurve {
+    /// Thrust samples [N], sorted in increasing order.
+    thrust: Vec<f64>,
+    /// Electrical power [W] at the corresponding thrust samples.
+    power: Vec<f64>,
+}
+
+impl PowerCurve {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.thrust.is_empty() || self.thrust.len() != self.power.len() {
+            return Err(
+                "The power curve needs as many power samples as thrust samples".to_string(),
+            );
+        }
+        if self.thrust.windows(2).any(|w| w[1] <= w[0]) {
+            return Err("The thrust samples of the power curve must be increasing".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn power(&self, thrust: f64) -> f64 {
+        interp1(&self.thrust, &self.power, thrust)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JointMotorPower {
+    /// Efficiency from electrical to mechanical power when the motor is driving the joint.
+    efficiency: f64,
+    /// Whether the motor drive can feed mechanical power back into the battery.
+    #[serde(default)]
+    regenerative: bool,
+    /// Efficiency from mechanical to electrical power when regenerating.
+    #[serde(default)]
+    regen_efficiency: f64,
+}
+
+impl JointMotorPower {
+    /// Electrical power [W] drawn by the joint motor. Negative values are power fed back into the battery.
+    pub fn power(&self, torque: f64, speed: f64) -> f64 {
+        let mech_power = torque * speed;
+        if mech_power >= 0.0 {
+            mech_power / self.efficiency
+        } else if self.regenerative {
+            self.regen_efficiency * mech_power
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BatteryConfig {
+    /// Usable battery capacity [Wh].
+    capacity: f64,
+    /// Initial state of charge in [0, 1].
+    soc_init: f64,
+    /// State of charge at which the actuators are cut off.
+    soc_min: f64,
+    /// State of charge above which regenerated power is dissipated instead of stored.
+    soc_max: f64,
+}
+
+impl BatteryConfig {
+    /// Capacity in joules.
+    pub fn capacity_joules(&self) -> f64 {
+        self.capacity * 3600.0
+    }
+
+    /// State of charge after `energy` [J] has been drawn from the battery.
+    pub fn soc(&self, energy: f64) -> f64 {
+        self.soc_init - energy / self.capacity_joules()
+    }
+
+    pub fn is_depleted(&self, soc: f64) -> bool {
+        soc <= self.soc_min
+    }
+
+    pub fn is_full(&self, soc: f64) -> bool {
+        soc >= self.soc_max
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EnergyConfig {
+    /// One power curve per thruster, in the same order as `thruster_dirs`.
+    thruster_power_curves: Vec<PowerCurve>,
+    /// One motor model per scalar joint, in the same order as the joint angles.
+    joint_motors: Vec<JointMotorPower>,
+    /// Constant power consumption of electronics, sensors and computers [W].
+    #[serde(default)]
+    hotel_load: f64,
+    pub battery: BatteryConfig,
+}
+
+impl EnergyConfig {
+    pub fn validate(&self, num_thrusters: usize, num_joints: usize) -> Result<(), String> {
+        if self.thruster_power_curves.len() != num_thrusters {
+            return Err(format!(
+                "Give one power curve for each of the {num_thrusters} thrusters"
+            ));
+        }
+        if self.joint_motors.len() != num_joints {
+            return Err(format!(
+                "Give one motor for each of the {num_joints} joints"
+            ));
+        }
+        for (i, curve) in self.thruster_power_curves.iter().enumerate() {
+            curve
+                .validate()
+                .map_err(|e| format!("Invalid power curve of thruster {}: {}", i + 1, e))?;
+        }
+        let in_unit_interval = |x: f64| x > 0.0 && x <= 1.0;
+        if self.joint_motors.iter().any(|motor| {
+            !in_unit_interval(motor.efficiency)
+                || (motor.regenerative && !in_unit_interval(motor.regen_efficiency))
+        }) {
+            return Err("The joint motor efficiencies must be in (0, 1]".to_string());
+        }
+        let battery = &self.battery;
+        if battery.capacity <= 0.0
+            || !(0.0..=1.0).contains(&battery.soc_min)
+            || !(battery.soc_min..=battery.soc_max.min(1.0)).contains(&battery.soc_init)
+        {
+            return Err(
+                "The battery needs a positive capacity and 0 <= soc_min <= soc_init <= soc_max, 1"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Computes the electrical power [W] drawn by the thrusters, the joint motors and the hotel load.
+    /// Regenerated joint power is only returned to the battery while it is below `soc_max`.
+    pub fn comp_power(
+        &self,
+        thrust: &[f64],
+        joint_torque: &[f64],
+        joint_speed: &[f64],
+        soc: f64,
+    ) -> Vector3<f64> {
+        let p_thrusters = self
+            .thruster_power_curves
+            .iter()
+            .zip(thrust)
+            .map(|(curve, f)| curve.power(*f))
+            .sum::<f64>();
+
+        let p_joints = self
+            .joint_motors
+            .iter()
+            .zip(joint_torque.iter().zip(joint_speed))
+            .map(|(motor, (tau, speed))| {
+                let p = motor.power(*tau, *speed);
+                if p < 0.0 && self.battery.is_full(soc) {
+                    0.0
+                } else {
+                    p
+                }
+            })
+            .sum::<f64>();
+
+        Vector3::new(p_thrusters, p_joints, self.hotel_load)
+    }
+
+    /// Estimates the remaining mission endurance [s] by extrapolating the average power over `time` seconds,
+    /// during which `energy` [J] was drawn from the battery.
+    pub fn estimate_endurance(&self, time: f64, energy: f64) -> f64 {
+        let avg_power = energy / time;
+        let remaining =
+            (self.battery.soc(energy) - self.battery.soc_min) * self.battery.capacity_joules();
+        if avg_power > 0.0 {
+            remaining.max(0.0) / avg_power
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+/// Piecewise linear interpolation of the samples (`xs`, `ys`) at `x`, holding the end values outside the samples.
+pub fn interp1(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    if x <= xs[0] {
+        return ys[0];
+    }
+    if x >= xs[xs.len() - 1] {
+        return ys[ys.len() - 1];
+    }
+    let k = xs.partition_point(|xk| *xk <= x);
+    let t = (x - xs[k - 1]) / (xs[k] - xs[k - 1]);
+    ys[k - 1] + t * (ys[k] - ys[k - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interp1() {
+        let xs = [-1.0, 0.0, 2.0];
+        let ys = [4.0, 0.0, 8.0];
+        assert_eq!(interp1(&xs, &ys, -3.0), 4.0);
+        assert_eq!(interp1(&xs, &ys, -0.5), 2.0);
+        assert_eq!(interp1(&xs, &ys, 1.0), 4.0);
+        assert_eq!(interp1(&xs, &ys, 5.0), 8.0);
+    }
+
+    #[test]
+    fn test_joint_motor_power() {
+        let motor = JointMotorPower {
+            efficiency: 0.8,
+            regenerative: true,
+            regen_efficiency: 0.5,
+        };
+        assert_eq!(motor.power(2.0, 4.0), 10.0);
+        assert_eq!(motor.power(-2.0, 4.0), -4.0);
+
+        let motor = JointMotorPower {
+            regenerative: false,
+            ..motor
+        };
+        assert_eq!(motor.power(-2.0, 4.0), 0.0);
+    }
+
+    #[test]
+    fn test_energy() {
+        let energy: EnergyConfig = serde_yaml::from_str(
+            "{thruster_power_curves: [{thrust: [-10.0, 0.0, 10.0], power: [50.0, 0.0, 40.0]}, \
+             {thrust: [0.0, 10.0], power: [0.0, 20.0]}], \
+             joint_motors: [{efficiency: 0.5, regenerative: true, regen_efficiency: 0.5}], hotel_load: 10.0, \
+             battery: {capacity: 1.0, soc_init: 0.9, soc_min: 0.1, soc_max: 0.95}}",
+        )
+        .unwrap();
+        energy.validate(2, 1).unwrap();
+        assert!(energy.validate(3, 1).is_err());
+
+        // the thrusters follow their curves, and regeneration stops once the battery is full
+        let power = energy.comp_power(&[-5.0, 5.0], &[2.0], &[-1.0], 0.5);
+        assert_eq!(power, Vector3::new(35.0, -1.0, 10.0));
+        let power = energy.comp_power(&[-5.0, 5.0], &[2.0], &[-1.0], 0.95);
+        assert_eq!(power.y, 0.0);
+
+        // 0.8 Wh remain above soc_min after drawing 360 J at an average of 36 W
+        assert!(!energy.battery.is_depleted(energy.battery.soc(360.0)));
+        assert!(energy.battery.is_depleted(energy.battery.soc(0.8 * 3600.0)));
+        assert!((energy.estimate_endurance(10.0, 360.0) - 0.7 * 3600.0 / 36.0).abs() < 1e-9);
+        assert_eq!(energy.estimate_endurance(10.0, 0.0), f64::INFINITY);
+    }
+}
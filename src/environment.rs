@@ -0,0 +1,337 @@
+extern crate nalgebra as na;
+use na::{Isometry3, Point3, Rotation3, Vector2, Vector3, Vector6};
+use serde::Deserialize;
+
+/// Depth of the seabed. The world z-axis points down.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Seabed {
+    /// Horizontal seabed at the world z-coordinate `z`.
+    Flat { z: f64 },
+    /// Seabed z-coordinates `z[j][k]` at the grid points (`x[k]`, `y[j]`), interpolated bilinearly and held
+    /// constant outside the grid.
+    Heightmap {
+        x: Vec<f64>,
+        y: Vec<f64>,
+        z: Vec<Vec<f64>>,
+    },
+}
+
+/// Static obstacle.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Obstacle {
+    /// Box with edge lengths `size`, rotated by `yaw` about the world z-axis.
+    Box {
+        center: Vector3<f64>,
+        size: Vector3<f64>,
+        #[serde(default)]
+        yaw: f64,
+    },
+    /// Vertical cylinder, e.g. a pile or a docking station post.
+    Cylinder {
+        center: Vector3<f64>,
+        radius: f64,
+        height: f64,
+    },
+    /// Straight pipeline from `start` to `end` with rounded ends.
+    Pipeline {
+        start: Vector3<f64>,
+        end: Vector3<f64>,
+        radius: f64,
+    },
+}
+
+/// Penalty contact model. The stiffness and damping are per metre of link length in contact.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ContactConfig {
+    /// Normal stiffness [N/m^2].
+    stiffness: f64,
+    /// Normal damping [N s/m^2], only acting while the penetration increases.
+    damping: f64,
+    /// Coulomb friction coefficient.
+    friction_coeff: f64,
+    /// Sliding velocity [m/s] over which the friction force is smoothed.
+    friction_smoothing_velocity: f64,
+}
+
+impl Default for ContactConfig {
+    fn default() -> Self {
+        ContactConfig {
+            stiffness: 1e5,
+            damping: 1e3,
+            friction_coeff: 0.5,
+            friction_smoothing_velocity: 1e-2,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EnvironmentConfig {
+    #[serde(default)]
+    seabed: Option<Seabed>,
+    #[serde(default)]
+    obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    contact: ContactConfig,
+}
+
+impl EnvironmentConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(Seabed::Heightmap { x, y, z }) = &self.seabed {
+            if x.is_empty() || y.is_empty() {
+                return Err("The seabed heightmap needs at least one grid point".to_string());
+            }
+            if z.len() != y.len() || z.iter().any(|row| row.len() != x.len()) {
+                return Err(format!(
+                    "The seabed heightmap must have {} rows of {} values",
+                    y.len(),
+                    x.len()
+                ));
+            }
+            let increasing = |v: &[f64]| v.windows(2).all(|w| w[0] < w[1]);
+            if !increasing(x) || !increasing(y) {
+                return Err("The seabed heightmap axes must be strictly increasing".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Signed distance from the world position `p` to the nearest surface, and the unit surface normal
+    /// pointing into the water. The distance is negative inside the seabed or an obstacle.
+    fn distance(&self, p: &Vector3<f64>) -> Option<(f64, Vector3<f64>)> {
+        let seabed = self.seabed.as_ref().map(|seabed| seabed.distance(p));
+        self.obstacles
+            .iter()
+            .map(|obstacle| obstacle.distance(p))
+            .chain(seabed)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// Computes the contact wrench on a cylindrical link of `length` and `radius`, centered at `center` on the
+    /// link's x-axis. The link has the world pose `pose` and the twist `twist` in the link frame. The wrench
+    /// is expressed in the link frame.
+    pub fn contact_wrench(
+        &self,
+        pose: &Isometry3<f64>,
+        twist: &Vector6<f64>,
+        center: &Vector3<f64>,
+        length: f64,
+        radius: f64,
+    ) -> Vector6<f64> {
+        let mut wrench = Vector6::zeros();
+        if self.seabed.is_none() && self.obstacles.is_empty() {
+            return wrench;
+        }
+        let cfg = &self.contact;
+        let (v, w) = (twist.fixed_rows::<3>(0), twist.fixed_rows::<3>(3));
+
+        // the link is covered by spheres of its radius along its axis
+        let num_points = ((length / radius).ceil() as usize + 1).max(2);
+        let spacing = length / (num_points - 1) as f64;
+        for k in 0..num_points {
+            let weight = if k == 0 || k == num_points - 1 {
+                0.5
+            } else {
+                1.0
+            } * spacing;
+            let x = center + Vector3::new(-0.5 * length + k as f64 * spacing, 0.0, 0.0);
+            let p = pose * Point3::from(x);
+            let Some((distance, normal)) = self.distance(&p.coords) else {
+                return wrench;
+            };
+            let penetration = radius - distance;
+            if penetration <= 0.0 {
+                continue;
+            }
+
+            // contact point on the link surface, and its velocity in the world frame
+            let r = pose.inverse() * (p - radius * normal);
+            let vel = pose.rotation * (v + w.cross(&r.coords));
+            let vel_n = vel.dot(&normal);
+            let damping = if vel_n < 0.0 {
+                -cfg.damping * vel_n
+            } else {
+                0.0
+            };
+            let force_n = weight * (cfg.stiffness * penetration + damping);
+
+            let vel_t = vel - vel_n * normal;
+            let speed_t = vel_t.norm();
+            let mut force = force_n * normal;
+            if speed_t > 1e-12 {
+                let friction = cfg.friction_coeff
+                    * force_n
+                    * (speed_t / cfg.friction_smoothing_velocity).tanh();
+                force -= friction * vel_t / speed_t;
+            }
+
+            let force = pose.rotation.inverse() * force;
+            let moment = r.coords.cross(&force);
+            wrench += Vector6::new(
+                force[0], force[1], force[2], moment[0], moment[1], moment[2],
+            );
+        }
+        wrench
+    }
+}
+
+impl Seabed {
+    /// Z-coordinate of the seabed below the horizontal position `p` and its gradient.
+    fn height(&self, p: &Vector2<f64>) -> (f64, Vector2<f64>) {
+        match self {
+            Seabed::Flat { z } => (*z, Vector2::zeros()),
+            Seabed::Heightmap { x, y, z } => {
+                let (i, s, ds) = cell(x, p[0]);
+                let (j, t, dt) = cell(y, p[1]);
+                let (i1, j1) = ((i + 1).min(x.len() - 1), (j + 1).min(y.len() - 1));
+                let (z00, z10, z01, z11) = (z[j][i], z[j][i1], z[j1][i], z[j1][i1]);
+                let height =
+                    (1.0 - t) * ((1.0 - s) * z00 + s * z10) + t * ((1.0 - s) * z01 + s * z11);
+                let grad = Vector2::new(
+                    ds * ((1.0 - t) * (z10 - z00) + t * (z11 - z01)),
+                    dt * ((1.0 - s) * (z01 - z00) + s * (z11 - z10)),
+                );
+                (height, grad)
+            }
+        }
+    }
+
+    fn distance(&self, p: &Vector3<f64>) -> (f64, Vector3<f64>) {
+        let (height, grad) = self.height(&p.xy());
+        let normal = Vector3::new(grad[0], grad[1], -1.0);
+        let norm = normal.norm();
+        ((height - p[2]) / norm, normal / norm)
+    }
+}
+
+/// Grid cell of `value` on `axis`: the lower index, the interpolation weight and the derivative of the weight.
+/// Values outside the axis are held at the boundary.
+fn cell(axis: &[f64], value: f64) -> (usize, f64, f64) {
+    if axis.len() == 1 || value <= axis[0] {
+        return (0, 0.0, 0.0);
+    }
+    if value >= axis[axis.len() - 1] {
+        return (axis.len() - 1, 0.0, 0.0);
+    }
+    let i = axis.partition_point(|v| *v <= value) - 1;
+    let dx = axis[i + 1] - axis[i];
+    (i, (value - axis[i]) / dx, 1.0 / dx)
+}
+
+impl Obstacle {
+    fn distance(&self, p: &Vector3<f64>) -> (f64, Vector3<f64>) {
+        match self {
+            Obstacle::Box { center, size, yaw } => {
+                let rot = Rotation3::from_axis_angle(&Vector3::z_axis(), *yaw);
+                let local = rot.inverse() * (p - center);
+                let q = local.abs() - 0.5 * size;
+                let outside = q.map(|v| v.max(0.0));
+                let (distance, normal) = if outside.norm() > 0.0 {
+                    (
+                        outside.norm(),
+                        outside.component_mul(&local.map(f64::signum)),
+                    )
+                } else {
+                    let k = q.imax();
+                    let mut normal = Vector3::zeros();
+                    normal[k] = local[k].signum();
+                    (q[k], normal)
+                };
+                (distance, rot * normal.normalize())
+            }
+            Obstacle::Cylinder {
+                center,
+                radius,
+                height,
+            } => {
+                let d = p - center;
+                let radial = d.xy().norm();
+                let q = Vector2::new(radial - radius, d[2].abs() - 0.5 * height);
+                let e_r = if radial > 1e-12 {
+                    Vector3::new(d[0] / radial, d[1] / radial, 0.0)
+                } else {
+                    Vector3::x()
+                };
+                let e_z = Vector3::new(0.0, 0.0, d[2].signum());
+                let outside = q.map(|v| v.max(0.0));
+                if outside.norm() > 0.0 {
+                    let normal = outside[0] * e_r + outside[1] * e_z;
+                    (outside.norm(), normal / outside.norm())
+                } else if q[0] > q[1] {
+                    (q[0], e_r)
+                } else {
+                    (q[1], e_z)
+                }
+            }
+            Obstacle::Pipeline { start, end, radius } => {
+                let axis = end - start;
+                let s = ((p - start).dot(&axis) / axis.norm_squared()).clamp(0.0, 1.0);
+                let d = p - (start + s * axis);
+                let norm = d.norm();
+                let normal = if norm > 1e-12 {
+                    d / norm
+                } else {
+                    Vector3::new(0.0, 0.0, -1.0)
+                };
+                (norm - radius, normal)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distances() {
+        let seabed = Seabed::Heightmap {
+            x: vec![0.0, 10.0],
+            y: vec![0.0, 10.0],
+            z: vec![vec![20.0, 30.0], vec![20.0, 30.0]],
+        };
+        // 45 degree slope rising towards -x
+        let (d, n) = seabed.distance(&Vector3::new(5.0, 5.0, 23.0));
+        assert!((d - 2.0 / 2f64.sqrt()).abs() < 1e-12);
+        assert!((n - Vector3::new(1.0, 0.0, -1.0) / 2f64.sqrt()).norm() < 1e-12);
+
+        let boxed = Obstacle::Box {
+            center: Vector3::zeros(),
+            size: Vector3::new(2.0, 2.0, 2.0),
+            yaw: 0.0,
+        };
+        assert!((boxed.distance(&Vector3::new(3.0, 0.0, 0.0)).0 - 2.0).abs() < 1e-12);
+        assert!((boxed.distance(&Vector3::new(0.0, 0.5, 0.0)).0 + 0.5).abs() < 1e-12);
+
+        let pipe = Obstacle::Pipeline {
+            start: Vector3::zeros(),
+            end: Vector3::new(10.0, 0.0, 0.0),
+            radius: 0.5,
+        };
+        let (d, n) = pipe.distance(&Vector3::new(4.0, 0.0, -2.0));
+        assert!((d - 1.5).abs() < 1e-12 && (n - Vector3::new(0.0, 0.0, -1.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_resting_on_flat_seabed() {
+        let env = EnvironmentConfig {
+            seabed: Some(Seabed::Flat { z: 10.0 }),
+            obstacles: Vec::new(),
+            contact: ContactConfig::default(),
+        };
+        let (length, radius) = (1.0, 0.1);
+        // horizontal link pressed 1 cm into the seabed, sliding along x
+        let pose = Isometry3::translation(0.0, 0.0, 10.0 - radius + 0.01);
+        let twist = Vector6::new(1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let wrench = env.contact_wrench(&pose, &twist, &Vector3::zeros(), length, radius);
+
+        let normal_force = 1e5 * 0.01 * length;
+        assert!((wrench[2] + normal_force).abs() < 1e-6);
+        assert!((wrench[0] + 0.5 * normal_force).abs() < 1e-6);
+        // symmetric about the center, so only the friction moment about y remains
+        assert!(wrench[3].abs() < 1e-9 && wrench[5].abs() < 1e-9);
+        assert!((wrench[4] + 0.5 * normal_force * radius).abs() < 1e-6);
+    }
+}
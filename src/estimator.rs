@@ -0,0 +1,409 @@
+extern crate nalgebra as na;
+use std::collections::VecDeque;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use multibody_dynamics::math_functions::skew;
+use na::{Matrix3, Rotation3, SMatrix, SVector, UnitQuaternion, Vector1, Vector3, Vector6};
+use serde::Deserialize;
+
+use crate::sensors::{Measurement, SensorConfig};
+use crate::utils::discrete_quat_update;
+use crate::State;
+
+type Covariance = SMatrix<f64, 15, 15>;
+
+/// Error-state Kalman filter settings. The measurement noise is taken from the sensor config.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct EstimatorConfig {
+    /// Feed the estimated rather than the true state to the controller.
+    pub use_in_controller: bool,
+    /// Initial standard deviations of the position [m], velocity [m/s], attitude [rad], and the
+    /// accelerometer [m/s^2] and gyro [rad/s] biases.
+    initial_position_std: f64,
+    initial_velocity_std: f64,
+    initial_attitude_std: f64,
+    initial_accel_bias_std: f64,
+    initial_gyro_bias_std: f64,
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        EstimatorConfig {
+            use_in_controller: false,
+            initial_position_std: 0.5,
+            initial_velocity_std: 0.1,
+            initial_attitude_std: 0.05,
+            initial_accel_bias_std: 0.05,
+            initial_gyro_bias_std: 0.01,
+        }
+    }
+}
+
+/// Pose and velocities of the vehicle as used by the controller.
+#[derive(Debug, Clone)]
+pub struct NavState {
+    pub pos: Vector3<f64>,
+    pub quat: UnitQuaternion<f64>,
+    /// Base velocities, expressed in the base frame.
+    pub nu_b: Vector6<f64>,
+    pub theta: SVector<f64, 8>,
+    pub theta_dot: SVector<f64, 8>,
+}
+
+impl NavState {
+    pub fn from_state(y: &State) -> Self {
+        NavState {
+            pos: y.fixed_rows::<3>(0).into(),
+            quat: UnitQuaternion::from_quaternion(na::Quaternion::new(y[3], y[4], y[5], y[6])),
+            nu_b: y.fixed_rows::<6>(15).into(),
+            theta: y.fixed_rows::<8>(7).into(),
+            theta_dot: y.fixed_rows::<8>(21).into(),
+        }
+    }
+}
+
+/// Error-state Kalman filter estimating the position, velocity and attitude of the base link, and the IMU
+/// biases. The nominal state is propagated with the IMU, and corrected by the DVL, depth sensor and USBL.
+/// The error state is [dp, dv, dtheta, db_a, db_g], with the position and velocity of the base link origin in
+/// the world frame, the attitude error in the base frame, and the biases in the base frame.
+/// The joint angles are taken from the encoders, and the joint velocities by differentiating them.
+#[derive(Debug, Clone)]
+pub struct Estimator {
+    config: EstimatorConfig,
+    sensors: SensorConfig,
+    gravity: Vector3<f64>,
+    time: f64,
+    pos: Vector3<f64>,
+    vel: Vector3<f64>,
+    quat: UnitQuaternion<f64>,
+    accel_bias: Vector3<f64>,
+    gyro_bias: Vector3<f64>,
+    cov: Covariance,
+    /// Latest angular rate and specific force, rotated into the base frame.
+    imu: Option<(Vector3<f64>, Vector3<f64>)>,
+    /// Past positions and attitudes, used for the delayed USBL fixes.
+    history: VecDeque<(f64, Vector3<f64>, UnitQuaternion<f64>)>,
+    theta: SVector<f64, 8>,
+    theta_dot: SVector<f64, 8>,
+    encoder_time: Option<f64>,
+    log: Vec<(f64, SVector<f64, 15>)>,
+}
+
+impl Estimator {
+    /// Creates an estimator initialized at the state `y0`.
+    pub fn new(
+        cfg: &EstimatorConfig,
+        sensors: &SensorConfig,
+        gravity: &Vector3<f64>,
+        y0: &State,
+    ) -> Result<Self, String> {
+        match &sensors.imu {
+            Some(imu) if imu.mount.link == 1 => (),
+            _ => return Err("The estimator needs an IMU on the base link".to_string()),
+        }
+
+        let init = NavState::from_state(y0);
+        let variances = [
+            cfg.initial_position_std,
+            cfg.initial_velocity_std,
+            cfg.initial_attitude_std,
+            cfg.initial_accel_bias_std,
+            cfg.initial_gyro_bias_std,
+        ]
+        .map(|std| std.powi(2));
+        let cov = Covariance::from_diagonal(&SVector::from_fn(|i, _| variances[i / 3]));
+
+        Ok(Estimator {
+            config: cfg.clone(),
+            sensors: sensors.clone(),
+            gravity: *gravity,
+            time: 0.0,
+            pos: init.pos,
+            vel: init.quat * init.nu_b.fixed_rows::<3>(0),
+            quat: init.quat,
+            accel_bias: Vector3::zeros(),
+            gyro_bias: Vector3::zeros(),
+            cov,
+            imu: None,
+            history: VecDeque::new(),
+            theta: init.theta,
+            theta_dot: init.theta_dot,
+            encoder_time: None,
+            log: Vec::new(),
+        })
+    }
+
+    pub fn use_in_controller(&self) -> bool {
+        self.config.use_in_controller
+    }
+
+    /// Current estimate of the vehicle state.
+    pub fn estimate(&self) -> NavState {
+        let omega = self.angular_rate().unwrap_or_default();
+        let vel_b = self.quat.inverse() * self.vel;
+        NavState {
+            pos: self.pos,
+            quat: self.quat,
+            nu_b: Vector6::new(vel_b[0], vel_b[1], vel_b[2], omega[0], omega[1], omega[2]),
+            theta: self.theta,
+            theta_dot: self.theta_dot,
+        }
+    }
+
+    fn angular_rate(&self) -> Option<Vector3<f64>> {
+        self.imu.map(|(omega, _)| omega - self.gyro_bias)
+    }
+
+    /// Processes a measurement. IMU samples propagate the filter; the other sensors correct it.
+    pub fn process(&mut self, m: &Measurement) {
+        match m {
+            Measurement::Imu {
+                time,
+                angular_rate,
+                specific_force,
+            } => {
+                self.predict(*time);
+                let rot = self.sensors.imu.as_ref().unwrap().mount.rotation();
+                self.imu = Some((rot * angular_rate, rot * specific_force));
+            }
+            Measurement::Dvl { velocity, .. } => {
+                let (dvl, omega) = match (&self.sensors.dvl, self.angular_rate()) {
+                    (Some(dvl), Some(omega)) => (dvl.clone(), omega),
+                    _ => return,
+                };
+                let rot_s = dvl.mount.rotation().inverse();
+                let p_s = dvl.mount.position;
+                let vel_b = self.quat.inverse() * self.vel;
+                let innovation = velocity - rot_s * (vel_b + omega.cross(&p_s));
+
+                let mut h = SMatrix::<f64, 3, 15>::zeros();
+                h.fixed_view_mut::<3, 3>(0, 3)
+                    .copy_from(&(rot_s * self.quat.inverse().to_rotation_matrix()).into_inner()); // dv
+                h.fixed_view_mut::<3, 3>(0, 6)
+                    .copy_from(&(rot_s * skew(&vel_b))); // dtheta
+                h.fixed_view_mut::<3, 3>(0, 12)
+                    .copy_from(&(rot_s * skew(&p_s))); // db_g
+                self.correct(&innovation, &h, dvl.noise);
+            }
+            Measurement::Depth { depth, .. } => {
+                let Some(sensor) = self.sensors.depth.clone() else {
+                    return;
+                };
+                let rot = self.quat.to_rotation_matrix();
+                let pos = self.pos + rot * sensor.mount.position;
+                let innovation = Vector1::new(depth - (pos[2] - sensor.surface_z));
+
+                let mut h = SMatrix::<f64, 1, 15>::zeros();
+                h[(0, 2)] = 1.0; // dp
+                h.fixed_view_mut::<1, 3>(0, 6)
+                    .copy_from(&-(rot * skew(&sensor.mount.position)).row(2)); // dtheta
+                self.correct(&innovation, &h, sensor.noise);
+            }
+            Measurement::Usbl { time, position } => {
+                let Some(usbl) = self.sensors.usbl.clone() else {
+                    return;
+                };
+                // compare with the estimate at the time of validity, and correct the current state
+                let (pos, quat) = self
+                    .history
+                    .iter()
+                    .rev()
+                    .find(|(t, _, _)| *t <= time + 1e-9)
+                    .map(|(_, pos, quat)| (*pos, *quat))
+                    .unwrap_or((self.pos, self.quat));
+                let innovation = position - (pos + quat * usbl.mount.position);
+
+                let mut h = SMatrix::<f64, 3, 15>::zeros();
+                h.fixed_view_mut::<3, 3>(0, 0)
+                    .copy_from(&Matrix3::identity()); // dp
+                h.fixed_view_mut::<3, 3>(0, 6)
+                    .copy_from(&-(self.quat.to_rotation_matrix() * skew(&usbl.mount.position))); // dtheta
+                self.correct(&innovation, &h, usbl.noise);
+            }
+            Measurement::Encoders { time, angles } => {
+                if let Some(t_prev) = self.encoder_time {
+                    if *time > t_prev {
+                        self.theta_dot = (angles - self.theta) / (time - t_prev);
+                    }
+                }
+                self.theta = *angles;
+                self.encoder_time = Some(*time);
+            }
+        }
+    }
+
+    /// Propagates the nominal state and the error covariance to time `t`, holding the latest IMU sample.
+    pub fn predict(&mut self, t: f64) {
+        let dt = t - self.time;
+        if dt <= 0.0 {
+            return;
+        }
+        self.time = t;
+        let imu_cfg = self.sensors.imu.as_ref().unwrap();
+
+        if let Some((omega_m, force_m)) = self.imu {
+            let omega = omega_m - self.gyro_bias;
+            // remove the centripetal acceleration of the IMU about the base link origin
+            let p_imu = imu_cfg.mount.position;
+            let force = force_m - self.accel_bias - omega.cross(&omega.cross(&p_imu));
+            let rot = self.quat.to_rotation_matrix().into_inner();
+            let accel = rot * force + self.gravity;
+
+            self.pos += self.vel * dt + 0.5 * accel * dt.powi(2);
+            self.vel += accel * dt;
+            self.quat = discrete_quat_update(&self.quat, &(omega * dt));
+
+            let mut f = Covariance::identity();
+            f.fixed_view_mut::<3, 3>(0, 3)
+                .copy_from(&(Matrix3::identity() * dt)); // dp/dv
+            f.fixed_view_mut::<3, 3>(3, 6)
+                .copy_from(&(-rot * skew(&force) * dt)); // dv/dtheta
+            f.fixed_view_mut::<3, 3>(3, 9).copy_from(&(-rot * dt)); // dv/db_a
+            f.fixed_view_mut::<3, 3>(6, 6).copy_from(
+                Rotation3::new(omega * dt).inverse().matrix(), // dtheta/dtheta
+            );
+            f.fixed_view_mut::<3, 3>(6, 12)
+                .copy_from(&(-Matrix3::identity() * dt)); // dtheta/db_g
+
+            let noise = [
+                0.0,
+                (imu_cfg.accel_noise * dt).powi(2),
+                (imu_cfg.gyro_noise * dt).powi(2),
+                imu_cfg.accel_bias_walk.powi(2) * dt,
+                imu_cfg.gyro_bias_walk.powi(2) * dt,
+            ];
+            let q = Covariance::from_diagonal(&SVector::from_fn(|i, _| noise[i / 3]));
+            self.cov = f * self.cov * f.transpose() + q;
+        }
+
+        self.history.push_back((t, self.pos, self.quat));
+        let horizon = self.sensors.usbl.as_ref().map_or(0.0, |usbl| usbl.latency) + 1.0;
+        while self.history.len() > 1 && self.history[0].0 < t - horizon {
+            self.history.pop_front();
+        }
+    }
+
+    /// Kalman update with the innovation `y = z - h(x)`, measurement Jacobian `h` with respect to the error
+    /// state, and measurement noise standard deviation `std`. The error is injected into the nominal state and
+    /// the covariance reset.
+    fn correct<const M: usize>(
+        &mut self,
+        innovation: &SVector<f64, M>,
+        h: &SMatrix<f64, M, 15>,
+        std: f64,
+    ) {
+        let r = SMatrix::<f64, M, M>::identity() * std.powi(2).max(1e-12);
+        let s = h * self.cov * h.transpose() + r;
+        let Some(s_inv) = s.try_inverse() else {
+            return;
+        };
+        let k = self.cov * h.transpose() * s_inv;
+        let dx = k * innovation;
+
+        // Joseph form
+        let i_kh = Covariance::identity() - k * h;
+        self.cov = i_kh * self.cov * i_kh.transpose() + k * r * k.transpose();
+
+        let dtheta = dx.fixed_rows::<3>(6).into_owned();
+        self.pos += dx.fixed_rows::<3>(0);
+        self.vel += dx.fixed_rows::<3>(3);
+        self.quat = discrete_quat_update(&self.quat, &dtheta);
+        self.accel_bias += dx.fixed_rows::<3>(9);
+        self.gyro_bias += dx.fixed_rows::<3>(12);
+
+        let mut g = Covariance::identity();
+        g.fixed_view_mut::<3, 3>(6, 6)
+            .copy_from(&(Matrix3::identity() - skew(&(0.5 * dtheta))));
+        self.cov = g * self.cov * g.transpose();
+    }
+
+    /// Logs the estimation error with respect to the true state at time `t`.
+    pub fn log_error(&mut self, t: f64, truth: &NavState) {
+        let est = self.estimate();
+        let mut quat_err = truth.quat.inverse() * est.quat;
+        if quat_err.w < 0.0 {
+            quat_err = UnitQuaternion::new_unchecked(-quat_err.into_inner());
+        }
+        let mut row = SVector::<f64, 15>::zeros();
+        row.fixed_rows_mut::<3>(0).copy_from(&(est.pos - truth.pos));
+        row.fixed_rows_mut::<3>(3)
+            .copy_from(&(est.nu_b - truth.nu_b).fixed_rows::<3>(0));
+        row.fixed_rows_mut::<3>(6)
+            .copy_from(&(2.0 * quat_err.vector()));
+        for i in 0..3 {
+            row[9 + i] = self.cov[(i, i)].sqrt();
+            row[12 + i] = self.cov[(6 + i, 6 + i)].sqrt();
+        }
+        self.log.push((t, row));
+    }
+
+    /// Writes the logged errors as rows of time, position error, body velocity error, attitude error, and the
+    /// estimated standard deviations of the position and attitude.
+    pub fn save(&self, filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let mut buf = BufWriter::new(file);
+        for (t, row) in &self.log {
+            buf.write_fmt(format_args!("{}", t)).unwrap();
+            for val in row.iter() {
+                buf.write_fmt(format_args!(", {}", val)).unwrap();
+            }
+            buf.write_fmt(format_args!("\n")).unwrap();
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_converges_to_position_fixes() {
+        let sensors: SensorConfig = serde_yaml::from_str(
+            "
+            imu: {rate: 100.0}
+            depth: {rate: 10.0, noise: 0.01}
+            usbl: {rate: 1.0, noise: 0.1, latency: 0.5}
+            ",
+        )
+        .unwrap();
+        let gravity = Vector3::new(0.0, 0.0, 9.81);
+        let mut y0 = State::zeros();
+        y0[3] = 1.0;
+        let mut est = Estimator::new(&EstimatorConfig::default(), &sensors, &gravity, &y0).unwrap();
+
+        // vehicle at rest at (2, -1, 5), estimator initialized at the origin
+        let truth = Vector3::new(2.0, -1.0, 5.0);
+        for k in 0..2000 {
+            let t = k as f64 * 0.01;
+            est.process(&Measurement::Imu {
+                time: t,
+                angular_rate: Vector3::zeros(),
+                specific_force: -gravity,
+            });
+            if k % 10 == 0 {
+                est.process(&Measurement::Depth {
+                    time: t,
+                    depth: truth[2],
+                });
+            }
+            if k % 100 == 50 {
+                est.process(&Measurement::Usbl {
+                    time: t - 0.5,
+                    position: truth,
+                });
+            }
+        }
+        assert!((est.estimate().pos - truth).norm() < 0.05);
+        assert!(est.estimate().nu_b.norm() < 0.05);
+    }
+}
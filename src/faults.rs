@@ -0,0 +1,286 @@
+extern crate nalgebra as na;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use na::{SMatrix, SVector};
+use serde::Deserialize;
+
+fn default_lock_stiffness() -> f64 {
+    2000.0
+}
+
+fn default_lock_damping() -> f64 {
+    200.0
+}
+
+/// Actuator fault. Thrusters and joints are numbered from 1.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Fault {
+    /// The thruster produces a constant thrust [N] whatever the command.
+    ThrusterStuck { thruster: usize, thrust: f64 },
+    /// The thruster produces no thrust.
+    ThrusterLost { thruster: usize },
+    /// The thruster produces a fraction `efficiency` of the commanded thrust.
+    ThrusterEfficiency { thruster: usize, efficiency: f64 },
+    /// The joint brake holds the joint at its angle at the time of the fault, and the motor is lost.
+    JointLocked { joint: usize },
+    /// The joint motor is lost, and the joint swings freely.
+    JointFree { joint: usize },
+}
+
+/// Fault taking effect at the first sample time at or after `time` [s].
+#[derive(Debug, Deserialize, Clone)]
+pub struct FaultEvent {
+    time: f64,
+    #[serde(flatten)]
+    fault: Fault,
+}
+
+/// What the allocation knows about the faults.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(tag = "type")]
+pub enum FaultAwareness {
+    /// The allocation keeps using all the thrusters and joint motors.
+    #[default]
+    None,
+    /// The allocation is informed of the faults when they occur.
+    Informed,
+    /// The faults are detected `delay` [s] after they occur.
+    Detected { delay: f64 },
+}
+
+/// Actuator faults injected during the run, and the reallocation of the controller output over the remaining
+/// columns of the thruster configuration matrix once the faults are known.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FaultConfig {
+    events: Vec<FaultEvent>,
+    #[serde(default)]
+    awareness: FaultAwareness,
+    /// Stiffness [Nm/rad] of the brakes of the locked joints.
+    #[serde(default = "default_lock_stiffness")]
+    lock_stiffness: f64,
+    /// Damping [Nms/rad] of the brakes of the locked joints.
+    #[serde(default = "default_lock_damping")]
+    lock_damping: f64,
+}
+
+impl FaultConfig {
+    pub fn validate(&self, num_thrusters: usize, num_joints: usize) -> Result<(), String> {
+        for event in &self.events {
+            match event.fault {
+                Fault::ThrusterStuck { thruster, .. }
+                | Fault::ThrusterLost { thruster }
+                | Fault::ThrusterEfficiency { thruster, .. }
+                    if thruster == 0 || thruster > num_thrusters =>
+                {
+                    return Err(format!("There is no thruster {thruster} to fail"));
+                }
+                Fault::ThrusterEfficiency { efficiency, .. }
+                    if !(0.0..=1.0).contains(&efficiency) =>
+                {
+                    return Err("The thruster efficiency must be between 0 and 1".to_string());
+                }
+                Fault::JointLocked { joint } | Fault::JointFree { joint }
+                    if joint == 0 || joint > num_joints =>
+                {
+                    return Err(format!("There is no joint {joint} to fail"));
+                }
+                _ => (),
+            }
+        }
+        if let FaultAwareness::Detected { delay } = self.awareness {
+            if delay < 0.0 {
+                return Err("The fault detection delay must be non-negative".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the controller output is reallocated once the faults are known.
+    pub fn is_aware(&self) -> bool {
+        !matches!(self.awareness, FaultAwareness::None)
+    }
+}
+
+/// Faults with the times they took effect, and the angles the locked joints are held at.
+#[derive(Debug, Clone)]
+pub struct Faults {
+    cfg: FaultConfig,
+    active: Vec<Option<(f64, f64)>>,
+}
+
+impl Faults {
+    pub fn new(cfg: &FaultConfig) -> Self {
+        Faults {
+            cfg: cfg.clone(),
+            active: vec![None; cfg.events.len()],
+        }
+    }
+
+    /// Activates the faults due at the sample time `t`.
+    pub fn update(&mut self, t: f64, theta: &SVector<f64, 8>) {
+        for (event, active) in self.cfg.events.iter().zip(&mut self.active) {
+            if active.is_none() && event.time <= t {
+                let angle = match event.fault {
+                    Fault::JointLocked { joint } => theta[joint - 1],
+                    _ => 0.0,
+                };
+                *active = Some((t, angle));
+            }
+        }
+    }
+
+    /// Active faults with the angles of the locked joints.
+    fn active(&self) -> impl Iterator<Item = (&Fault, f64)> {
+        self.cfg
+            .events
+            .iter()
+            .zip(&self.active)
+            .filter_map(|(event, active)| active.map(|(_, angle)| (&event.fault, angle)))
+    }
+
+    /// Allocation matrix over the actuators not known to have failed at time `t`, with the efficiencies of the
+    /// degraded thrusters, and the known stuck thrusts, so that u = allocation (tau - tcm_tot stuck) + stuck.
+    /// None until a fault is known.
+    pub fn allocation(
+        &self,
+        t: f64,
+        tcm_tot: &SMatrix<f64, 14, 20>,
+    ) -> Option<(SMatrix<f64, 20, 14>, SVector<f64, 20>)> {
+        let delay = match self.cfg.awareness {
+            FaultAwareness::None => return None,
+            FaultAwareness::Informed => 0.0,
+            FaultAwareness::Detected { delay } => delay,
+        };
+        let known: Vec<&Fault> = self
+            .cfg
+            .events
+            .iter()
+            .zip(&self.active)
+            .filter(|(_, active)| active.is_some_and(|(time, _)| t >= time + delay))
+            .map(|(event, _)| &event.fault)
+            .collect();
+        if known.is_empty() {
+            return None;
+        }
+
+        let mut efficiency = SVector::<f64, 20>::repeat(1.0);
+        let mut stuck = SVector::<f64, 20>::zeros();
+        for fault in known {
+            match *fault {
+                Fault::ThrusterStuck { thruster, thrust } => {
+                    efficiency[thruster - 1] = 0.0;
+                    stuck[thruster - 1] = thrust;
+                }
+                Fault::ThrusterLost { thruster } => efficiency[thruster - 1] = 0.0,
+                Fault::ThrusterEfficiency {
+                    thruster,
+                    efficiency: e,
+                } => efficiency[thruster - 1] *= e,
+                Fault::JointLocked { joint } | Fault::JointFree { joint } => {
+                    efficiency[11 + joint] = 0.0
+                }
+            }
+        }
+        let degraded = tcm_tot * SMatrix::from_diagonal(&efficiency);
+        Some((degraded.pseudo_inverse(1e-9).ok()?, stuck))
+    }
+
+    /// Replaces the commanded thrusts and joint motor torques in `u` with what the faulty actuators produce, and
+    /// adds the brake torques of the locked joints to `joint_torque_passive`.
+    pub fn actuate(
+        &self,
+        u: &mut SVector<f64, 20>,
+        joint_torque_passive: &mut SVector<f64, 8>,
+        theta: &SVector<f64, 8>,
+        theta_dot: &SVector<f64, 8>,
+    ) {
+        for (fault, angle) in self.active() {
+            match *fault {
+                Fault::ThrusterStuck { thruster, thrust } => u[thruster - 1] = thrust,
+                Fault::ThrusterLost { thruster } => u[thruster - 1] = 0.0,
+                Fault::ThrusterEfficiency {
+                    thruster,
+                    efficiency,
+                } => u[thruster - 1] *= efficiency,
+                Fault::JointLocked { joint } => {
+                    let i = joint - 1;
+                    u[12 + i] = 0.0;
+                    joint_torque_passive[i] += self.cfg.lock_stiffness * (angle - theta[i])
+                        - self.cfg.lock_damping * theta_dot[i];
+                }
+                Fault::JointFree { joint } => u[11 + joint] = 0.0,
+            }
+        }
+    }
+
+    /// Writes the faults that took effect as rows of time, time known to the allocation and fault. The time
+    /// known is NaN if the allocation is not aware of the faults.
+    pub fn save(&self, filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let delay = match self.cfg.awareness {
+            FaultAwareness::None => f64::NAN,
+            FaultAwareness::Informed => 0.0,
+            FaultAwareness::Detected { delay } => delay,
+        };
+        let mut buf = BufWriter::new(file);
+        for (event, active) in self.cfg.events.iter().zip(&self.active) {
+            if let Some((t, _)) = active {
+                buf.write_fmt(format_args!("{}, {}, {:?}\n", t, t + delay, event.fault))
+                    .unwrap();
+            }
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::comp_tcm_tot;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+    use na::Isometry3;
+
+    #[test]
+    fn test_reallocation() {
+        let config = test_config();
+        let multibody = setup_aiauv(&config, &comp_link_properties(&config));
+        let theta = SVector::<f64, 8>::repeat(0.4);
+        let conf = multibody.minimal_to_homogenous_configuration(&Isometry3::identity(), &theta);
+        let tcm_tot = comp_tcm_tot(&config, &multibody.compute_jacobians(&conf));
+        let tau = SVector::<f64, 14>::from_fn(|i, _| i as f64 - 6.0);
+        let cfg: FaultConfig = serde_yaml::from_str(
+            "{events: [{time: 1.0, type: ThrusterLost, thruster: 2}, {time: 1.0, type: ThrusterStuck, thruster: 5, thrust: 3.0}, \
+             {time: 1.0, type: ThrusterEfficiency, thruster: 7, efficiency: 0.5}, {time: 2.0, type: JointLocked, joint: 3}], \
+             awareness: {type: Detected, delay: 0.5}}",
+        )
+        .unwrap();
+        cfg.validate(12, 8).unwrap();
+        let mut faults = Faults::new(&cfg);
+
+        // nothing is known before the detection delay has passed
+        faults.update(1.0, &theta);
+        assert!(faults.allocation(1.2, &tcm_tot).is_none());
+
+        // once known, the faulty actuators still realize the wrench without the lost thruster, but for the torque
+        // on the locked joint, which no thruster acts on and the brake takes
+        faults.update(2.0, &theta);
+        let (allocation, stuck) = faults.allocation(2.5, &tcm_tot).unwrap();
+        let mut u = allocation * (tau - tcm_tot * stuck) + stuck;
+        let mut passive = SVector::<f64, 8>::zeros();
+        faults.actuate(&mut u, &mut passive, &(theta * 1.1), &SVector::zeros());
+        let mut realized = tcm_tot * u;
+        realized[8] = tau[8];
+        assert!((realized - tau).norm() < 1e-9);
+        assert!(u[1] == 0.0 && u[4] == 3.0 && u[14] == 0.0);
+        assert!((passive[2] + 2000.0 * 0.04).abs() < 1e-9);
+    }
+}
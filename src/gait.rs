@@ -0,0 +1,186 @@
+extern crate nalgebra as na;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use multibody_dynamics::multibody::{Axis, JointType};
+use na::SVector;
+use serde::Deserialize;
+
+use crate::reference::Reference;
+use crate::Config;
+
+fn default_head_amplitude() -> f64 {
+    0.3
+}
+
+/// Shape of the travelling waves along the body. Lateral joints rotate about the z axis of their link, bending
+/// the body sideways, and vertical joints about the y axis.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum GaitPattern {
+    /// Wave of constant amplitude along the lateral joints, keeping the vertical joints straight.
+    LateralUndulation,
+    /// Wave along the lateral joints whose amplitude grows linearly from `head_amplitude` times the amplitude
+    /// at the joint nearest the head to the full amplitude at the tail, as in anguilliform swimming.
+    EelLike {
+        #[serde(default = "default_head_amplitude")]
+        head_amplitude: f64,
+    },
+    /// Waves along both the lateral and the vertical joints, the vertical wave lagging a quarter period behind.
+    Sidewinding { vertical_amplitude: f64 },
+}
+
+/// Periodic swimming gait replacing the joint references. The head is the front end of the straight body, along
+/// the x axis of the base. Each joint bends the body by bias + amplitude sin(2 pi frequency t - k phase_offset),
+/// where k counts the joints of the same axis from the head, and the joints that take no part in the gait are
+/// kept straight.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GaitConfig {
+    pattern: GaitPattern,
+    /// Amplitude [rad] of the lateral joint angles.
+    amplitude: f64,
+    /// Frequency [Hz] of the joint oscillations.
+    frequency: f64,
+    /// Phase offset [rad] between consecutive joints of the same axis. Positive offsets send the waves from
+    /// the head towards the tail.
+    phase_offset: f64,
+    /// Offset [rad] of the lateral joint angles, bending the body to one side to turn.
+    #[serde(default)]
+    bias: f64,
+    /// Switches off the thrusters, so that the vehicle is propelled by the joint motion alone.
+    #[serde(default)]
+    thrusters_off: bool,
+}
+
+impl GaitConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.amplitude < 0.0 || self.frequency < 0.0 {
+            return Err("The gait amplitude and frequency must be non-negative".to_string());
+        }
+        match self.pattern {
+            GaitPattern::EelLike { head_amplitude } if !(0.0..=1.0).contains(&head_amplitude) => {
+                Err("The head amplitude must be between 0 and 1".to_string())
+            }
+            GaitPattern::Sidewinding { vertical_amplitude } if vertical_amplitude < 0.0 => {
+                Err("The vertical amplitude must be non-negative".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn thrusters_off(&self) -> bool {
+        self.thrusters_off
+    }
+
+    /// Replaces the joint angles, rates and accelerations of `reference` with the gait at time `t`.
+    pub fn apply(&self, t: f64, cfg: &Config, reference: Reference) -> Reference {
+        let omega = 2.0 * PI * self.frequency;
+        let lateral = ordered_joints(cfg, |joint| matches!(joint, JointType::Revolute(Axis::Z)));
+        let vertical = ordered_joints(cfg, |joint| matches!(joint, JointType::Revolute(Axis::Y)));
+        let vertical_amplitude = match self.pattern {
+            GaitPattern::Sidewinding { vertical_amplitude } => vertical_amplitude,
+            _ => 0.0,
+        };
+
+        let mut waves = Vec::new();
+        for (k, &(i, sign)) in lateral.iter().enumerate() {
+            let envelope = match self.pattern {
+                GaitPattern::EelLike { head_amplitude } if lateral.len() > 1 => {
+                    let along = k as f64 / (lateral.len() - 1) as f64;
+                    head_amplitude + (1.0 - head_amplitude) * along
+                }
+                _ => 1.0,
+            };
+            let phase = -(k as f64) * self.phase_offset;
+            waves.push((i, sign, envelope * self.amplitude, phase, self.bias));
+        }
+        for (k, &(i, sign)) in vertical.iter().enumerate() {
+            let phase = -(k as f64) * self.phase_offset - FRAC_PI_2;
+            waves.push((i, sign, vertical_amplitude, phase, 0.0));
+        }
+
+        let mut theta = SVector::<f64, 8>::zeros();
+        let mut theta_dot = SVector::<f64, 8>::zeros();
+        let mut theta_ddot = SVector::<f64, 8>::zeros();
+        for (i, sign, amplitude, phase, bias) in waves {
+            let phase = omega * t + phase;
+            theta[i] = sign * (bias + amplitude * phase.sin());
+            theta_dot[i] = sign * omega * amplitude * phase.cos();
+            theta_ddot[i] = -sign * omega.powi(2) * amplitude * phase.sin();
+        }
+        Reference {
+            theta,
+            theta_dot,
+            theta_ddot,
+            ..reference
+        }
+    }
+}
+
+/// Indices of the joints of the given kind, ordered from the head at the front end of the straight body to the
+/// tail, with the signs making positive angles bend the body the same way on both sides of the base.
+pub fn ordered_joints(cfg: &Config, is_kind: impl Fn(&JointType) -> bool) -> Vec<(usize, f64)> {
+    let mut stations = vec![0.0; cfg.joint_types.len()];
+    for body in 1..stations.len() {
+        stations[body] = stations[cfg.parents[body] as usize - 1] + cfg.pos_offsets[body].x;
+    }
+    let mut joints: Vec<_> = (1..stations.len())
+        .filter(|&body| is_kind(&cfg.joint_types[body]))
+        .collect();
+    joints.sort_by(|a, b| stations[*b].total_cmp(&stations[*a]));
+    joints
+        .into_iter()
+        .map(|body| (body - 1, cfg.pos_offsets[body].x.signum()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use crate::test_config;
+
+    #[test]
+    fn test_gaits() {
+        let cfg = test_config();
+        // joints from the head to the tail, and the signs of their bending
+        let lateral = [(6, 1.0), (4, 1.0), (1, -1.0), (3, -1.0)];
+        let vertical = [7, 5, 0, 2];
+        let at = |gait: &GaitConfig, t: f64| gait.apply(t, &cfg, ReferenceConfig::default().at(t));
+
+        // a quarter period in, the joint nearest the head peaks and the wave follows towards the tail
+        let gait: GaitConfig = serde_yaml::from_str(
+            "{pattern: {type: LateralUndulation}, amplitude: 0.4, frequency: 0.5, phase_offset: 0.8, bias: 0.1}",
+        )
+        .unwrap();
+        gait.validate().unwrap();
+        let reference = at(&gait, 0.5);
+        for (k, &(i, sign)) in lateral.iter().enumerate() {
+            let phase = 0.5 * PI - k as f64 * 0.8;
+            assert!((reference.theta[i] - sign * (0.1 + 0.4 * phase.sin())).abs() < 1e-12);
+            assert!((reference.theta_dot[i] - sign * 0.4 * PI * phase.cos()).abs() < 1e-12);
+        }
+        assert!(vertical.iter().all(|&i| reference.theta[i] == 0.0));
+
+        // the rates and accelerations are the derivatives of the angles
+        let gait: GaitConfig = serde_yaml::from_str(
+            "{pattern: {type: Sidewinding, vertical_amplitude: 0.2}, amplitude: 0.4, frequency: 0.5, phase_offset: 0.8}",
+        )
+        .unwrap();
+        let (reference, later) = (at(&gait, 1.3), at(&gait, 1.3 + 1e-6));
+        assert!(((later.theta - reference.theta) / 1e-6 - reference.theta_dot).amax() < 1e-5);
+        assert!(
+            ((later.theta_dot - reference.theta_dot) / 1e-6 - reference.theta_ddot).amax() < 1e-5
+        );
+        assert!(vertical.iter().all(|&i| reference.theta[i] != 0.0));
+
+        // the eel-like amplitudes grow towards the tail
+        let gait: GaitConfig = serde_yaml::from_str(
+            "{pattern: {type: EelLike, head_amplitude: 0.25}, amplitude: 0.4, frequency: 0.5, phase_offset: 0.0}",
+        )
+        .unwrap();
+        let reference = at(&gait, 0.5);
+        for (k, &(i, sign)) in lateral.iter().enumerate() {
+            assert!((reference.theta[i] - sign * 0.4 * (0.25 + 0.25 * k as f64)).abs() < 1e-12);
+        }
+    }
+}
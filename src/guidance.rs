@@ -0,0 +1,378 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::{SVector, UnitQuaternion, Vector3};
+use serde::Deserialize;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use crate::estimator::NavState;
+use crate::reference::Reference;
+
+/// Number of Newton iterations projecting the base onto a parametric path at each sample.
+const NUM_PROJECTION_ITER: usize = 3;
+
+fn default_lookahead() -> f64 {
+    3.0
+}
+
+fn default_acceptance_radius() -> f64 {
+    1.0
+}
+
+fn default_max_lead() -> f64 {
+    1.0
+}
+
+/// Path followed by the base, in the world frame.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum PathConfig {
+    /// Straight legs between consecutive waypoints, starting at the first.
+    Waypoints { waypoints: Vec<Vector3<f64>> },
+    /// Helix about a vertical axis through `center`, starting at `center + [radius, 0, 0]` and turning from the
+    /// x axis towards the y axis. The depth changes by `rise_per_turn` [m] along the z axis every turn.
+    Helix {
+        center: Vector3<f64>,
+        radius: f64,
+        #[serde(default)]
+        rise_per_turn: f64,
+        turns: f64,
+    },
+}
+
+/// Point and derivative at the angle `s` of the helix given by `PathConfig::Helix`.
+fn helix(
+    center: &Vector3<f64>,
+    radius: f64,
+    rise_per_turn: f64,
+    s: f64,
+) -> (Vector3<f64>, Vector3<f64>) {
+    let rise = rise_per_turn / (2.0 * PI);
+    (
+        center + Vector3::new(radius * s.cos(), radius * s.sin(), rise * s),
+        Vector3::new(-radius * s.sin(), radius * s.cos(), rise),
+    )
+}
+
+impl PathConfig {
+    fn start(&self) -> Vector3<f64> {
+        match self {
+            PathConfig::Waypoints { waypoints } => waypoints[0],
+            PathConfig::Helix {
+                center,
+                radius,
+                rise_per_turn,
+                ..
+            } => helix(center, *radius, *rise_per_turn, 0.0).0,
+        }
+    }
+}
+
+/// Line-of-sight guidance of the base along a path. The base is projected onto the path at every sample time,
+/// and the reference attitude points at the line-of-sight point `lookahead` ahead of the projection along
+/// the path, with zero roll. The reference position leads the base along the line of sight at the surge
+/// speed, so that the base controllers regulate the surge. Waypoints are switched within the circle of
+/// acceptance, and the base holds its pose at the end of the path.
+#[derive(Debug, Deserialize, Clone)]
+pub struct GuidanceConfig {
+    path: PathConfig,
+    /// Surge speed [m/s] along the path.
+    surge_speed: f64,
+    /// Lookahead distance [m] along the path.
+    #[serde(default = "default_lookahead")]
+    lookahead: f64,
+    /// Radius [m] of the circle of acceptance around the waypoints and the end of the path.
+    #[serde(default = "default_acceptance_radius")]
+    acceptance_radius: f64,
+    /// Largest distance [m] of the position reference from the base along the line of sight.
+    #[serde(default = "default_max_lead")]
+    max_lead: f64,
+}
+
+impl GuidanceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.surge_speed < 0.0 {
+            return Err("The guidance surge speed must be non-negative".to_string());
+        }
+        if self.lookahead <= 0.0 || self.acceptance_radius <= 0.0 || self.max_lead < 0.0 {
+            return Err(
+                "The lookahead distance and the acceptance radius must be positive, and the lead non-negative"
+                    .to_string(),
+            );
+        }
+        match &self.path {
+            PathConfig::Waypoints { waypoints } => {
+                if waypoints.len() < 2 {
+                    return Err("The path needs at least two waypoints".to_string());
+                }
+                if waypoints.windows(2).any(|w| (w[1] - w[0]).norm() < 1e-9) {
+                    return Err("Consecutive waypoints must be distinct".to_string());
+                }
+            }
+            PathConfig::Helix { radius, turns, .. } => {
+                if *radius <= 0.0 || *turns <= 0.0 {
+                    return Err("The helix radius and number of turns must be positive".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Guidance state between the sample times, and the logged progress.
+pub struct Guidance {
+    cfg: GuidanceConfig,
+    /// Index of the current leg between waypoints.
+    leg: usize,
+    /// Index of the current leg plus the fraction of it covered, or the helix angle [rad].
+    progress: f64,
+    completion_time: Option<f64>,
+    /// Reference position at the last sample time, moving along `direction` at `surge` until the next.
+    anchor: Vector3<f64>,
+    time: f64,
+    direction: Vector3<f64>,
+    surge: f64,
+    quat: UnitQuaternion<f64>,
+    log: Vec<(f64, SVector<f64, 6>)>,
+}
+
+impl Guidance {
+    pub fn new(cfg: &GuidanceConfig) -> Self {
+        Guidance {
+            cfg: cfg.clone(),
+            leg: 0,
+            progress: 0.0,
+            completion_time: None,
+            anchor: cfg.path.start(),
+            time: 0.0,
+            direction: Vector3::zeros(),
+            surge: 0.0,
+            quat: UnitQuaternion::identity(),
+            log: Vec::new(),
+        }
+    }
+
+    /// Projects the base onto the path, switching waypoints and detecting the end of the path. Returns the
+    /// projection and the unit tangent of the path there.
+    fn project(&mut self, t: f64, pos: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        let radius = self.cfg.acceptance_radius;
+        match &self.cfg.path {
+            PathConfig::Waypoints { waypoints } => {
+                while self.completion_time.is_none()
+                    && (pos - waypoints[self.leg + 1]).norm() < radius
+                {
+                    if self.leg + 2 == waypoints.len() {
+                        self.completion_time = Some(t);
+                    } else {
+                        self.leg += 1;
+                    }
+                }
+                let leg = self.leg;
+                let (start, end) = (waypoints[leg], waypoints[leg + 1]);
+                let length = (end - start).norm();
+                let tangent = (end - start) / length;
+                let along = (pos - start).dot(&tangent).clamp(0.0, length);
+                self.progress = leg as f64 + along / length;
+                (start + along * tangent, tangent)
+            }
+            PathConfig::Helix {
+                center,
+                radius: helix_radius,
+                rise_per_turn,
+                turns,
+            } => {
+                let helix = |s| helix(center, *helix_radius, *rise_per_turn, s);
+                let s_end = 2.0 * PI * turns;
+                let mut s = self.progress;
+                for _ in 0..NUM_PROJECTION_ITER {
+                    let (point, deriv) = helix(s);
+                    s += (pos - point).dot(&deriv) / deriv.norm_squared();
+                }
+                // progress along the path never goes back
+                s = s.clamp(self.progress, s_end);
+                self.progress = s;
+                let (point, deriv) = helix(s);
+                let (end, _) = helix(s_end);
+                if self.completion_time.is_none() && s >= s_end && (pos - end).norm() < radius {
+                    self.completion_time = Some(t);
+                }
+                (point, deriv.normalize())
+            }
+        }
+    }
+
+    /// Computes the line-of-sight references at the sample time `t` and logs the progress along the path.
+    pub fn update(&mut self, t: f64, nav: &NavState) {
+        let reference_pos = self.anchor + (t - self.time) * self.surge * self.direction;
+        let (projection, tangent) = self.project(t, &nav.pos);
+
+        let heading = |v: &Vector3<f64>| v[1].atan2(v[0]);
+        let pitch = |v: &Vector3<f64>| (-v[2]).atan2(v.xy().norm());
+        if self.completion_time.is_some() {
+            // hold the end of the path, level and on the last heading
+            self.anchor = match &self.cfg.path {
+                PathConfig::Waypoints { waypoints } => waypoints[waypoints.len() - 1],
+                PathConfig::Helix { .. } => projection,
+            };
+            self.surge = 0.0;
+            self.quat = UnitQuaternion::from_euler_angles(0.0, 0.0, heading(&tangent));
+        } else {
+            let line_of_sight = (projection + self.cfg.lookahead * tangent - nav.pos).normalize();
+            let lead = (reference_pos - nav.pos)
+                .dot(&line_of_sight)
+                .clamp(-self.cfg.max_lead, self.cfg.max_lead);
+            self.anchor = nav.pos + lead * line_of_sight;
+            self.direction = line_of_sight;
+            self.surge = self.cfg.surge_speed;
+            self.quat = UnitQuaternion::from_euler_angles(
+                0.0,
+                pitch(&line_of_sight),
+                heading(&line_of_sight),
+            );
+        }
+        self.time = t;
+
+        // Cross-track errors to the right of and below the path, in the path-tangential frame
+        let path_frame = UnitQuaternion::from_euler_angles(0.0, pitch(&tangent), heading(&tangent));
+        let track_err = path_frame.inverse() * (nav.pos - projection);
+        let (_, pitch_d, heading_d) = self.quat.euler_angles();
+        self.log.push((
+            t,
+            SVector::<f64, 6>::new(
+                self.progress,
+                track_err[1],
+                track_err[2],
+                heading_d,
+                pitch_d,
+                self.surge,
+            ),
+        ));
+    }
+
+    /// Replaces the base pose and velocity of `reference` with the guidance references at time `t`.
+    pub fn reference(&self, t: f64, reference: Reference) -> Reference {
+        Reference {
+            pos: self.anchor + (t - self.time) * self.surge * self.direction,
+            quat: self.quat,
+            vel: self.surge * self.direction,
+            ..reference
+        }
+    }
+
+    /// Progress along the path: the index of the current leg plus the fraction of it covered, or the number of
+    /// turns of the helix.
+    pub fn progress(&self) -> f64 {
+        match &self.cfg.path {
+            PathConfig::Waypoints { .. } => self.progress,
+            PathConfig::Helix { .. } => self.progress / (2.0 * PI),
+        }
+    }
+
+    /// Time at which the end of the path was reached, if it was.
+    pub fn completion_time(&self) -> Option<f64> {
+        self.completion_time
+    }
+
+    /// Root mean square of the logged cross-track errors.
+    pub fn rms_cross_track_error(&self) -> f64 {
+        let sum_sq: f64 = self
+            .log
+            .iter()
+            .map(|(_, row)| row[1].powi(2) + row[2].powi(2))
+            .sum();
+        (sum_sq / self.log.len().max(1) as f64).sqrt()
+    }
+
+    /// Writes the logged progress as rows of time, progress along the path, horizontal and vertical cross-track
+    /// errors, and the heading, pitch and surge speed references.
+    pub fn save(&self, filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let mut buf = BufWriter::new(file);
+        for (t, row) in &self.log {
+            buf.write_fmt(format_args!("{}", t)).unwrap();
+            for val in row.iter() {
+                buf.write_fmt(format_args!(", {}", val)).unwrap();
+            }
+            buf.write_fmt(format_args!("\n")).unwrap();
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use na::Vector6;
+
+    fn nav_at(pos: Vector3<f64>, quat: UnitQuaternion<f64>) -> NavState {
+        let reference = ReferenceConfig::default().at(0.0);
+        NavState {
+            pos,
+            quat,
+            nu_b: Vector6::zeros(),
+            theta: reference.theta,
+            theta_dot: SVector::zeros(),
+        }
+    }
+
+    /// Moves a point at the reference velocity of the guidance, and returns where it ends up.
+    fn follow(guidance: &mut Guidance, start: Vector3<f64>, duration: f64) -> Vector3<f64> {
+        let dt = 0.05;
+        let mut pos = start;
+        for k in 0..(duration / dt) as usize {
+            let t = k as f64 * dt;
+            guidance.update(t, &nav_at(pos, UnitQuaternion::identity()));
+            pos += dt * guidance.reference(t, ReferenceConfig::default().at(t)).vel;
+        }
+        pos
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let cfg: GuidanceConfig = serde_yaml::from_str(
+            "{path: {type: Waypoints, waypoints: [[0.0, 0.0, 0.0], [10.0, 0.0, 0.0], [10.0, 10.0, 5.0]]}, surge_speed: 0.5, lookahead: 2.0}",
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+
+        // 2 m to the right of and 2 m below the first leg, the base turns left by 45 degrees and pitches up
+        let mut guidance = Guidance::new(&cfg);
+        guidance.update(
+            0.0,
+            &nav_at(Vector3::new(3.0, 2.0, 2.0), UnitQuaternion::identity()),
+        );
+        let (roll, pitch, heading) = guidance.quat.euler_angles();
+        assert!(roll.abs() < 1e-9 && (heading + PI / 4.0).abs() < 1e-9);
+        assert!((pitch - (2.0 / 8f64.sqrt()).atan()).abs() < 1e-9);
+        let (_, row) = guidance.log[0];
+        assert!(
+            (row[0] - 0.3).abs() < 1e-9
+                && (row[1] - 2.0).abs() < 1e-9
+                && (row[2] - 2.0).abs() < 1e-9
+        );
+
+        // starting off the path, the waypoints are switched and the end of the path is held
+        let end = follow(&mut guidance, Vector3::new(0.0, -3.0, 1.0), 200.0);
+        assert!(guidance.completion_time().is_some() && guidance.progress() > 1.5);
+        assert!((end - Vector3::new(10.0, 10.0, 5.0)).norm() < cfg.acceptance_radius);
+
+        let cfg: GuidanceConfig = serde_yaml::from_str(
+            "{path: {type: Helix, center: [0.0, 0.0, 2.0], radius: 5.0, rise_per_turn: 1.0, turns: 1.5}, surge_speed: 0.5}",
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+        let mut guidance = Guidance::new(&cfg);
+        follow(&mut guidance, Vector3::new(6.0, 0.0, 2.0), 200.0);
+        assert!(guidance.completion_time().is_some() && (guidance.progress() - 1.5).abs() < 1e-9);
+        assert!(guidance.rms_cross_track_error() < 0.25);
+    }
+}
@@ -0,0 +1,266 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::{Matrix3, Matrix6, Vector3};
+use serde::Deserialize;
+
+use crate::energy::interp1;
+use crate::utils::{line_mass_rotational, trapz};
+use crate::Config;
+
+/// Number of strips used when integrating section properties along a link.
+const NUM_STRIPS: usize = 40;
+
+/// Shape of a link hull along its x-axis, which runs from x = 0 to x = `length` in the link frame.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "type")]
+pub enum HullProfile {
+    /// Cylinder of constant `radius`.
+    #[default]
+    Cylinder,
+    /// Circular sections with the radius interpolated linearly between axial stations.
+    Profile { x: Vec<f64>, radius: Vec<f64> },
+    /// Cylinder of constant `radius` with ellipsoidal ends. The tail is at x = 0 and the nose at x = `length`.
+    Ellipsoidal { nose_length: f64, tail_length: f64 },
+    /// Rectangular sections of constant `width` (along y) and `height` (along z).
+    Rectangular { width: f64, height: f64 },
+}
+
+/// Geometric properties of a cross-section of the hull.
+#[derive(Debug, Clone, Copy)]
+pub struct Section {
+    pub area: f64,
+    /// Half of the width projected onto a flow along y.
+    pub half_width_y: f64,
+    /// Half of the width projected onto a flow along z.
+    pub half_width_z: f64,
+    /// 2D added mass per unit length and fluid density for motion along y.
+    pub added_mass_y: f64,
+    /// 2D added mass per unit length and fluid density for motion along z.
+    pub added_mass_z: f64,
+    /// Second moments of area about the section y- and z-axes, \int z^2 dA and \int y^2 dA.
+    pub second_moment_y: f64,
+    pub second_moment_z: f64,
+}
+
+impl Section {
+    pub fn circular(radius: f64) -> Section {
+        let area = PI * radius.powi(2);
+        Section {
+            area,
+            half_width_y: radius,
+            half_width_z: radius,
+            added_mass_y: area,
+            added_mass_z: area,
+            second_moment_y: area * radius.powi(2) / 4.0,
+            second_moment_z: area * radius.powi(2) / 4.0,
+        }
+    }
+
+    /// The 2D added mass is approximated by that of a flat plate with the same projected width.
+    pub fn rectangular(width: f64, height: f64) -> Section {
+        let area = width * height;
+        Section {
+            area,
+            half_width_y: 0.5 * height,
+            half_width_z: 0.5 * width,
+            added_mass_y: PI * (0.5 * height).powi(2),
+            added_mass_z: PI * (0.5 * width).powi(2),
+            second_moment_y: area * height.powi(2) / 12.0,
+            second_moment_z: area * width.powi(2) / 12.0,
+        }
+    }
+}
+
+impl HullProfile {
+    pub fn validate(&self, length: f64) -> Result<(), String> {
+        match self {
+            HullProfile::Cylinder => Ok(()),
+            HullProfile::Profile { x, radius } => {
+                if x.is_empty() || x.len() != radius.len() {
+                    Err("The hull profile needs as many radii as stations".to_string())
+                } else if x.windows(2).any(|w| w[1] <= w[0]) {
+                    Err("The hull profile stations must be increasing".to_string())
+                } else if radius.iter().any(|r| *r < 0.0) {
+                    Err("The hull profile radii must be non-negative".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+            HullProfile::Ellipsoidal {
+                nose_length,
+                tail_length,
+            } => {
+                if *nose_length < 0.0 || *tail_length < 0.0 || nose_length + tail_length > length {
+                    Err("The ellipsoidal ends must be non-negative and fit in the link".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+            HullProfile::Rectangular { width, height } => {
+                if *width <= 0.0 || *height <= 0.0 {
+                    Err("The hull width and height must be positive".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    pub fn section(&self, x: f64, radius: f64, length: f64) -> Section {
+        match self {
+            HullProfile::Cylinder => Section::circular(radius),
+            HullProfile::Profile { x: xs, radius: rs } => Section::circular(interp1(xs, rs, x)),
+            HullProfile::Ellipsoidal {
+                nose_length,
+                tail_length,
+            } => {
+                let s = if x < *tail_length {
+                    (tail_length - x) / tail_length
+                } else if x > length - nose_length {
+                    (x - (length - nose_length)) / nose_length
+                } else {
+                    0.0
+                };
+                Section::circular(radius * (1.0 - s.min(1.0).powi(2)).sqrt())
+            }
+            HullProfile::Rectangular { width, height } => Section::rectangular(*width, *height),
+        }
+    }
+}
+
+static CYLINDER: HullProfile = HullProfile::Cylinder;
+
+/// Returns the hull profile of link `i`. Links without a profile are cylinders.
+pub fn hull_profile(cfg: &Config, i: usize) -> &HullProfile {
+    cfg.hull_profiles.get(i).unwrap_or(&CYLINDER)
+}
+
+/// Integrates `f(x, section)` along link `i` by strip theory.
+fn strip_integral<F>(cfg: &Config, i: usize, f: F) -> f64
+where
+    F: Fn(f64, &Section) -> f64,
+{
+    let profile = hull_profile(cfg, i);
+    let (radius, length) = (cfg.radius[i], cfg.length[i]);
+    trapz(
+        |x| f(x, &profile.section(x, radius, length)),
+        0.0,
+        length,
+        NUM_STRIPS,
+    )
+}
+
+pub fn hull_volume(cfg: &Config, i: usize) -> f64 {
+    match hull_profile(cfg, i) {
+        HullProfile::Cylinder => cfg.length[i] * PI * cfg.radius[i].powi(2),
+        _ => strip_integral(cfg, i, |_, s| s.area),
+    }
+}
+
+/// Radius of the circle with the same area as the largest cross-section of link `i`.
+pub fn equivalent_radius(cfg: &Config, i: usize) -> f64 {
+    match hull_profile(cfg, i) {
+        HullProfile::Cylinder => cfg.radius[i],
+        profile => {
+            let max_area = (0..=NUM_STRIPS)
+                .map(|k| {
+                    let x = k as f64 * cfg.length[i] / NUM_STRIPS as f64;
+                    profile.section(x, cfg.radius[i], cfg.length[i]).area
+                })
+                .fold(0.0, f64::max);
+            (max_area / PI).sqrt()
+        }
+    }
+}
+
+/// Computes the added mass matrix of link `i` by strip integration over its hull profile.
+/// Reduces to `slendermasss` for a cylinder.
+pub fn hull_added_mass(
+    cfg: &Config,
+    i: usize,
+    alpha: Option<f64>,
+    coeff_added: Option<f64>,
+) -> Matrix6<f64> {
+    let mut fluid_added_mass = Matrix6::zeros();
+
+    fluid_added_mass[(0, 0)] = alpha.unwrap_or(0.0) * strip_integral(cfg, i, |_, s| s.area);
+    fluid_added_mass[(1, 1)] = strip_integral(cfg, i, |_, s| s.added_mass_y);
+    fluid_added_mass[(2, 2)] = strip_integral(cfg, i, |_, s| s.added_mass_z);
+    fluid_added_mass[(4, 4)] = strip_integral(cfg, i, |x, s| x.powi(2) * s.added_mass_z);
+    fluid_added_mass[(5, 5)] = strip_integral(cfg, i, |x, s| x.powi(2) * s.added_mass_y);
+    fluid_added_mass[(2, 4)] = -strip_integral(cfg, i, |x, s| x * s.added_mass_z);
+    fluid_added_mass[(4, 2)] = fluid_added_mass[(2, 4)];
+    fluid_added_mass[(1, 5)] = strip_integral(cfg, i, |x, s| x * s.added_mass_y);
+    fluid_added_mass[(5, 1)] = fluid_added_mass[(1, 5)];
+
+    fluid_added_mass * cfg.fluid_density * coeff_added.unwrap_or(1.0)
+}
+
+/// Computes the rotational inertia of link `i` about the link frame origin. As in `comp_rb_mass_rotational`, the
+/// vertical offset of the center of gravity is obtained by moving part of `mass` to a line along the link at that
+/// height, and the rest is distributed uniformly over the hull volume.
+pub fn hull_rb_mass_rotational(cfg: &Config, i: usize, mass: f64) -> Matrix3<f64> {
+    let d = cfg.pos_com[i][2];
+    let m_r = d.abs() / equivalent_radius(cfg, i) * mass;
+    let density = (mass - m_r) / hull_volume(cfg, i);
+
+    let i_xx = strip_integral(cfg, i, |_, s| s.second_moment_y + s.second_moment_z);
+    let i_yy = strip_integral(cfg, i, |x, s| x.powi(2) * s.area + s.second_moment_y);
+    let i_zz = strip_integral(cfg, i, |x, s| x.powi(2) * s.area + s.second_moment_z);
+
+    density * Matrix3::from_diagonal(&Vector3::new(i_xx, i_yy, i_zz))
+        + line_mass_rotational(d, cfg.length[i], m_r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{comp_rb_mass_rotational, slendermasss};
+
+    fn single_link_config(profile: HullProfile) -> Config {
+        Config {
+            radius: vec![0.1],
+            length: vec![1.0],
+            pos_com: vec![Vector3::new(0.0, 0.0, 0.02)],
+            fluid_density: 1000.0,
+            hull_profiles: vec![profile],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_profile_matches_cylinder() {
+        let cfg = single_link_config(HullProfile::Profile {
+            x: vec![0.0, 1.0],
+            radius: vec![0.1, 0.1],
+        });
+        let added_mass = hull_added_mass(&cfg, 0, Some(0.2), None);
+        let added_mass_cyl = slendermasss(1.0, 0.1, 1000.0, Some(0.2), None);
+        assert!((added_mass - added_mass_cyl).norm() < 1e-3 * added_mass_cyl.norm());
+        assert!((hull_volume(&cfg, 0) - PI * 0.01).abs() < 1e-12);
+
+        let inertia = hull_rb_mass_rotational(&cfg, 0, 1.0);
+        let inertia_cyl = comp_rb_mass_rotational(cfg.pos_com[0], 0.1, 1.0, 1.0);
+        assert!((inertia - inertia_cyl).norm() < 1e-3 * inertia_cyl.norm());
+    }
+
+    #[test]
+    fn test_ellipsoidal_volume() {
+        // two half-ellipsoids of length 0.5 make up an ellipsoid with half the volume of the cylinder
+        let cfg = single_link_config(HullProfile::Ellipsoidal {
+            nose_length: 0.5,
+            tail_length: 0.5,
+        });
+        let volume_ellipsoid = 4.0 / 3.0 * PI * 0.5 * 0.1 * 0.1;
+        assert!((hull_volume(&cfg, 0) - volume_ellipsoid).abs() < 1e-2 * volume_ellipsoid);
+
+        assert!(cfg.hull_profiles[0].validate(1.0).is_ok());
+        assert!(cfg.hull_profiles[0].validate(0.8).is_err());
+        let profile = HullProfile::Profile {
+            x: vec![0.0, 1.0],
+            radius: vec![0.1],
+        };
+        assert!(profile.validate(1.0).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+extern crate nalgebra as na;
+use na::allocator::Allocator;
+use na::{Const, DefaultAllocator, DimDiff, DimSub, Matrix3, Matrix6, SMatrix, Vector6, U1};
+use serde::{Deserialize, Deserializer};
+
+/// Relative tolerance used when checking the symmetry and definiteness of user-supplied matrices.
+const MATRIX_CHECK_TOL: f64 = 1e-9;
+
+/// User-supplied hydrodynamic and inertial matrices of a link, e.g. obtained from CFD or experiments.
+/// Each matrix that is given overrides the corresponding analytical model. Matrices are written row by row.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LinkHydrodynamics {
+    /// 6x6 added mass matrix, expressed in the link frame.
+    #[serde(default, deserialize_with = "opt_matrix")]
+    added_mass: Option<Matrix6<f64>>,
+    /// 3x3 rigid-body inertia tensor about the link frame origin.
+    #[serde(default, deserialize_with = "opt_matrix")]
+    inertia: Option<Matrix3<f64>>,
+    /// 6x6 linear damping matrix D_l in w = -D_l nu.
+    #[serde(default, deserialize_with = "opt_matrix")]
+    linear_damping: Option<Matrix6<f64>>,
+    /// 6x6 quadratic damping matrix D_q in w = -D_q (|nu| .* nu).
+    #[serde(default, deserialize_with = "opt_matrix")]
+    quadratic_damping: Option<Matrix6<f64>>,
+}
+
+impl LinkHydrodynamics {
+    pub fn added_mass(&self) -> Option<Matrix6<f64>> {
+        self.added_mass
+    }
+
+    pub fn inertia(&self) -> Option<Matrix3<f64>> {
+        self.inertia
+    }
+
+    pub fn has_damping(&self) -> bool {
+        self.linear_damping.is_some() || self.quadratic_damping.is_some()
+    }
+
+    /// Computes the damping wrench for the relative link velocity `nu`. Matrices that are not given are zero.
+    pub fn damping(&self, nu: &Vector6<f64>) -> Vector6<f64> {
+        let mut out = Vector6::zeros();
+        if let Some(d_l) = &self.linear_damping {
+            out -= d_l * nu;
+        }
+        if let Some(d_q) = &self.quadratic_damping {
+            out -= d_q * nu.abs().component_mul(nu);
+        }
+        out
+    }
+
+    /// Checks that the inertia is symmetric positive definite, that the added mass is symmetric positive
+    /// semi-definite, as for the roll of slender bodies, and that the damping matrices are dissipative, i.e. that
+    /// their symmetric parts are positive semi-definite.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(m_a) = &self.added_mass {
+            check_symmetric_positive_semi_definite(m_a).map_err(|e| format!("added_mass {}", e))?;
+        }
+        if let Some(inertia) = &self.inertia {
+            check_symmetric_positive_definite(inertia).map_err(|e| format!("inertia {}", e))?;
+        }
+        for (name, d) in [
+            ("linear_damping", &self.linear_damping),
+            ("quadratic_damping", &self.quadratic_damping),
+        ] {
+            if let Some(d) = d {
+                let d_sym = 0.5 * (d + d.transpose());
+                if d_sym.symmetric_eigenvalues().min() < -MATRIX_CHECK_TOL * d.norm() {
+                    return Err(format!("{} is not dissipative", name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a mass matrix is finite and symmetric positive definite.
+pub fn check_symmetric_positive_definite<const N: usize>(
+    m: &SMatrix<f64, N, N>,
+) -> Result<(), String> {
+    check_symmetric(m)?;
+    if m.cholesky().is_none() {
+        return Err("is not positive definite".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a mass matrix is finite and symmetric positive semi-definite, e.g. the added mass of a slender
+/// body in roll.
+pub fn check_symmetric_positive_semi_definite<const N: usize>(
+    m: &SMatrix<f64, N, N>,
+) -> Result<(), String>
+where
+    Const<N>: DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<N>, U1>>,
+{
+    check_symmetric(m)?;
+    if m.symmetric_eigenvalues().min() < -MATRIX_CHECK_TOL * m.norm() {
+        return Err("is not positive semi-definite".to_string());
+    }
+    Ok(())
+}
+
+fn check_symmetric<const N: usize>(m: &SMatrix<f64, N, N>) -> Result<(), String> {
+    if m.iter().any(|v| !v.is_finite()) {
+        return Err("is not finite".to_string());
+    }
+    if (m - m.transpose()).norm() > MATRIX_CHECK_TOL * m.norm() {
+        return Err("is not symmetric".to_string());
+    }
+    Ok(())
+}
+
+/// Deserializes an optional matrix written as a list of rows.
+pub fn opt_matrix<'de, D, const R: usize, const C: usize>(
+    deserializer: D,
+) -> Result<Option<SMatrix<f64, R, C>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rows = Option::<Vec<Vec<f64>>>::deserialize(deserializer)?;
+    match rows {
+        None => Ok(None),
+        Some(rows) => {
+            if rows.len() != R || rows.iter().any(|row| row.len() != C) {
+                return Err(serde::de::Error::custom(format!(
+                    "expected a {}x{} matrix given as a list of rows",
+                    R, C
+                )));
+            }
+            Ok(Some(SMatrix::from_fn(|i, j| rows[i][j])))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut hydro = LinkHydrodynamics {
+            added_mass: Some(Matrix6::identity()),
+            inertia: Some(Matrix3::from_diagonal(&na::Vector3::new(1.0, 2.0, 3.0))),
+            linear_damping: Some(Matrix6::identity()),
+            ..Default::default()
+        };
+        assert!(hydro.validate().is_ok());
+
+        let mut m_a = Matrix6::identity();
+        m_a[(1, 5)] = 0.5;
+        hydro.added_mass = Some(m_a);
+        assert!(hydro.validate().is_err());
+
+        // slender bodies have no added mass in roll
+        hydro.added_mass = Some(crate::utils::slendermasss(
+            1.0,
+            0.1,
+            1000.0,
+            Some(0.2),
+            None,
+        ));
+        assert!(hydro.validate().is_ok());
+
+        hydro.added_mass = None;
+        hydro.inertia = Some(Matrix3::from_diagonal(&na::Vector3::new(1.0, -2.0, 3.0)));
+        assert!(hydro.validate().is_err());
+
+        hydro.inertia = None;
+        hydro.quadratic_damping = Some(-Matrix6::identity());
+        assert!(hydro.validate().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_rows() {
+        let hydro: LinkHydrodynamics =
+            serde_yaml::from_str("inertia: [[1.0, 0.1, 0.0], [0.1, 2.0, 0.0], [0.0, 0.0, 3.0]]")
+                .unwrap();
+        assert_eq!(hydro.inertia().unwrap()[(0, 1)], 0.1);
+        assert_eq!(hydro.inertia().unwrap()[(2, 2)], 3.0);
+        assert!(serde_yaml::from_str::<LinkHydrodynamics>("inertia: [[1.0, 0.0]]").is_err());
+    }
+}
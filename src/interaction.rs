@@ -0,0 +1,330 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::{Isometry3, Point3, Vector3, Vector6};
+use serde::Deserialize;
+
+use crate::hull::equivalent_radius;
+use crate::Config;
+
+fn default_diameter() -> f64 {
+    0.076
+}
+
+fn default_thrust_coefficient() -> f64 {
+    0.4
+}
+
+fn default_zero_thrust_advance_ratio() -> f64 {
+    0.8
+}
+
+fn default_jet_spread() -> f64 {
+    0.1
+}
+
+fn default_jet_length() -> f64 {
+    1.0
+}
+
+fn default_impingement_loss() -> f64 {
+    0.5
+}
+
+/// Thrust losses of one thruster from its jet hitting the other links, and from the water flowing into its
+/// propeller. The jet leaves the propeller disk against the thrust as a cone of half angle `jet_spread`, and each
+/// link it meets within `jet_length`, taken as a cylinder, costs `impingement_loss` times the fraction of the jet
+/// width it covers and the sine of the angle between the jet and the link. The inflow is the velocity of the
+/// thruster through the water along the thrust, including the jets of the other thrusters entering its disk, and
+/// the thrust falls linearly with the advance ratio J = V_a / (n D) to nothing at `zero_thrust_advance_ratio`, the
+/// propeller speed n following from the commanded thrust T = K_T rho n^2 D^4.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ThrusterInteraction {
+    /// Propeller diameter D [m].
+    #[serde(default = "default_diameter")]
+    diameter: f64,
+    /// Thrust coefficient K_T in open water at zero advance ratio.
+    #[serde(default = "default_thrust_coefficient")]
+    thrust_coefficient: f64,
+    #[serde(default = "default_zero_thrust_advance_ratio")]
+    zero_thrust_advance_ratio: f64,
+    /// Half angle [rad] of the jet cone.
+    #[serde(default = "default_jet_spread")]
+    jet_spread: f64,
+    /// Distance [m] from the propeller over which the jet interacts with the links and thrusters.
+    #[serde(default = "default_jet_length")]
+    jet_length: f64,
+    /// Fraction of the thrust lost when a link covers the whole jet.
+    #[serde(default = "default_impingement_loss")]
+    impingement_loss: f64,
+}
+
+impl ThrusterInteraction {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.diameter <= 0.0
+            || self.thrust_coefficient <= 0.0
+            || self.zero_thrust_advance_ratio <= 0.0
+        {
+            return Err(
+                "The propeller diameter, thrust coefficient and zero thrust advance ratio must be positive"
+                    .to_string(),
+            );
+        }
+        if self.jet_length < 0.0 {
+            return Err("The jet length must be non-negative".to_string());
+        }
+        if !(0.0..std::f64::consts::FRAC_PI_2).contains(&self.jet_spread) {
+            return Err("The jet spread must be non-negative and below pi/2".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.impingement_loss) {
+            return Err("The impingement loss must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Radius [m] of the jet at the distance `s` [m] from the propeller.
+    fn jet_radius(&self, s: f64) -> f64 {
+        self.diameter / 2.0 + s * self.jet_spread.tan()
+    }
+}
+
+/// Thruster in the world frame, with the unit thrust direction and the velocity of the thruster through the water.
+#[derive(Debug, Clone)]
+pub struct ThrusterState {
+    /// Link the thruster is mounted on (0-based).
+    pub link: usize,
+    pub position: Vector3<f64>,
+    pub direction: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+}
+
+/// Link hull as a cylinder in the world frame.
+#[derive(Debug, Clone)]
+pub struct LinkCylinder {
+    pub center: Vector3<f64>,
+    /// Unit axis of the cylinder.
+    pub axis: Vector3<f64>,
+    pub half_length: f64,
+    pub radius: f64,
+}
+
+/// Thrusters and link cylinders in the current configuration, from the link poses and the velocities of the links
+/// relative to the water, expressed in the link frames.
+pub fn interaction_geometry(
+    cfg: &Config,
+    link_poses: &[Isometry3<f64>],
+    relative_twists: &[Vector6<f64>],
+) -> (Vec<ThrusterState>, Vec<LinkCylinder>) {
+    let thrusters = (0..cfg.thruster_dirs.len())
+        .map(|i| {
+            let link = cfg.thruster_parents[i] as usize - 1;
+            let pose = &link_poses[link];
+            let offset = cfg.thruster_pos_offsets[i];
+            let twist = &relative_twists[link];
+            let velocity = twist.fixed_rows::<3>(0) + twist.fixed_rows::<3>(3).cross(&offset);
+            ThrusterState {
+                link,
+                position: (pose * Point3::from(offset)).coords,
+                direction: pose.rotation * cfg.thruster_dirs[i].normalize(),
+                velocity: pose.rotation * velocity,
+            }
+        })
+        .collect();
+    let links = link_poses
+        .iter()
+        .enumerate()
+        .map(|(i, pose)| LinkCylinder {
+            center: (pose * Point3::from(cfg.pos_cob[i])).coords,
+            axis: pose.rotation * Vector3::x(),
+            half_length: cfg.length[i] / 2.0,
+            radius: equivalent_radius(cfg, i),
+        })
+        .collect();
+    (thrusters, links)
+}
+
+/// Delivered thrusts [N] of the commanded `thrusts`, reduced by the impingement of the jets on the links other
+/// than the ones the thrusters are mounted on, and by the inflow into the propellers.
+pub fn effective_thrusts(
+    interactions: &[ThrusterInteraction],
+    thrusters: &[ThrusterState],
+    links: &[LinkCylinder],
+    fluid_density: f64,
+    thrusts: &[f64],
+) -> Vec<f64> {
+    let mut efficiency = vec![1.0; thrusters.len()];
+    // Velocity through the water along the thrust, before the jets of the other thrusters
+    let mut inflow: Vec<f64> = thrusters
+        .iter()
+        .zip(thrusts)
+        .map(|(thruster, thrust)| thrust.signum() * thruster.velocity.dot(&thruster.direction))
+        .collect();
+
+    for (i, (thruster, interaction)) in thrusters.iter().zip(interactions).enumerate() {
+        if thrusts[i] == 0.0 {
+            continue;
+        }
+        let jet = -thrusts[i].signum() * thruster.direction;
+        let area = PI * interaction.diameter.powi(2) / 4.0;
+        let jet_speed = (2.0 * thrusts[i].abs() / (fluid_density * area)).sqrt();
+
+        for (k, link) in links.iter().enumerate() {
+            if k == thruster.link {
+                continue;
+            }
+            let (s, distance) = closest_approach(
+                &thruster.position,
+                &jet,
+                interaction.jet_length,
+                &link.center,
+                &link.axis,
+                link.half_length,
+            );
+            // A link across the jet takes its momentum along the thrust, while the jet runs past a link along it
+            let coverage = coverage(distance, link.radius, interaction.jet_radius(s));
+            let incidence = jet.cross(&link.axis).norm();
+            efficiency[i] *= 1.0 - interaction.impingement_loss * coverage * incidence;
+        }
+
+        // The jet slows down as it widens, and adds to the inflow of the thrusters it enters
+        for (j, other) in thrusters.iter().enumerate() {
+            if j == i {
+                continue;
+            }
+            let (s, distance) = closest_approach(
+                &thruster.position,
+                &jet,
+                interaction.jet_length,
+                &other.position,
+                &other.direction,
+                0.0,
+            );
+            let radius = interaction.jet_radius(s);
+            let coverage = coverage(distance, interactions[j].diameter / 2.0, radius);
+            let speed = jet_speed * interaction.diameter / (2.0 * radius);
+            inflow[j] -= thrusts[j].signum() * coverage * speed * jet.dot(&other.direction);
+        }
+    }
+
+    thrusts
+        .iter()
+        .zip(interactions)
+        .zip(efficiency.iter().zip(&inflow))
+        .map(|((&thrust, interaction), (&efficiency, &inflow))| {
+            let d = interaction.diameter;
+            let n = (thrust.abs() / (interaction.thrust_coefficient * fluid_density * d.powi(4)))
+                .sqrt();
+            let advance_ratio = if n > 0.0 { inflow / (n * d) } else { 0.0 };
+            let advance =
+                (1.0 - advance_ratio / interaction.zero_thrust_advance_ratio).clamp(0.0, 1.0);
+            thrust * efficiency * advance
+        })
+        .collect()
+}
+
+/// Distance `s` along the ray from `origin` in the unit direction `direction`, limited to `length`, at which it
+/// comes closest to the segment of half length `half_length` about `center` along the unit `axis`, and the
+/// distance between them.
+fn closest_approach(
+    origin: &Vector3<f64>,
+    direction: &Vector3<f64>,
+    length: f64,
+    center: &Vector3<f64>,
+    axis: &Vector3<f64>,
+    half_length: f64,
+) -> (f64, f64) {
+    let r = origin - center;
+    let (b, c, f) = (direction.dot(axis), direction.dot(&r), axis.dot(&r));
+    let denom = 1.0 - b * b;
+    let mut s = if denom > 1e-12 {
+        ((b * f - c) / denom).clamp(0.0, length)
+    } else {
+        0.0
+    };
+    let t = b * s + f;
+    if t.abs() > half_length {
+        let t = t.clamp(-half_length, half_length);
+        s = (b * t - c).clamp(0.0, length);
+    }
+    let t = (b * s + f).clamp(-half_length, half_length);
+    (s, (r + s * direction - t * axis).norm())
+}
+
+/// Fraction of the width of a jet of radius `jet_radius` covered by an obstacle of half width `half_width` whose
+/// axis passes `distance` from that of the jet.
+fn coverage(distance: f64, half_width: f64, jet_radius: f64) -> f64 {
+    let overlap =
+        (distance + half_width).min(jet_radius) - (distance - half_width).max(-jet_radius);
+    (overlap / (2.0 * jet_radius)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+
+    #[test]
+    fn test_interaction_losses() {
+        let interaction: ThrusterInteraction = serde_yaml::from_str("{diameter: 0.1}").unwrap();
+        interaction.validate().unwrap();
+        let flat: ThrusterInteraction = serde_yaml::from_str("{jet_spread: 1.6}").unwrap();
+        assert!(flat.validate().is_err());
+        let interactions = vec![interaction; 2];
+        // thrusters mounted on a link other than the ones in the tests
+        let thruster = |x: f64, direction: Vector3<f64>, velocity: Vector3<f64>| ThrusterState {
+            link: 2,
+            position: Vector3::new(x, 0.0, 0.0),
+            direction,
+            velocity,
+        };
+        let link = |x: f64| LinkCylinder {
+            center: Vector3::new(x, 0.0, 0.0),
+            axis: Vector3::y(),
+            half_length: 0.3,
+            radius: 0.2,
+        };
+        let still = Vector3::zeros();
+        let thrust = |thrusters: &[ThrusterState], links: &[LinkCylinder], thrusts: &[f64]| {
+            effective_thrusts(&interactions, thrusters, links, 1026.0, thrusts)
+        };
+
+        // a link across the jet takes the full impingement loss, one behind the propeller or beyond the jet nothing
+        let single = [
+            thruster(0.0, Vector3::x(), still),
+            thruster(0.0, Vector3::z(), still),
+        ];
+        assert_eq!(thrust(&single, &[link(-0.5)], &[10.0, 0.0])[0], 5.0);
+        assert_eq!(
+            thrust(&single, &[link(0.5), link(-1.5)], &[10.0, 0.0])[0],
+            10.0
+        );
+        assert_eq!(thrust(&single, &[link(0.5)], &[-10.0, 0.0])[0], -5.0);
+
+        // moving along the thrust reduces it, down to nothing at the zero thrust advance ratio
+        let n = (10.0 / (0.4 * 1026.0 * 1e-4_f64)).sqrt();
+        let moving = [thruster(0.0, Vector3::x(), Vector3::x() * 0.4 * n * 0.1)];
+        assert!((thrust(&moving, &[], &[10.0])[0] - 5.0).abs() < 1e-9);
+        assert_eq!(thrust(&moving, &[], &[-10.0])[0], -10.0);
+
+        // the jet of the upstream thruster enters the one behind it, but not the other way round
+        let tandem = [
+            thruster(0.0, Vector3::x(), still),
+            thruster(-0.3, Vector3::x(), still),
+        ];
+        let thrusts = thrust(&tandem, &[], &[10.0, 10.0]);
+        assert!(thrusts[0] == 10.0 && thrusts[1] < 10.0 && thrusts[1] > 0.0);
+
+        // the jets clear the links of the straight vehicle at rest
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let conf = multibody.minimal_to_homogenous_configuration(
+            &Isometry3::identity(),
+            &na::SVector::<f64, 8>::zeros(),
+        );
+        let link_poses = multibody.compute_body_configurations(&conf);
+        let (thrusters, links) = interaction_geometry(&cfg, &link_poses, &[Vector6::zeros(); 9]);
+        let interactions = vec![interactions[0].clone(); 12];
+        let thrusts = effective_thrusts(&interactions, &thrusters, &links, 1026.0, &[10.0; 12]);
+        assert!(thrusts.iter().all(|&thrust| thrust == 10.0), "{thrusts:?}");
+    }
+}
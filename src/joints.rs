@@ -0,0 +1,183 @@
+use serde::Deserialize;
+
+/// Velocity [rad/s] over which the Coulomb friction is smoothed, to avoid a discontinuity at zero speed.
+const COULOMB_SMOOTHING_VELOCITY: f64 = 1e-2;
+
+fn default_gear_ratio() -> f64 {
+    1.0
+}
+
+/// Limits, friction and actuator dynamics of a scalar (revolute) joint.
+#[derive(Debug, Deserialize, Clone)]
+pub struct JointConfig {
+    /// Lower joint position limit [rad].
+    #[serde(default)]
+    lower_limit: Option<f64>,
+    /// Upper joint position limit [rad].
+    #[serde(default)]
+    upper_limit: Option<f64>,
+    /// Stiffness of the hard stops at the joint limits [Nm/rad].
+    #[serde(default)]
+    limit_stiffness: f64,
+    /// Damping of the hard stops at the joint limits [Nms/rad].
+    #[serde(default)]
+    limit_damping: f64,
+    /// Viscous friction coefficient [Nms/rad].
+    #[serde(default)]
+    viscous_friction: f64,
+    /// Coulomb friction torque [Nm].
+    #[serde(default)]
+    coulomb_friction: f64,
+    /// Gear ratio between the motor and the joint.
+    #[serde(default = "default_gear_ratio")]
+    gear_ratio: f64,
+    /// Rotor inertia on the motor side of the gear [kg m^2].
+    #[serde(default)]
+    motor_inertia: f64,
+    /// Time constant of the first-order response from commanded to delivered torque [s].
+    /// The commanded torque is applied directly if zero.
+    #[serde(default)]
+    torque_time_constant: f64,
+}
+
+impl Default for JointConfig {
+    fn default() -> Self {
+        JointConfig {
+            lower_limit: None,
+            upper_limit: None,
+            limit_stiffness: 0.0,
+            limit_damping: 0.0,
+            viscous_friction: 0.0,
+            coulomb_friction: 0.0,
+            gear_ratio: default_gear_ratio(),
+            motor_inertia: 0.0,
+            torque_time_constant: 0.0,
+        }
+    }
+}
+
+impl JointConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(lower), Some(upper)) = (self.lower_limit, self.upper_limit) {
+            if lower >= upper {
+                return Err("The lower joint limit must be below the upper limit".to_string());
+            }
+        }
+        if [
+            self.limit_stiffness,
+            self.limit_damping,
+            self.viscous_friction,
+            self.coulomb_friction,
+            self.motor_inertia,
+            self.torque_time_constant,
+        ]
+        .iter()
+        .any(|x| *x < 0.0)
+        {
+            return Err(
+                "The joint stop, friction and motor parameters must be non-negative".to_string(),
+            );
+        }
+        if self.gear_ratio <= 0.0 {
+            return Err("The gear ratio must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Spring-damper torque from the hard stops. The damper only acts while moving into the stop,
+    /// and the stop never pulls the joint back towards the limit.
+    pub fn limit_torque(&self, theta: f64, theta_dot: f64) -> f64 {
+        if let Some(lower) = self.lower_limit {
+            if theta < lower {
+                let tau = self.limit_stiffness * (lower - theta)
+                    - self.limit_damping * theta_dot.min(0.0);
+                return tau.max(0.0);
+            }
+        }
+        if let Some(upper) = self.upper_limit {
+            if theta > upper {
+                let tau = self.limit_stiffness * (upper - theta)
+                    - self.limit_damping * theta_dot.max(0.0);
+                return tau.min(0.0);
+            }
+        }
+        0.0
+    }
+
+    /// Viscous and (smoothed) Coulomb friction torque opposing the joint motion.
+    pub fn friction_torque(&self, theta_dot: f64) -> f64 {
+        -self.viscous_friction * theta_dot
+            - self.coulomb_friction * (theta_dot / COULOMB_SMOOTHING_VELOCITY).tanh()
+    }
+
+    /// Motor inertia reflected through the gear to the joint side.
+    pub fn reflected_inertia(&self) -> f64 {
+        self.gear_ratio.powi(2) * self.motor_inertia
+    }
+
+    pub fn has_torque_dynamics(&self) -> bool {
+        self.torque_time_constant > 0.0
+    }
+
+    /// Time derivative of the delivered torque `tau` given the commanded torque `tau_cmd`.
+    pub fn torque_rate(&self, tau_cmd: f64, tau: f64) -> f64 {
+        if self.has_torque_dynamics() {
+            (tau_cmd - tau) / self.torque_time_constant
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_torque() {
+        let joint = JointConfig {
+            lower_limit: Some(-1.0),
+            upper_limit: Some(1.0),
+            limit_stiffness: 100.0,
+            limit_damping: 10.0,
+            ..Default::default()
+        };
+        assert_eq!(joint.limit_torque(0.5, 1.0), 0.0);
+        assert!((joint.limit_torque(1.1, 1.0) + 20.0).abs() < 1e-9);
+        assert!((joint.limit_torque(-1.1, -1.0) - 20.0).abs() < 1e-9);
+        // the damper is inactive when leaving the stop
+        assert!((joint.limit_torque(1.1, -1.0) + 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_joint_dynamics() {
+        let joint = JointConfig {
+            upper_limit: Some(1.0),
+            limit_stiffness: 100.0,
+            limit_damping: 20.0,
+            torque_time_constant: 0.1,
+            ..Default::default()
+        };
+        joint.validate().unwrap();
+        assert!(JointConfig {
+            lower_limit: Some(1.0),
+            ..joint.clone()
+        }
+        .validate()
+        .is_err());
+
+        // a unit inertia driven by a step in the commanded torque: the delivered torque rises with the time
+        // constant, and the stop holds the joint where it balances the torque
+        let (mut theta, mut theta_dot, mut tau) = (0.0, 0.0, 0.0);
+        let dt = 1e-4;
+        for n in 0..100000 {
+            if n == 1000 {
+                assert!((tau - 5.0 * (1.0 - (-1.0_f64).exp())).abs() < 1e-2);
+            }
+            tau += dt * joint.torque_rate(5.0, tau);
+            theta_dot += dt * (tau + joint.limit_torque(theta, theta_dot));
+            theta += dt * theta_dot;
+        }
+        assert!((theta - 1.05).abs() < 1e-6 && theta_dot.abs() < 1e-6);
+    }
+}
@@ -1,4 +1,5 @@
 // use core::num;
+#![allow(clippy::toplevel_ref_arg)] // triggered by nalgebra's `stack!` macro
 use std::f64::consts::PI;
 
 use multibody_dynamics::multibody::{Axis, JointType};
@@ -9,19 +10,81 @@ use multibody_dynamics::multibody::MultiBody;
 
 extern crate nalgebra as na;
 use na::{
-    stack, vector, Isometry3, Matrix3, Matrix6, Quaternion, SMatrix, SVector, Translation3,
-    UnitQuaternion, Vector3, Vector4, Vector6,
+    vector, Isometry3, Matrix3, Matrix6, Point3, Quaternion, SMatrix, SVector, Translation3,
+    UnitQuaternion, Vector2, Vector3, Vector4, Vector6,
 };
 
+mod allocation;
+mod control;
+mod cpg;
+mod current;
+mod energy;
+mod environment;
+mod estimator;
+mod faults;
+mod gait;
+mod guidance;
+mod hull;
+mod hydrodynamics;
+mod interaction;
+mod joints;
+mod mission;
+mod mpc;
+mod payload;
+mod pid;
+mod qp;
+mod quadrature;
+mod random;
+mod reference;
+mod robust;
+mod sensors;
+mod tether;
 mod utils;
+mod waves;
+use crate::allocation::AllocationAnalysisConfig;
+use crate::control::ControllerConfig;
+use crate::cpg::{Cpg, CpgConfig, CpgState};
+use crate::current::{CurrentConfig, CurrentField};
+use crate::energy::EnergyConfig;
+use crate::environment::EnvironmentConfig;
+use crate::estimator::{Estimator, EstimatorConfig, NavState};
+use crate::faults::{FaultConfig, Faults};
+use crate::gait::GaitConfig;
+use crate::guidance::{Guidance, GuidanceConfig};
+use crate::hull::*;
+use crate::hydrodynamics::LinkHydrodynamics;
+use crate::interaction::{effective_thrusts, interaction_geometry, ThrusterInteraction};
+use crate::joints::JointConfig;
+use crate::mission::{Mission, MissionConfig};
+use crate::mpc::Mpc;
+use crate::payload::{PayloadConfig, ToolWrenchConfig};
+use crate::quadrature::Quadrature;
+use crate::reference::{Reference, ReferenceConfig};
+use crate::robust::ControllerState;
+use crate::sensors::{LinkKinematics, SensorConfig, Sensors};
+use crate::tether::TetherConfig;
 use crate::utils::*;
+use crate::waves::{WaveConfig, WaveField};
 
+use ode_solvers::dop_shared::{OutputType, Stats};
 use ode_solvers::*;
 
 use std::{fs::File, io::BufWriter, io::Write, path::Path};
 
-type State = SVector<f64, 43>;
+type State = SVector<f64, { CPG_STATES + cpg::NUM_STATES }>;
 type Time = f64;
+/// Number of links of the multibody, the base included.
+const NUM_LINKS: usize = 9;
+/// Index of the first tether state, following the vehicle states.
+const TETHER_STATES: usize = 54;
+/// Index of the first controller state, following the tether states.
+const CONTROLLER_STATES: usize = TETHER_STATES + tether::NUM_STATES;
+/// Index of the first oscillator state, following the controller states.
+const CPG_STATES: usize = CONTROLLER_STATES + robust::NUM_STATES;
+
+fn cpg_states(y: &State) -> CpgState {
+    y.fixed_rows::<{ cpg::NUM_STATES }>(CPG_STATES).into()
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 enum SerdeAxis {
@@ -63,8 +126,14 @@ impl From<SerdeJointType> for JointType {
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct Config {
     sim_time: f64,
+    /// Sample time [s] of the logged states and of the discrete-time subsystems such as the sensors.
+    #[serde(default = "default_sample_time")]
+    sample_time: f64,
     gravity: Vector3<f64>,
     dragcoeffs: Vec<Vector6<f64>>,
+    /// Quadrature rule used to integrate the cross-flow drag along each link. Defaults to the trapezoid rule with 9 partitions.
+    #[serde(default)]
+    drag_quadrature: Vec<Quadrature>,
     #[serde(deserialize_with = "vec_joint_type")]
     joint_types: Vec<JointType>,
     #[serde(default)]
@@ -82,19 +151,250 @@ pub struct Config {
     thruster_pos_offsets: Vec<Vector3<f64>>,
     thruster_dirs: Vec<Vector3<f64>>,
     thruster_parents: Vec<u16>,
+    /// Lower and upper thrust [N] of every thruster. The allocated thrusts are not limited if omitted.
+    #[serde(default)]
+    thrust_limits: Option<Vector2<f64>>,
+    /// Thrust losses of each thruster from its jet hitting the other links and from the inflow into its propeller,
+    /// in the same order as `thruster_dirs`. The thrusters deliver the commanded thrusts if empty.
+    #[serde(default)]
+    thruster_interactions: Vec<ThrusterInteraction>,
     #[serde(default)]
     added_mass_coeffs: Vec<Option<f64>>,
-    // fluid_added_mass: Vec<Matrix6<f64>>,
     added_alpha: Vec<f64>,
+    /// User-supplied added mass, inertia and damping matrices of each link, overriding the analytical models.
+    #[serde(default)]
+    hydrodynamics: Vec<LinkHydrodynamics>,
+    /// Power models for the thrusters and joint motors, and the battery. Energy is not tracked if omitted.
+    #[serde(default)]
+    energy: Option<EnergyConfig>,
+    /// Hull profile of each link. Links are uniform cylinders of `radius` and `length` if empty.
+    #[serde(default)]
+    hull_profiles: Vec<HullProfile>,
+    /// Limits, friction and motor dynamics of each revolute joint. Ideal joints are assumed if empty.
+    #[serde(default)]
+    joints: Vec<JointConfig>,
+    /// Regular or irregular waves. The water is assumed to be quiescent if omitted.
+    #[serde(default)]
+    waves: Option<WaveConfig>,
+    /// Spatially varying ocean current. The water is assumed to be at rest if omitted.
+    #[serde(default)]
+    current: Option<CurrentConfig>,
+    /// Seabed and obstacles in contact with the links. The vehicle moves in open water if omitted.
+    #[serde(default)]
+    environment: Option<EnvironmentConfig>,
+    /// Umbilical cable from a fixed anchor to one of the links. The vehicle is free-swimming if omitted.
+    #[serde(default)]
+    tether: Option<TetherConfig>,
+    /// Payloads attached to the links at runtime, merged into the link inertia and hydrostatics while attached.
+    #[serde(default)]
+    payloads: Vec<PayloadConfig>,
+    /// External tool wrenches applied at points on the links.
+    #[serde(default)]
+    tools: Vec<ToolWrenchConfig>,
+    /// Controller of the base and joints. Defaults to PID control of the base pose and joint angles.
+    #[serde(default)]
+    controller: ControllerConfig,
+    /// Reference trajectory of the base and joint controllers. Defaults to holding the base at the origin.
+    #[serde(default)]
+    reference: ReferenceConfig,
+    /// Line-of-sight guidance along a path, replacing the base pose and velocity of the reference.
+    #[serde(default)]
+    guidance: Option<GuidanceConfig>,
+    /// Sequence of tasks replacing the reference, starting from the reference at the start time.
+    #[serde(default)]
+    mission: Option<MissionConfig>,
+    /// Swimming gait replacing the joint references.
+    #[serde(default)]
+    gait: Option<GaitConfig>,
+    /// Oscillator network replacing the joint references, as an alternative to the gait.
+    #[serde(default)]
+    cpg: Option<CpgConfig>,
+    /// Actuator faults injected during the run.
+    #[serde(default)]
+    faults: Option<FaultConfig>,
+    /// Sweep of joint configurations analysing the thruster configuration matrix, run instead of the simulation.
+    #[serde(default)]
+    allocation_analysis: Option<AllocationAnalysisConfig>,
+    /// Simulated navigation sensors. No measurements are generated if omitted.
+    #[serde(default)]
+    sensors: Option<SensorConfig>,
+    /// Error-state Kalman filter fed by the sensors. Requires the sensors, including an IMU on the base link.
+    #[serde(default)]
+    estimator: Option<EstimatorConfig>,
+}
+
+fn default_sample_time() -> f64 {
+    0.01
+}
+
+/// Mass and hydrodynamic properties of each link, computed from the config.
+#[derive(Debug, Clone)]
+pub struct LinkProperties {
+    mass: Vec<f64>,
+    added_mass: Vec<Matrix6<f64>>,
+    rb_mass_rotational: Vec<Matrix3<f64>>,
+    volume: Vec<f64>,
+    /// Centers of gravity and buoyancy, including any attached payloads.
+    pos_com: Vec<Vector3<f64>>,
+    pos_cob: Vec<Vector3<f64>>,
 }
 
 pub struct AIAUV {
     multibody: MultiBody<9, 14>,
     config: Config,
+    links: LinkProperties,
+    waves: Option<WaveField>,
+    current: Option<CurrentField>,
+    sensors: Option<Sensors>,
+    estimator: Option<Estimator>,
+    /// Whether each payload is currently merged into the link properties.
+    payloads_attached: Vec<bool>,
+    /// Model predictive controller, if selected.
+    mpc: Option<Mpc>,
+    guidance: Option<Guidance>,
+    mission: Option<Mission>,
+    cpg: Option<Cpg>,
+    faults: Option<Faults>,
+}
+
+impl AIAUV {
+    /// Computes the pose, twist and twist derivative of every link.
+    fn link_kinematics(&self, t: Time, y: &State) -> Vec<LinkKinematics> {
+        let mut dy = State::zeros();
+        self.system(t, y, &mut dy);
+
+        let quat = UnitQuaternion::from_quaternion(Quaternion::from_parts(
+            y[3],
+            Vector3::new(y[4], y[5], y[6]),
+        ));
+        let configuration_base = Isometry3::from_parts(Translation3::new(y[0], y[1], y[2]), quat);
+        let theta: SVector<f64, 8> = y.fixed_rows::<8>(7).into();
+        let zeta: SVector<f64, 14> = y.fixed_rows::<14>(15).into();
+        let zeta_dot: SVector<f64, 14> = dy.fixed_rows::<14>(15).into();
+
+        let conf = self
+            .multibody
+            .minimal_to_homogenous_configuration(&configuration_base, &theta);
+        let jacs = self.multibody.compute_jacobians(&conf);
+        let jac_derivs = self
+            .multibody
+            .compute_jacobian_derivatives(&jacs, &conf, &zeta);
+        let poses = self.multibody.compute_body_configurations(&conf);
+
+        poses
+            .into_iter()
+            .enumerate()
+            .map(|(i, pose)| LinkKinematics {
+                pose: configuration_base * pose,
+                twist: jacs[i] * zeta,
+                twist_dot: jacs[i] * zeta_dot + jac_derivs[i] * zeta,
+            })
+            .collect()
+    }
+
+    /// Rebuilds the link properties and the multibody model when payloads are attached or detached.
+    fn update_payloads(&mut self, t: Time) {
+        let attached: Vec<bool> = self
+            .config
+            .payloads
+            .iter()
+            .map(|payload| payload.is_attached(t))
+            .collect();
+        if attached == self.payloads_attached {
+            return;
+        }
+        self.links = comp_link_properties(&self.config);
+        for payload in self.config.payloads.iter().filter(|p| p.is_attached(t)) {
+            payload.merge_into(&mut self.links);
+        }
+        self.multibody = setup_aiauv(&self.config, &self.links);
+        self.payloads_attached = attached;
+    }
+
+    /// State fed to the controller: the true state, or the estimate when navigating in the loop.
+    fn nav_state(&self, y: &State) -> NavState {
+        match &self.estimator {
+            Some(estimator) if estimator.use_in_controller() => estimator.estimate(),
+            _ => NavState::from_state(y),
+        }
+    }
+
+    /// Reference of the controllers at time `t`, from the mission if any, with the base following the
+    /// guidance and the joints the gait or the oscillators if enabled. The oscillators are extrapolated from their
+    /// states in `y` at time `now`.
+    fn reference(&self, t: Time, y: &State, now: Time) -> Reference {
+        let reference = match (&self.mission, &self.guidance) {
+            (Some(mission), _) => mission.reference(t),
+            (None, Some(guidance)) => guidance.reference(t, self.config.reference.at(t)),
+            (None, None) => self.config.reference.at(t),
+        };
+        match (&self.config.gait, &self.cpg) {
+            (Some(gait), _) => gait.apply(t, &self.config, reference),
+            (None, Some(cpg)) => cpg.apply(&cpg_states(y), t - now, reference),
+            (None, None) => reference,
+        }
+    }
+
+    /// Runs the discrete-time subsystems at the sample time `t`, and resets the PID integral states when due.
+    fn update(&mut self, t: Time, y: &mut State) {
+        self.update_payloads(t);
+        if self.sensors.is_some() {
+            let links = self.link_kinematics(t, y);
+            let theta = y.fixed_rows::<8>(7).into();
+            let gravity = self.config.gravity;
+            if let Some(sensors) = &mut self.sensors {
+                let measurements = sensors.sample(t, &links, &theta, &gravity);
+                if let Some(estimator) = &mut self.estimator {
+                    for m in &measurements {
+                        estimator.process(m);
+                    }
+                    estimator.predict(t);
+                    estimator.log_error(t, &NavState::from_state(y));
+                }
+            }
+        }
+        let nav = self.nav_state(y);
+        if let Some(guidance) = &mut self.guidance {
+            guidance.update(t, &nav);
+        }
+        if let Some(mission) = &mut self.mission {
+            mission.update(t, &nav);
+        }
+        if let Some(cpg) = &mut self.cpg {
+            cpg.update(t);
+        }
+        if let Some(faults) = &mut self.faults {
+            faults.update(t, &y.fixed_rows::<8>(7).into());
+        }
+        if let Some(mut mpc) = self.mpc.take() {
+            if mpc.is_due(t) {
+                mpc.step(t, &self.multibody, &self.config, &nav, &|t_ref| {
+                    self.reference(t_ref, y, t)
+                });
+            }
+            self.mpc = Some(mpc);
+        }
+        if let ControllerConfig::Pid(pid) = &self.config.controller {
+            if pid.is_reset_due(t, self.config.sample_time) {
+                let reference = self.reference(t, y, t);
+                let integrals = y.fixed_rows::<14>(29).into();
+                let (tau, _) = pid.control(&nav, &reference, &integrals);
+                y.fixed_rows_mut::<14>(29)
+                    .copy_from(&pid.bumpless_integrals(&nav, &reference, &tau));
+            }
+        }
+    }
+}
+
+/// Lets the solver borrow the system, so that it can be updated between the sample times.
+impl ode_solvers::System<f64, State> for &AIAUV {
+    fn system(&self, t: Time, y: &State, dy: &mut State) {
+        (**self).system(t, y, dy)
+    }
 }
 
 impl ode_solvers::System<f64, State> for AIAUV {
-    fn system(&self, _t: Time, y: &State, dy: &mut State) {
+    fn system(&self, t: Time, y: &State, dy: &mut State) {
         let quat = UnitQuaternion::from_quaternion(Quaternion::from_parts(
             y[3],
             Vector3::new(y[4], y[5], y[6]),
@@ -104,66 +404,20 @@ impl ode_solvers::System<f64, State> for AIAUV {
 
         let theta = y.fixed_rows::<8>(7).into(); // joint angles
         let zeta: SVector<f64, 14> = y.fixed_rows::<14>(15).into(); // joint velocities
-        let z_b = y.fixed_rows::<6>(29); // integral state
-        let z_j = y.fixed_rows::<8>(35); // integral theta state
+        let integrals: SVector<f64, 14> = y.fixed_rows::<14>(29).into(); // PID integral states (base, joints)
+        let energy = y.fixed_rows::<3>(43); // consumed energy [J] (thrusters, joints, hotel load)
+        let joint_torque_act = y.fixed_rows::<8>(46); // delivered joint motor torques
+        let controller_states: ControllerState = y
+            .fixed_rows::<{ robust::NUM_STATES }>(CONTROLLER_STATES)
+            .into();
 
-        let nu_b = zeta.fixed_rows::<6>(0); // base velocities
         let theta_dot = zeta.fixed_rows::<8>(6); // joint velocities
         let lin_vel_current = Vector3::<f64>::zeros();
         let lin_accel_current = Vector3::<f64>::zeros();
         // let eta: SVector<f64, 14>;
 
-        let pos_d = Vector3::<f64>::zeros();
-        let quat_d = UnitQuaternion::identity();
-
-        let quat_e = quat_d.inverse() * quat;
-
-        let pos_e = quat.inverse() * (pos - pos_d);
-
-        let k_p_b: Vector6<f64> = 1.0 * Vector6::new(70.0, 70.0, 70.0, 100.0, 500.0, 500.0);
-        let k_i_b: Vector6<f64> = 0.01 * Vector6::new(10.0, 10.0, 10.0, 10.0, 20.0, 20.0);
-        let k_d_b: Vector6<f64> = 0.2 * Vector6::new(100.0, 100.0, 100.0, 50.0, 150.0, 150.0);
-
-        let k_p_j: SVector<f64, 8> =
-            0.5 * vector![250.0, 250.0, 500.0, 500.0, 500.0, 500.0, 250.0, 250.0];
-        let k_i_j: SVector<f64, 8> = 0.01 * vector![10.0, 10.0, 20.0, 20.0, 20.0, 20.0, 10.0, 10.0];
-        let k_d_j: SVector<f64, 8> =
-            0.1 * vector![100.0, 100.0, 200.0, 200.0, 200.0, 100.0, 100.0, 100.0];
-
-        let theta_d = SVector::<f64, 8>::from_vec(vec![
-            PI / 4.0,
-            0.0,
-            PI / 4.0,
-            0.0,
-            PI / 4.0,
-            0.0,
-            PI / 4.0,
-            0.0,
-        ]);
-
-        let theta_dotd = SVector::<f64, 8>::zeros();
-
-        let config_err = stack![pos_e; quat_e.vector()];
-
-        let mut f_pid_b = -k_p_b.component_mul(&config_err)
-            - k_i_b.component_mul(&z_b)
-            - k_d_b.component_mul(&nu_b);
-
-        let f_pid_b_max = vector![100.0, 100.0, 100.0, 100.0, 100.0, 100.0];
-
-        f_pid_b = f_pid_b.zip_map(&f_pid_b_max, |val, max| val.clamp(-max, max));
-
-        let theta_e = theta - theta_d;
-        let theta_e_dot = theta_dot - theta_dotd;
-
-        let mut f_pid_joint_torque: SVector<f64, 8> = -k_p_j.component_mul(&theta_e)
-            - k_i_j.component_mul(&z_j)
-            - k_d_j.component_mul(&theta_e_dot);
-
-        let f_pid_j_max = vector![80.0, 80.0, 80.0, 80.0, 80.0, 80.0, 80.0, 80.0];
-
-        f_pid_joint_torque =
-            f_pid_joint_torque.zip_map(&f_pid_j_max, |val, max| val.clamp(-max, max));
+        let reference = self.reference(t, y, t);
+        let nav = self.nav_state(y);
 
         // let joint_torque = -kp * (theta - theta_d) - kd * (theta_dot - theta_dotd);
         // let num_thrusters = self.config.thruster_dirs.len();
@@ -177,20 +431,223 @@ impl ode_solvers::System<f64, State> for AIAUV {
             .minimal_to_homogenous_configuration(&configuration_base, &theta);
 
         let jacs = self.multibody.compute_jacobians(&conf);
-        let tcm = comp_tcm::<14, 12>(&self.config, &jacs);
-        let tcm_tot = stack![
-            tcm,
-            stack![SMatrix::<f64, 6, 8>::zeros();
-        SMatrix::<f64, 8, 8>::identity()]
-        ];
+        let tcm_tot = comp_tcm_tot(&self.config, &jacs);
+
+        // Once faults are known, the output is reallocated over the remaining actuators
+        let (tcm_pinv, stuck) = self
+            .faults
+            .as_ref()
+            .and_then(|faults| faults.allocation(t, &tcm_tot))
+            .unwrap_or_else(|| {
+                let tcm_pinv =
+                    tcm_tot.transpose() * (tcm_tot * tcm_tot.transpose()).try_inverse().unwrap();
+                (tcm_pinv, SVector::zeros())
+            });
+        let allocate = |tau: SVector<f64, 14>| tcm_pinv * (tau - tcm_tot * stuck) + stuck;
+        let mut controller_states_dot = ControllerState::zeros();
+        let mut tau_pid = SVector::<f64, 14>::zeros();
+        let mut u = match &self.config.controller {
+            ControllerConfig::Pid(pid) => {
+                let (tau, tau_unsaturated) = pid.control(&nav, &reference, &integrals);
+                tau_pid = tau_unsaturated;
+                allocate(tau)
+            }
+            ControllerConfig::TaskSpace(task) => {
+                allocate(task.control(&self.multibody, &self.config, &conf, &jacs, &nav))
+            }
+            ControllerConfig::ComputedTorque(computed_torque) => {
+                let tau =
+                    computed_torque.control(&self.multibody, &self.config, &conf, &nav, &reference);
+                allocate(tau)
+            }
+            // The MPC commands the thrusters and joint motors directly, at its own sample times
+            ControllerConfig::Mpc(_) => self.mpc.as_ref().map_or(SVector::zeros(), Mpc::command),
+            ControllerConfig::SuperTwisting(twisting) => {
+                let (tau, states_dot) = twisting.control(
+                    &self.multibody,
+                    &self.config,
+                    &conf,
+                    &nav,
+                    &reference,
+                    &controller_states,
+                );
+                controller_states_dot = states_dot;
+                allocate(tau)
+            }
+            ControllerConfig::Adaptive(adaptive) => {
+                let (tau, states_dot) = adaptive.control(
+                    &self.multibody,
+                    &self.config,
+                    &conf,
+                    &nav,
+                    &reference,
+                    &controller_states,
+                );
+                controller_states_dot = states_dot;
+                allocate(tau)
+            }
+        };
+
+        if let Some(limits) = &self.config.thrust_limits {
+            u.fixed_rows_mut::<12>(0)
+                .apply(|thrust| *thrust = thrust.clamp(limits[0], limits[1]));
+        }
+
+        let idle = self.mission.as_ref().is_some_and(Mission::is_idle);
+        if idle {
+            u.fill(0.0);
+        }
+
+        let thrusters_off = matches!(&self.config.gait, Some(gait) if gait.thrusters_off())
+            || matches!(&self.config.cpg, Some(cpg) if cpg.thrusters_off());
+        if thrusters_off {
+            u.fixed_rows_mut::<12>(0).fill(0.0);
+        }
+
+        let mut soc = 1.0;
+        if let Some(energy_cfg) = &self.config.energy {
+            soc = energy_cfg.battery.soc(energy.sum());
+            if energy_cfg.battery.is_depleted(soc) {
+                u.fill(0.0);
+            }
+        }
+
+        // The faulty actuators act on the commands, and the brakes of the locked joints add passive torques
+        let mut joint_torque_passive = SVector::<f64, 8>::zeros();
+        if let Some(faults) = &self.faults {
+            faults.actuate(&mut u, &mut joint_torque_passive, &theta, &theta_dot.into());
+        }
 
-        let f_pid: SVector<f64, 14> = stack![f_pid_b; f_pid_joint_torque];
-        let tcm_pinv = tcm_tot.transpose() * (tcm_tot * tcm_tot.transpose()).try_inverse().unwrap();
-        let u = tcm_pinv * f_pid;
+        // The PID integrators see the commanded forces that the thrusters and joint motors can realize
+        let mut integrals_dot = match &self.config.controller {
+            ControllerConfig::Pid(pid) => {
+                pid.integral_rates(&nav, &reference, &integrals, &tau_pid, &(tcm_tot * u))
+            }
+            _ => SVector::zeros(),
+        };
+        // The integrators hold while the output is switched off, so that the control resumes without a jump
+        if idle {
+            integrals_dot.fill(0.0);
+        }
+        if thrusters_off {
+            integrals_dot.fixed_rows_mut::<6>(0).fill(0.0);
+        }
+
+        // Motor torque response, and passive joint torques from the hard stops and friction
+        let mut joint_torque_dot = SVector::<f64, 8>::zeros();
+        let mut reflected_inertia = SVector::<f64, 14>::zeros();
+        for (i, joint) in self.config.joints.iter().enumerate().take(8) {
+            if joint.has_torque_dynamics() {
+                joint_torque_dot[i] = joint.torque_rate(u[12 + i], joint_torque_act[i]);
+                u[12 + i] = joint_torque_act[i];
+            }
+            joint_torque_passive[i] +=
+                joint.limit_torque(theta[i], theta_dot[i]) + joint.friction_torque(theta_dot[i]);
+            reflected_inertia[6 + i] = joint.reflected_inertia();
+        }
+        let mut power = Vector3::<f64>::zeros();
+        if let Some(energy_cfg) = &self.config.energy {
+            power = energy_cfg.comp_power(
+                u.fixed_rows::<12>(0).as_slice(),
+                u.fixed_rows::<8>(12).as_slice(),
+                theta_dot.as_slice(),
+                soc,
+            );
+        }
 
         // let wrenches = compute_thruster_wrenches::<8>(&self.config, &thrust, None);
-        let eta = tcm_tot * u;
+        let mut eta = tcm_tot * u;
         // eta = f_pid;
+        let mut eta_joints = eta.fixed_rows_mut::<8>(6);
+        eta_joints += joint_torque_passive;
+
+        // Velocity of the surrounding water at each link's center of buoyancy from currents and waves, and the
+        // Froude–Krylov and added mass forces from the wave acceleration, and the contact forces, all in the link frames
+        // The base configuration is not part of `conf`, so the link poses are relative to the base frame
+        let link_poses: Vec<Isometry3<f64>> = self
+            .multibody
+            .compute_body_configurations(&conf)
+            .into_iter()
+            .map(|pose| configuration_base * pose)
+            .collect();
+        let mut flow_vel = vec![Vector6::<f64>::zeros(); 9];
+        let mut external_wrenches = vec![Vector6::<f64>::zeros(); 9];
+        for (i, pose) in link_poses.iter().enumerate() {
+            let p_cob = pose * Point3::from(self.links.pos_cob[i]);
+            let rot_inv = pose.rotation.inverse();
+            let mut flow_vel_i = flow_vel[i].fixed_rows_mut::<3>(0);
+
+            if let Some(current) = &self.current {
+                flow_vel_i += rot_inv * current.velocity(&p_cob.coords);
+            }
+            if let Some(waves) = &self.waves {
+                let (vel, acc) = waves.kinematics(t, &p_cob.coords);
+                flow_vel_i += rot_inv * vel;
+                external_wrenches[i] += froude_krylov_wrench(
+                    &self.links.added_mass[i],
+                    self.config.fluid_density * self.links.volume[i],
+                    &self.links.pos_cob[i],
+                    &(rot_inv * acc),
+                );
+            }
+            if let Some(environment) = &self.config.environment {
+                external_wrenches[i] += environment.contact_wrench(
+                    pose,
+                    &(jacs[i] * zeta),
+                    &self.config.pos_cob[i],
+                    self.config.length[i],
+                    equivalent_radius(&self.config, i),
+                );
+            }
+        }
+
+        // Thrust lost to the jets hitting the other links in the current shape and to the inflow into the propellers
+        if !self.config.thruster_interactions.is_empty() {
+            let relative_twists: Vec<Vector6<f64>> =
+                (0..9).map(|i| jacs[i] * zeta - flow_vel[i]).collect();
+            let (thrusters, links) =
+                interaction_geometry(&self.config, &link_poses, &relative_twists);
+            let thrusts = u.fixed_rows::<12>(0);
+            let delivered = effective_thrusts(
+                &self.config.thruster_interactions,
+                &thrusters,
+                &links,
+                self.config.fluid_density,
+                thrusts.as_slice(),
+            );
+            eta += tcm_tot.fixed_columns::<12>(0)
+                * (SVector::<f64, 12>::from_vec(delivered) - thrusts);
+        }
+
+        // Wrenches from the tools at their points of application
+        for tool in &self.config.tools {
+            external_wrenches[tool.link()] += tool.wrench(t, &link_poses[tool.link()]);
+        }
+
+        // Force from the tether on its link, and the motion of the tether nodes
+        if let Some(tether) = &self.config.tether {
+            let link = tether.link();
+            let (wrench, tether_dot) = tether.dynamics(
+                &y.fixed_rows::<{ tether::NUM_STATES }>(TETHER_STATES).into(),
+                &link_poses[link],
+                &(jacs[link] * zeta),
+                &self.config.gravity,
+                self.config.fluid_density,
+                |p| {
+                    let mut vel = Vector3::zeros();
+                    if let Some(current) = &self.current {
+                        vel += current.velocity(p);
+                    }
+                    if let Some(waves) = &self.waves {
+                        vel += waves.kinematics(t, p).0;
+                    }
+                    vel
+                },
+            );
+            external_wrenches[link] += wrench;
+            dy.fixed_rows_mut::<{ tether::NUM_STATES }>(TETHER_STATES)
+                .copy_from(&tether_dot);
+        }
 
         let cross_flow_drag =
             &|_confs: &[Isometry3<f64>], nu: &[Vector6<f64>]| -> SMatrix<f64, 6, 9> {
@@ -200,25 +657,32 @@ impl ode_solvers::System<f64, State> for AIAUV {
                 //     out.column_mut(i).copy_from(&drag);
                 // }
                 for (i, nu_i) in nu.iter().enumerate().take(9) {
-                    let drag = cross_flow_drag_rb(nu_i, nu_i, &self.config, i);
+                    let drag = link_damping(&self.config, i, &(nu_i - flow_vel[i]));
                     out.column_mut(i).copy_from(&drag);
                 }
                 out
             };
 
-        // let feedforward = self.multibody.generalized_newton_euler(&conf, &zeta, mu_prime, sigma_prime, rigid_body_forces, eta)
-
-        let accel = self.multibody.forward_dynamics_ab(
+        let mut accel = self.multibody.forward_dynamics_ab(
             &conf,
             &zeta,
             cross_flow_drag,
             // &wrenches,
-            &vec![Vector6::<f64>::zeros(); 9],
+            &external_wrenches,
             &eta,
             &lin_vel_current,
             &lin_accel_current,
         );
 
+        // The motor rotor inertias only add to the joint diagonal of the mass matrix: (M + D) a = M a_0
+        if reflected_inertia.iter().any(|j| *j > 0.0) {
+            let mass_matrix = self.multibody.compute_mass_matrix(&conf);
+            accel = (mass_matrix + SMatrix::from_diagonal(&reflected_inertia))
+                .lu()
+                .solve(&(mass_matrix * accel))
+                .unwrap();
+        }
+
         let pos_dot = quat * zeta.fixed_rows::<3>(0);
         let quat_dot = trans_mat_quat_dot(&quat) * zeta.fixed_rows::<3>(3);
 
@@ -226,8 +690,15 @@ impl ode_solvers::System<f64, State> for AIAUV {
         dy.fixed_rows_mut::<4>(3).copy_from(&quat_dot);
         dy.fixed_rows_mut::<8>(7).copy_from(&theta_dot);
         dy.fixed_rows_mut::<14>(15).copy_from(&accel);
-        dy.fixed_rows_mut::<6>(29).copy_from(&config_err);
-        dy.fixed_rows_mut::<8>(35).copy_from(&theta_e);
+        dy.fixed_rows_mut::<14>(29).copy_from(&integrals_dot);
+        dy.fixed_rows_mut::<3>(43).copy_from(&power);
+        dy.fixed_rows_mut::<8>(46).copy_from(&joint_torque_dot);
+        dy.fixed_rows_mut::<{ robust::NUM_STATES }>(CONTROLLER_STATES)
+            .copy_from(&controller_states_dot);
+        if let Some(cpg) = &self.cpg {
+            dy.fixed_rows_mut::<{ cpg::NUM_STATES }>(CPG_STATES)
+                .copy_from(&cpg.derivatives(&cpg_states(y)));
+        }
     }
 }
 
@@ -239,21 +710,17 @@ where
     Ok(v.into_iter().map(|j| j.into()).collect())
 }
 
-fn setup_aiauv(cfg: &Config) -> MultiBody<9, 14> {
+fn comp_link_properties(cfg: &Config) -> LinkProperties {
     let num_bodies = cfg.joint_types.len();
-    let mut offset_matrices = vec![Isometry3::<f64>::identity(); num_bodies];
     let mut added_mass = vec![Matrix6::<f64>::zeros(); num_bodies];
     let mut rb_mass_rotational = vec![Matrix3::<f64>::zeros(); num_bodies];
     let mut volume = vec![0.0; num_bodies];
 
-    let joint_types = cfg.joint_types.clone();
-    let parent = cfg.parents.clone();
-
     let mass = if cfg.mass.is_empty() {
         println!("Mass not specified – assuming a neutrally buoyant vehicle. \nCalculating mass from length, radius and fluid density.");
         let mut mass = vec![0.0; num_bodies];
         for (i, mass_iter) in mass.iter_mut().enumerate().take(num_bodies) {
-            let volume = cfg.length[i] * PI * cfg.radius[i].powi(2);
+            let volume = hull_volume(cfg, i);
             *mass_iter = volume * cfg.fluid_density;
         }
         mass
@@ -262,9 +729,59 @@ fn setup_aiauv(cfg: &Config) -> MultiBody<9, 14> {
     };
 
     for i in 0..num_bodies {
+        let coeff_added = cfg.added_mass_coeffs.get(i).copied().flatten();
+
+        match hull_profile(cfg, i) {
+            HullProfile::Cylinder => {
+                added_mass[i] = slendermasss(
+                    cfg.length[i],
+                    cfg.radius[i],
+                    cfg.fluid_density,
+                    Some(cfg.added_alpha[i]),
+                    coeff_added,
+                );
+                rb_mass_rotational[i] =
+                    comp_rb_mass_rotational(cfg.pos_com[i], cfg.radius[i], cfg.length[i], mass[i]);
+            }
+            _ => {
+                added_mass[i] = hull_added_mass(cfg, i, Some(cfg.added_alpha[i]), coeff_added);
+                rb_mass_rotational[i] = hull_rb_mass_rotational(cfg, i, mass[i]);
+            }
+        }
+
+        volume[i] = hull_volume(cfg, i);
+
+        if let Some(hydro) = cfg.hydrodynamics.get(i) {
+            if let Some(added_mass_i) = hydro.added_mass() {
+                added_mass[i] = added_mass_i;
+            }
+            if let Some(inertia_i) = hydro.inertia() {
+                rb_mass_rotational[i] = inertia_i;
+            }
+        }
+    }
+
+    LinkProperties {
+        mass,
+        added_mass,
+        rb_mass_rotational,
+        volume,
+        pos_com: cfg.pos_com.clone(),
+        pos_cob: cfg.pos_cob.clone(),
+    }
+}
+
+fn setup_aiauv(cfg: &Config, links: &LinkProperties) -> MultiBody<9, 14> {
+    let num_bodies = cfg.joint_types.len();
+    let mut offset_matrices = vec![Isometry3::<f64>::identity(); num_bodies];
+
+    let joint_types = cfg.joint_types.clone();
+    let parent = cfg.parents.clone();
+
+    for (i, offset_matrix) in offset_matrices.iter_mut().enumerate() {
         let pos_offset: Translation3<f64> = cfg.pos_offsets[i].into();
         let roll_pitch_yaw_offsets = cfg.roll_pitch_yaw_offsets[i];
-        offset_matrices[i] = Isometry3::from_parts(
+        *offset_matrix = Isometry3::from_parts(
             pos_offset,
             UnitQuaternion::from_euler_angles(
                 roll_pitch_yaw_offsets[0],
@@ -272,66 +789,190 @@ fn setup_aiauv(cfg: &Config) -> MultiBody<9, 14> {
                 roll_pitch_yaw_offsets[2],
             ),
         );
-
-        if !cfg.added_mass_coeffs.is_empty() {
-            match cfg.added_mass_coeffs[i] {
-                Some(coeff_added) => {
-                    added_mass[i] = slendermasss(
-                        cfg.length[i],
-                        cfg.radius[i],
-                        cfg.fluid_density,
-                        Some(cfg.added_alpha[i]),
-                        Some(coeff_added),
-                    );
-                }
-                None => {
-                    added_mass[i] = slendermasss(
-                        cfg.length[i],
-                        cfg.radius[i],
-                        cfg.fluid_density,
-                        Some(cfg.added_alpha[i]),
-                        None,
-                    );
-                }
-            }
-        } else {
-            added_mass[i] = slendermasss(
-                cfg.length[i],
-                cfg.radius[i],
-                cfg.fluid_density,
-                Some(cfg.added_alpha[i]),
-                None,
-            );
-        }
-
-        rb_mass_rotational[i] =
-            comp_rb_mass_rotational(cfg.pos_com[i], cfg.radius[i], cfg.length[i], mass[i]);
-
-        volume[i] = cfg.length[i] * PI * cfg.radius[i].powi(2);
     }
 
     MultiBody::new(
         offset_matrices,
         None,
-        Some(added_mass),
-        Some(rb_mass_rotational),
+        Some(links.added_mass.clone()),
+        Some(links.rb_mass_rotational.clone()),
         joint_types,
         parent,
         cfg.gravity,
-        Some(cfg.pos_com.clone()),
-        Some(cfg.pos_cob.clone()),
-        Some(mass),
-        Some(volume),
+        Some(links.pos_com.clone()),
+        Some(links.pos_cob.clone()),
+        Some(links.mass.clone()),
+        Some(links.volume.clone()),
         Some(cfg.fluid_density),
     )
     .unwrap()
 }
 
+/// The vehicle of eely_config.yml without any of the optional models, so that the tests do not depend on the
+/// runtime configuration.
+#[cfg(test)]
+fn test_config() -> Config {
+    serde_yaml::from_str(
+        "
+        sim_time: 10.0
+        gravity: [0.0, 0.0, 9.7975]
+        dragcoeffs: [[0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1],
+                     [0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1],
+                     [0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1], [0.2, 0.1, 0.5, 0.1, 0.1, 0.1]]
+        joint_types: [{type: SixDOF}, {type: Revolute, axis: Y}, {type: Revolute, axis: Z}, {type: Revolute, axis: Y},
+                      {type: Revolute, axis: Z}, {type: Revolute, axis: Z}, {type: Revolute, axis: Y},
+                      {type: Revolute, axis: Z}, {type: Revolute, axis: Y}]
+        radius: [0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1]
+        length: [1.618, 0.084, 1.2760, 0.084, 0.8395, 0.084, 1.2760, 0.084, 0.6045]
+        fluid_density: 1026.0
+        parents: [0, 1, 2, 3, 4, 1, 6, 7, 8]
+        pos_com: [[0.0, 0.0, 0.03], [-0.042, 0.0, 0.0], [-0.638, 0.0, 0.03], [-0.042, 0.0, 0.0],
+                  [-0.41975, 0.0, 0.03], [0.042, 0.0, 0.0], [0.638, 0.0, 0.03], [0.042, 0.0, 0.0],
+                  [0.30225, 0.0, 0.03]]
+        pos_cob: [[0.0, 0.0, 0.0], [-0.042, 0.0, 0.0], [-0.638, 0.0, 0.0], [-0.042, 0.0, 0.0], [-0.41975, 0.0, 0.0],
+                  [0.042, 0.0, 0.0], [0.638, 0.0, 0.0], [0.042, 0.0, 0.0], [0.30225, 0.0, 0.0]]
+        pos_offsets: [[0.0, 0.0, 0.0], [-0.809, 0.0, 0.0], [-0.084, 0.0, 0.0], [-1.2760, 0.0, 0.0],
+                      [-0.084, 0.0, 0.0], [0.809, 0.0, 0.0], [0.084, 0.0, 0.0], [1.2760, 0.0, 0.0],
+                      [0.084, 0.0, 0.0]]
+        roll_pitch_yaw_offsets: [[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0],
+                                 [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]]
+        thruster_pos_offsets: [[-0.9115, -0.174, 0.0], [-0.9115, 0.174, 0.0], [-0.8015, 0.0, 0.09],
+                               [-0.8015, 0.0, -0.09], [0.0575, -0.174, 0.0], [0.0575, 0.174, 0.0],
+                               [-0.0525, 0.0, 0.09], [-0.0525, 0.0, -0.09], [0.9115, -0.174, 0.0],
+                               [0.9115, 0.174, 0.0], [0.8015, 0.0, -0.09], [0.8015, 0.0, 0.09]]
+        thruster_dirs: [[1.0, 0.0, -1.0], [1.0, 0.0, 1.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [1.0, 0.0, 0.0],
+                        [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [1.0, 0.0, 1.0], [1.0, 0.0, -1.0],
+                        [0.0, 1.0, 0.0], [0.0, -1.0, 0.0]]
+        thruster_parents: [3, 3, 3, 3, 1, 1, 1, 1, 7, 7, 7, 7]
+        added_alpha: [0.2, 0.2, 0.2, 0.2, 0.2, 0.2, 0.2, 0.2, 0.2]
+        ",
+    )
+    .unwrap()
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let f = std::fs::File::open("eely_config.yml").expect("Could not open file.");
     let cfg: Config = serde_yaml::from_reader(f).expect("Could not parse file.");
 
-    let multibody = setup_aiauv(&cfg);
+    if !cfg.hydrodynamics.is_empty() && cfg.hydrodynamics.len() != NUM_LINKS {
+        return Err("Give the hydrodynamics of either none or all of the links".into());
+    }
+    for (i, hydro) in cfg.hydrodynamics.iter().enumerate() {
+        hydro
+            .validate()
+            .map_err(|e| format!("Invalid hydrodynamics of link {}: {}", i + 1, e))?;
+    }
+
+    if !cfg.joints.is_empty() && cfg.joints.len() != cfg.joint_types.len() - 1 {
+        return Err(
+            "Give the limits, friction and motor of either none or all of the joints".into(),
+        );
+    }
+    for (i, joint) in cfg.joints.iter().enumerate() {
+        joint
+            .validate()
+            .map_err(|e| format!("Invalid joint {}: {}", i + 1, e))?;
+    }
+    if !cfg.drag_quadrature.is_empty() && cfg.drag_quadrature.len() != NUM_LINKS {
+        return Err("Give the drag quadrature of either none or all of the links".into());
+    }
+    if !cfg.hull_profiles.is_empty() && cfg.hull_profiles.len() != NUM_LINKS {
+        return Err("Give the hull profiles of either none or all of the links".into());
+    }
+    for (i, profile) in cfg.hull_profiles.iter().enumerate() {
+        profile
+            .validate(cfg.length[i])
+            .map_err(|e| format!("Invalid hull profile of link {}: {}", i + 1, e))?;
+    }
+    if let Some(energy) = &cfg.energy {
+        energy.validate(cfg.thruster_dirs.len(), cfg.joint_types.len() - 1)?;
+    }
+    if let Some(waves) = &cfg.waves {
+        waves.validate()?;
+    }
+    if let Some(current) = &cfg.current {
+        current.validate()?;
+    }
+    if let Some(sensors) = &cfg.sensors {
+        sensors.validate(NUM_LINKS, cfg.sample_time)?;
+    }
+    if let Some(environment) = &cfg.environment {
+        environment.validate()?;
+    }
+    if let Some(tether) = &cfg.tether {
+        tether.validate(NUM_LINKS)?;
+    }
+    if let Some(guidance) = &cfg.guidance {
+        guidance.validate()?;
+    }
+    if let Some(mission) = &cfg.mission {
+        if cfg.guidance.is_some() {
+            return Err("Use a FollowPath task instead of the guidance in a mission".into());
+        }
+        mission.validate()?;
+    }
+    if let Some(gait) = &cfg.gait {
+        gait.validate()?;
+    }
+    if let Some(cpg) = &cfg.cpg {
+        if cfg.gait.is_some() {
+            return Err("Use either the gait or the oscillators for the joint references".into());
+        }
+        cpg.validate()?;
+    }
+    if let Some(faults) = &cfg.faults {
+        faults.validate(cfg.thruster_dirs.len(), cfg.joint_types.len() - 1)?;
+        if faults.is_aware() && matches!(cfg.controller, ControllerConfig::Mpc(_)) {
+            return Err(
+                "The MPC commands the actuators directly, so its faults cannot be reallocated"
+                    .into(),
+            );
+        }
+    }
+    if let Some(analysis) = &cfg.allocation_analysis {
+        analysis.validate(cfg.joint_types.len() - 1)?;
+    }
+    if !cfg.thruster_interactions.is_empty()
+        && cfg.thruster_interactions.len() != cfg.thruster_dirs.len()
+    {
+        return Err("Give the interaction losses of either none or all of the thrusters".into());
+    }
+    for (i, interaction) in cfg.thruster_interactions.iter().enumerate() {
+        interaction
+            .validate()
+            .map_err(|e| format!("Invalid interaction losses of thruster {}: {}", i + 1, e))?;
+    }
+    if let Some(limits) = &cfg.thrust_limits {
+        if limits[0] >= limits[1] {
+            return Err("The lower thrust limit must be below the upper limit".into());
+        }
+    }
+    match &cfg.controller {
+        ControllerConfig::TaskSpace(task) => task.validate(NUM_LINKS)?,
+        ControllerConfig::Mpc(mpc) => mpc.validate(cfg.sample_time)?,
+        ControllerConfig::Pid(pid) => pid.validate()?,
+        ControllerConfig::SuperTwisting(twisting) => twisting.validate()?,
+        ControllerConfig::Adaptive(adaptive) => adaptive.validate()?,
+        _ => (),
+    }
+    for payload in &cfg.payloads {
+        payload.validate(NUM_LINKS)?;
+    }
+    for tool in &cfg.tools {
+        tool.validate(NUM_LINKS)?;
+    }
+
+    let links = comp_link_properties(&cfg);
+    let multibody = setup_aiauv(&cfg, &links);
+    if let Some(analysis) = &cfg.allocation_analysis {
+        analysis.run(&cfg, &multibody, Path::new("./aiauv_allocation.csv"))?;
+        return Ok(());
+    }
+    let waves = cfg
+        .waves
+        .as_ref()
+        .map(|wave_cfg| WaveField::new(wave_cfg, cfg.gravity.norm()));
+    let current = cfg.current.as_ref().map(CurrentField::new).transpose()?;
 
     // Simulation loop
     use std::time::Instant;
@@ -340,33 +981,186 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let joint_angles = vector![PI / 4.0, 0.0, PI / 4.0, 0.0, PI / 4.0, 0.0, PI / 4.0, 0.0];
     let zeta = SVector::<f64, 14>::repeat(1.0);
 
-    let system = AIAUV {
+    let mut system = AIAUV {
         multibody,
         config: cfg.clone(),
+        links,
+        waves,
+        current,
+        sensors: cfg.sensors.as_ref().map(Sensors::new),
+        estimator: None,
+        payloads_attached: vec![false; cfg.payloads.len()],
+        mpc: match &cfg.controller {
+            ControllerConfig::Mpc(mpc_cfg) => Some(Mpc::new(mpc_cfg)),
+            _ => None,
+        },
+        guidance: cfg.guidance.as_ref().map(Guidance::new),
+        mission: cfg
+            .mission
+            .as_ref()
+            .map(|mission| Mission::new(mission, &cfg.reference.at(0.0))),
+        cpg: cfg.cpg.as_ref().map(|cpg| Cpg::new(cpg, &cfg)),
+        faults: cfg.faults.as_ref().map(Faults::new),
     };
 
     let mut y0 = State::zeros();
     y0.fixed_rows_mut::<4>(3).copy_from(&Vector4::x());
     y0.fixed_rows_mut::<8>(7).copy_from(&joint_angles);
     y0.fixed_rows_mut::<14>(15).copy_from(&zeta);
+    if let Some(cpg) = &system.cpg {
+        y0.fixed_rows_mut::<{ cpg::NUM_STATES }>(CPG_STATES)
+            .copy_from(&cpg.initial_state());
+    }
+    if let Some(tether) = &cfg.tether {
+        let link_pose = system.link_kinematics(0.0, &y0)[tether.link()].pose;
+        y0.fixed_rows_mut::<{ tether::NUM_STATES }>(TETHER_STATES)
+            .copy_from(&tether.initial_state(&link_pose));
+    }
+
+    if let Some(estimator_cfg) = &cfg.estimator {
+        let sensor_cfg = cfg
+            .sensors
+            .as_ref()
+            .ok_or("The estimator needs the sensors to be configured")?;
+        system.estimator = Some(Estimator::new(
+            estimator_cfg,
+            sensor_cfg,
+            &cfg.gravity,
+            &y0,
+        )?);
+    }
 
     // let y0 = State::zeros();
 
     println!("y0: {}", y0);
-    // Create a stepper and run the integration.
-    let mut stepper = Dopri5::new(system, 0.0, cfg.sim_time, 0.01, y0, 1.0e-4, 1.0e-4);
-    // let mut stepper = Rk4::new(system, 0.0, y0, 0.01, cfg.sim_time);
-    let res = stepper.integrate();
+    // Integrate from one sample time to the next, running the discrete-time subsystems in between.
+    let mut times = vec![0.0];
+    let mut states = vec![y0];
+    let mut stats = Stats {
+        num_eval: 0,
+        accepted_steps: 0,
+        rejected_steps: 0,
+    };
+    let mut res = Ok(());
+    let num_samples = (cfg.sim_time / cfg.sample_time).round() as usize;
+    for k in 0..num_samples {
+        let t = k as f64 * cfg.sample_time;
+        let t_next = ((k + 1) as f64 * cfg.sample_time).min(cfg.sim_time);
+        system.update(t, &mut states[k]);
+
+        let mut stepper = Dopri5::from_param(
+            &system,
+            t,
+            t_next,
+            cfg.sample_time,
+            states[k],
+            1.0e-4,
+            1.0e-4,
+            0.9,
+            0.04,
+            0.2,
+            10.0,
+            cfg.sample_time,
+            0.0,
+            100000,
+            1000,
+            OutputType::Sparse,
+        );
+        // let mut stepper = Rk4::new(system, 0.0, y0, 0.01, cfg.sim_time);
+        match stepper.integrate() {
+            Ok(step_stats) => {
+                stats.num_eval += step_stats.num_eval;
+                stats.accepted_steps += step_stats.accepted_steps;
+                stats.rejected_steps += step_stats.rejected_steps;
+            }
+            Err(e) => {
+                res = Err(e);
+                break;
+            }
+        }
+        times.push(t_next);
+        states.push(*stepper.y_out().last().unwrap());
+    }
 
     println!("Time elapsed: {} ms", now.elapsed().as_millis());
 
     match res {
-        Ok(stats) => {
+        Ok(()) => {
             println!("{}", stats);
             let path = Path::new("./aiauv_dopri5.dat");
-            save(stepper.x_out(), stepper.y_out(), path);
+            save(&times, &states, path);
             println!("Results saved in: {:?}", path);
-            println!("{}", stepper.y_out()[stepper.y_out().len() - 1]);
+            println!("{}", states[states.len() - 1]);
+
+            if let Some(sensors) = &system.sensors {
+                let path = Path::new("./aiauv_sensors.dat");
+                sensors.save(path);
+                println!("Sensor measurements saved in: {:?}", path);
+            }
+            if let Some(estimator) = &system.estimator {
+                let path = Path::new("./aiauv_estimator.dat");
+                estimator.save(path);
+                println!("Estimation errors saved in: {:?}", path);
+            }
+
+            if let Some(faults) = &system.faults {
+                let path = Path::new("./aiauv_faults.dat");
+                faults.save(path);
+                println!("Fault events saved in: {:?}", path);
+            }
+
+            if let Some(guidance) = &system.guidance {
+                let path = Path::new("./aiauv_guidance.dat");
+                guidance.save(path);
+                println!("Guidance progress saved in: {:?}", path);
+                match guidance.completion_time() {
+                    Some(t) => println!("Path completed at {:.2} s", t),
+                    None => println!("Path not completed, progress {:.2}", guidance.progress()),
+                }
+                println!(
+                    "Cross-track RMS error: {:.3} m",
+                    guidance.rms_cross_track_error()
+                );
+            } else if let Some(mission) = &system.mission {
+                let path = Path::new("./aiauv_mission.dat");
+                mission.save(path);
+                println!("Mission events saved in: {:?}", path);
+                match (mission.completion_time(), mission.current_task()) {
+                    (Some(t), _) => println!("Mission completed at {:.2} s", t),
+                    (None, Some((i, name))) => {
+                        println!("Mission not completed, in task {} ({})", i + 1, name)
+                    }
+                    (None, None) => (),
+                }
+            } else {
+                let (pos_err, att_err, joint_err) =
+                    cfg.reference.rms_tracking_errors(&times, &states);
+                println!(
+                    "Tracking RMS error: position {:.3} m, attitude {:.3} rad, joints {:.3} rad",
+                    pos_err, att_err, joint_err
+                );
+                let path = format!("./aiauv_tracking_{}.dat", cfg.controller.name());
+                cfg.reference
+                    .save_tracking_errors(&times, &states, Path::new(&path));
+                println!("Tracking errors saved in: {:?}", path);
+            }
+
+            if let Some(energy_cfg) = &cfg.energy {
+                let y_end = &states[states.len() - 1];
+                let energy = y_end.fixed_rows::<3>(43);
+                let soc = energy_cfg.battery.soc(energy.sum());
+                println!(
+                    "Energy consumed [Wh]: thrusters {:.2}, joints {:.2}, hotel load {:.2}",
+                    energy[0] / 3600.0,
+                    energy[1] / 3600.0,
+                    energy[2] / 3600.0
+                );
+                println!("Final state of charge: {:.1} %", 100.0 * soc);
+                println!(
+                    "Estimated remaining endurance: {:.2} h",
+                    energy_cfg.estimate_endurance(cfg.sim_time, energy.sum()) / 3600.0
+                );
+            }
         }
         Err(e) => println!("An error occured: {}", e),
     }
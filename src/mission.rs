@@ -0,0 +1,440 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::{SVector, UnitQuaternion, Vector3};
+use serde::Deserialize;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use crate::estimator::NavState;
+use crate::guidance::{Guidance, GuidanceConfig};
+use crate::reference::Reference;
+
+fn default_speed() -> f64 {
+    0.3
+}
+
+fn default_position_tolerance() -> f64 {
+    0.2
+}
+
+fn default_attitude_tolerance() -> f64 {
+    0.1
+}
+
+fn default_joint_tolerance() -> f64 {
+    0.05
+}
+
+/// Task of a mission. Each task starts from the reference at the end of the previous one, and ends when its
+/// completion criterion is met or after its optional timeout [s].
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum TaskConfig {
+    /// Moves the base in a straight line at `speed` [m/s] to a pose in the world frame, turning to the goal
+    /// attitude on the way. Completes within the tolerances [m, rad] of the goal pose.
+    Goto {
+        position: Vector3<f64>,
+        #[serde(default)]
+        roll_pitch_yaw: Vector3<f64>,
+        #[serde(default = "default_speed")]
+        speed: f64,
+        #[serde(default = "default_position_tolerance")]
+        position_tolerance: f64,
+        #[serde(default = "default_attitude_tolerance")]
+        attitude_tolerance: f64,
+        timeout: Option<f64>,
+    },
+    /// Holds the base pose and joint angles for `duration` [s].
+    Hold { duration: f64, timeout: Option<f64> },
+    /// Moves the joints to `joint_angles` along a smooth profile lasting `duration` [s], holding the base pose.
+    /// Completes when all joints are within `tolerance` [rad] at the end of the profile.
+    ChangeShape {
+        joint_angles: SVector<f64, 8>,
+        duration: f64,
+        #[serde(default = "default_joint_tolerance")]
+        tolerance: f64,
+        timeout: Option<f64>,
+    },
+    /// Follows a path with line-of-sight guidance, holding the joint angles. Completes at the end of the path.
+    FollowPath {
+        #[serde(flatten)]
+        guidance: GuidanceConfig,
+        timeout: Option<f64>,
+    },
+    /// Switches off the thrusters and joint motors for `duration` [s].
+    Idle { duration: f64 },
+}
+
+impl TaskConfig {
+    fn name(&self) -> &'static str {
+        match self {
+            TaskConfig::Goto { .. } => "Goto",
+            TaskConfig::Hold { .. } => "Hold",
+            TaskConfig::ChangeShape { .. } => "ChangeShape",
+            TaskConfig::FollowPath { .. } => "FollowPath",
+            TaskConfig::Idle { .. } => "Idle",
+        }
+    }
+
+    fn timeout(&self) -> Option<f64> {
+        match self {
+            TaskConfig::Goto { timeout, .. }
+            | TaskConfig::Hold { timeout, .. }
+            | TaskConfig::ChangeShape { timeout, .. }
+            | TaskConfig::FollowPath { timeout, .. } => *timeout,
+            TaskConfig::Idle { .. } => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            TaskConfig::Goto {
+                speed,
+                position_tolerance,
+                attitude_tolerance,
+                ..
+            } => {
+                if *speed <= 0.0 || *position_tolerance <= 0.0 || *attitude_tolerance <= 0.0 {
+                    return Err("The speed and tolerances must be positive".to_string());
+                }
+            }
+            TaskConfig::Hold { duration, .. } | TaskConfig::Idle { duration } => {
+                if *duration < 0.0 {
+                    return Err("The duration must be non-negative".to_string());
+                }
+            }
+            TaskConfig::ChangeShape {
+                duration,
+                tolerance,
+                ..
+            } => {
+                if *duration < 0.0 || *tolerance <= 0.0 {
+                    return Err(
+                        "The duration must be non-negative and the tolerance positive".to_string(),
+                    );
+                }
+            }
+            TaskConfig::FollowPath { guidance, .. } => guidance.validate()?,
+        }
+        if self.timeout().is_some_and(|timeout| timeout <= 0.0) {
+            return Err("The timeout must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Sequence of tasks replacing the reference trajectory of the base and joint controllers.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MissionConfig {
+    tasks: Vec<TaskConfig>,
+}
+
+impl MissionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tasks.is_empty() {
+            return Err("The mission needs at least one task".to_string());
+        }
+        for (i, task) in self.tasks.iter().enumerate() {
+            task.validate()
+                .map_err(|e| format!("Invalid task {} ({}): {}", i + 1, task.name(), e))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissionEvent {
+    Started,
+    Completed,
+    TimedOut,
+}
+
+/// Mission state between the sample times, and the logged events.
+pub struct Mission {
+    cfg: MissionConfig,
+    /// Index of the current task, equal to the number of tasks when the mission is over.
+    current: usize,
+    start_time: f64,
+    /// Reference at the start of the current task, held by the tasks that do not move the base or joints.
+    start: Reference,
+    guidance: Option<Guidance>,
+    events: Vec<(f64, usize, MissionEvent)>,
+}
+
+impl Mission {
+    /// Starts the mission from `initial`, the reference at the start time.
+    pub fn new(cfg: &MissionConfig, initial: &Reference) -> Self {
+        Mission {
+            cfg: cfg.clone(),
+            current: 0,
+            start_time: 0.0,
+            start: hold(initial),
+            guidance: None,
+            events: Vec::new(),
+        }
+    }
+
+    fn task(&self) -> Option<&TaskConfig> {
+        self.cfg.tasks.get(self.current)
+    }
+
+    fn start_task(&mut self, t: f64, nav: &NavState) {
+        self.start_time = t;
+        self.guidance = match self.task() {
+            Some(TaskConfig::FollowPath { guidance, .. }) => Some(Guidance::new(guidance)),
+            _ => None,
+        };
+        if let Some(guidance) = &mut self.guidance {
+            guidance.update(t, nav);
+        }
+        if self.current < self.cfg.tasks.len() {
+            self.events.push((t, self.current, MissionEvent::Started));
+        }
+    }
+
+    /// Checks whether the current task is complete at the sample time `t`, and moves on to the next task when it
+    /// is complete or timed out.
+    pub fn update(&mut self, t: f64, nav: &NavState) {
+        if self.events.is_empty() {
+            self.start_task(t, nav);
+        } else if let Some(guidance) = &mut self.guidance {
+            guidance.update(t, nav);
+        }
+        let Some(task) = self.task() else {
+            return;
+        };
+        let elapsed = t - self.start_time;
+        let reference = self.reference(t);
+        let complete = match task {
+            TaskConfig::Goto {
+                position,
+                speed,
+                position_tolerance,
+                attitude_tolerance,
+                ..
+            } => {
+                elapsed * speed >= (position - self.start.pos).norm()
+                    && (nav.pos - reference.pos).norm() < *position_tolerance
+                    && (reference.quat.inverse() * nav.quat).angle() < *attitude_tolerance
+            }
+            TaskConfig::Hold { duration, .. } | TaskConfig::Idle { duration } => {
+                elapsed >= *duration
+            }
+            TaskConfig::ChangeShape {
+                joint_angles,
+                duration,
+                tolerance,
+                ..
+            } => elapsed >= *duration && (nav.theta - joint_angles).amax() < *tolerance,
+            TaskConfig::FollowPath { .. } => self
+                .guidance
+                .as_ref()
+                .is_some_and(|guidance| guidance.completion_time().is_some()),
+        };
+        let timed_out = task.timeout().is_some_and(|timeout| elapsed >= timeout);
+        if complete || timed_out {
+            let event = if complete {
+                MissionEvent::Completed
+            } else {
+                MissionEvent::TimedOut
+            };
+            self.events.push((t, self.current, event));
+            self.start = hold(&reference);
+            self.current += 1;
+            self.start_task(t, nav);
+        }
+    }
+
+    /// Reference of the base and joints at time `t`.
+    pub fn reference(&self, t: f64) -> Reference {
+        let elapsed = t - self.start_time;
+        match self.task() {
+            Some(TaskConfig::Goto {
+                position,
+                roll_pitch_yaw,
+                speed,
+                ..
+            }) => {
+                let distance = (position - self.start.pos).norm();
+                let fraction = if distance > 0.0 {
+                    (elapsed * speed / distance).min(1.0)
+                } else {
+                    1.0
+                };
+                let quat_goal = UnitQuaternion::from_euler_angles(
+                    roll_pitch_yaw[0],
+                    roll_pitch_yaw[1],
+                    roll_pitch_yaw[2],
+                );
+                Reference {
+                    pos: self.start.pos + fraction * (position - self.start.pos),
+                    quat: self
+                        .start
+                        .quat
+                        .try_slerp(&quat_goal, fraction, 1e-9)
+                        .unwrap_or(quat_goal),
+                    vel: if fraction < 1.0 {
+                        *speed * (position - self.start.pos) / distance
+                    } else {
+                        Vector3::zeros()
+                    },
+                    ..self.start.clone()
+                }
+            }
+            Some(TaskConfig::ChangeShape {
+                joint_angles,
+                duration,
+                ..
+            }) => {
+                // cosine profile with zero joint rates at both ends
+                let change = joint_angles - self.start.theta;
+                let (profile, rate, accel) = if elapsed < *duration {
+                    let omega = PI / duration;
+                    let phase = omega * elapsed;
+                    (
+                        0.5 * (1.0 - phase.cos()),
+                        0.5 * omega * phase.sin(),
+                        0.5 * omega.powi(2) * phase.cos(),
+                    )
+                } else {
+                    (1.0, 0.0, 0.0)
+                };
+                Reference {
+                    theta: self.start.theta + profile * change,
+                    theta_dot: rate * change,
+                    theta_ddot: accel * change,
+                    ..self.start.clone()
+                }
+            }
+            Some(TaskConfig::FollowPath { .. }) => match &self.guidance {
+                Some(guidance) => guidance.reference(t, self.start.clone()),
+                None => self.start.clone(),
+            },
+            _ => self.start.clone(),
+        }
+    }
+
+    /// Whether the thrusters and joint motors are switched off.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.task(), Some(TaskConfig::Idle { .. }))
+    }
+
+    /// Time at which the last task ended, if the mission is over.
+    pub fn completion_time(&self) -> Option<f64> {
+        match self.task() {
+            Some(_) => None,
+            None => self.events.last().map(|(t, _, _)| *t),
+        }
+    }
+
+    /// Index and name of the current task, if the mission is not over.
+    pub fn current_task(&self) -> Option<(usize, &'static str)> {
+        self.task().map(|task| (self.current, task.name()))
+    }
+
+    /// Writes the logged events as rows of time, task number (1-based), task type and event.
+    pub fn save(&self, filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let mut buf = BufWriter::new(file);
+        for (t, task, event) in &self.events {
+            buf.write_fmt(format_args!(
+                "{}, {}, {}, {:?}\n",
+                t,
+                task + 1,
+                self.cfg.tasks[*task].name(),
+                event
+            ))
+            .unwrap();
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
+
+/// The pose and joint angles of `reference`, at rest.
+fn hold(reference: &Reference) -> Reference {
+    Reference {
+        vel: Vector3::zeros(),
+        theta_dot: SVector::zeros(),
+        theta_ddot: SVector::zeros(),
+        ..reference.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use na::Vector6;
+
+    /// Runs the mission with a base and joints that follow the reference exactly, except for the joints that
+    /// are stuck at their initial angles when `stuck_joints` is set.
+    fn run(cfg: &MissionConfig, duration: f64, stuck_joints: bool) -> Mission {
+        let initial = ReferenceConfig::default().at(0.0);
+        let mut mission = Mission::new(cfg, &initial);
+        let dt = 0.01;
+        for k in 0..(duration / dt) as usize {
+            let t = k as f64 * dt;
+            let reference = mission.reference(t);
+            let nav = NavState {
+                pos: reference.pos,
+                quat: reference.quat,
+                nu_b: Vector6::zeros(),
+                theta: if stuck_joints {
+                    initial.theta
+                } else {
+                    reference.theta
+                },
+                theta_dot: reference.theta_dot,
+            };
+            mission.update(t, &nav);
+        }
+        mission
+    }
+
+    #[test]
+    fn test_mission_events() {
+        let cfg: MissionConfig = serde_yaml::from_str(
+            "
+            tasks:
+              - {type: Goto, position: [3.0, 4.0, 0.0], roll_pitch_yaw: [0.0, 0.0, 0.9], speed: 0.5}
+              - {type: Hold, duration: 2.0}
+              - {type: ChangeShape, joint_angles: [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], duration: 3.0, timeout: 5.0}
+              - {type: FollowPath, path: {type: Waypoints, waypoints: [[3.0, 4.0, 0.0], [3.0, 8.0, 0.0]]}, surge_speed: 0.5}
+              - {type: Idle, duration: 1.0}
+            ",
+        )
+        .unwrap();
+        cfg.validate().unwrap();
+
+        // the 5 m transit takes 10 s, and the held pose is the goal
+        let mission = run(&cfg, 40.0, false);
+        let events: Vec<(usize, MissionEvent)> =
+            mission.events.iter().map(|(_, i, e)| (*i, *e)).collect();
+        assert_eq!(events.len(), 10);
+        assert!(events
+            .chunks(2)
+            .enumerate()
+            .all(|(i, pair)| pair == [(i, MissionEvent::Started), (i, MissionEvent::Completed)]));
+        assert!((mission.events[1].0 - 10.0).abs() < 0.02);
+        assert!((mission.events[3].0 - 12.0).abs() < 0.02);
+        assert!((mission.events[5].0 - 15.0).abs() < 0.02);
+        assert!(mission.completion_time().is_some() && mission.current_task().is_none());
+        let reference = mission.reference(40.0);
+        assert!((reference.pos - Vector3::new(3.0, 8.0, 0.0)).norm() < 1.0);
+        assert_eq!(reference.theta, SVector::<f64, 8>::zeros());
+
+        // joints that cannot move time the shape change out, and the mission goes on
+        let mission = run(&cfg, 40.0, true);
+        assert_eq!(mission.events[5].2, MissionEvent::TimedOut);
+        assert!((mission.events[5].0 - 17.0).abs() < 0.02);
+        assert_eq!(mission.events[6].2, MissionEvent::Started);
+    }
+}
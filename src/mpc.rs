@@ -0,0 +1,330 @@
+extern crate nalgebra as na;
+
+use multibody_dynamics::multibody::MultiBody;
+use na::{stack, DMatrix, DVector, Isometry3, SMatrix, SVector, Translation3};
+use serde::Deserialize;
+
+use crate::control::{inverse_dynamics, tracking_errors, AttitudeError};
+use crate::estimator::NavState;
+use crate::qp::solve_box_qp;
+use crate::reference::Reference;
+use crate::utils::comp_tcm_tot;
+use crate::Config;
+
+/// Number of actuators: the thrusters followed by the joint motors.
+pub const NUM_INPUTS: usize = 20;
+/// Number of predicted states: the base pose and joint angle errors followed by the velocities.
+const NUM_STATES: usize = 28;
+const MAX_QP_ITER: usize = 50;
+
+fn default_sample_time() -> f64 {
+    0.05
+}
+
+fn default_prediction_step() -> f64 {
+    0.1
+}
+
+fn default_horizon() -> usize {
+    10
+}
+
+fn default_q_pose() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        100.0, 100.0, 100.0, 100.0, 100.0, 100.0, 50.0, 50.0, 50.0, 50.0, 50.0, 50.0, 50.0, 50.0,
+    ])
+}
+
+fn default_q_vel() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+    ])
+}
+
+fn default_r() -> f64 {
+    1e-3
+}
+
+fn default_thrust_min() -> f64 {
+    -40.0
+}
+
+fn default_thrust_max() -> f64 {
+    51.0
+}
+
+fn default_joint_torque_limit() -> f64 {
+    80.0
+}
+
+/// Model predictive control of the base pose and joint angles along the reference trajectory. At each sample
+/// time the dynamics are linearized about the current state, and the thrusts and joint torques over the
+/// horizon are found from a QP with the actuator limits as bounds. The first of them are held until the
+/// next sample time. The base errors are expressed in the base frame.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MpcConfig {
+    /// Time between the solutions [s], a multiple of the simulation sample time.
+    #[serde(default = "default_sample_time")]
+    sample_time: f64,
+    /// Length of each step of the prediction [s].
+    #[serde(default = "default_prediction_step")]
+    prediction_step: f64,
+    /// Number of steps of the prediction.
+    #[serde(default = "default_horizon")]
+    horizon: usize,
+    /// Weights of the base pose errors, in the base frame, and the joint angle errors.
+    #[serde(default = "default_q_pose")]
+    q_pose: SVector<f64, 14>,
+    /// Weights of the base velocity and joint rate errors.
+    #[serde(default = "default_q_vel")]
+    q_vel: SVector<f64, 14>,
+    #[serde(default = "default_r")]
+    r_thrust: f64,
+    #[serde(default = "default_r")]
+    r_joint_torque: f64,
+    #[serde(default = "default_thrust_min")]
+    thrust_min: f64,
+    #[serde(default = "default_thrust_max")]
+    thrust_max: f64,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+    #[serde(default)]
+    attitude_error: AttitudeError,
+}
+
+impl MpcConfig {
+    /// Checks the parameters, and that the MPC is solved at simulation sample times.
+    pub fn validate(&self, sim_sample_time: f64) -> Result<(), String> {
+        if self.sample_time <= 0.0 || self.prediction_step <= 0.0 || self.horizon == 0 {
+            return Err(
+                "The MPC sample time, prediction step and horizon must be positive".to_string(),
+            );
+        }
+        let steps = self.sample_time / sim_sample_time;
+        if steps < 1.0 - 1e-9 || (steps - steps.round()).abs() > 1e-9 * steps {
+            return Err(format!(
+                "The MPC sample time must be a multiple of the simulation sample time {sim_sample_time} s"
+            ));
+        }
+        if self.thrust_min > self.thrust_max || self.joint_torque_limit < 0.0 {
+            return Err("The MPC actuator limits are inconsistent".to_string());
+        }
+        if self.r_thrust <= 0.0 || self.r_joint_torque <= 0.0 {
+            return Err("The MPC actuator weights must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Discrete-time model x_{k+1} = A x_k + B u_k + c of the errors and velocities over one prediction step.
+struct LinearModel {
+    a: DMatrix<f64>,
+    b: DMatrix<f64>,
+    c: DVector<f64>,
+}
+
+/// Model predictive controller run at its sample times, holding its commands in between.
+pub struct Mpc {
+    cfg: MpcConfig,
+    next_time: f64,
+    command: SVector<f64, NUM_INPUTS>,
+    /// Solution at the previous sample time, warm starting the next.
+    solution: DVector<f64>,
+}
+
+impl Mpc {
+    pub fn new(cfg: &MpcConfig) -> Self {
+        Mpc {
+            cfg: cfg.clone(),
+            next_time: 0.0,
+            command: SVector::zeros(),
+            solution: DVector::zeros(NUM_INPUTS * cfg.horizon),
+        }
+    }
+
+    /// Thrusts and joint torques to apply.
+    pub fn command(&self) -> SVector<f64, NUM_INPUTS> {
+        self.command
+    }
+
+    pub fn is_due(&self, t: f64) -> bool {
+        t >= self.next_time - 1e-9
+    }
+
+    /// Linearizes the dynamics about the vehicle state `nav`, and zero-order hold discretizes them. The
+    /// damping, Coriolis and restoring forces are linearized by finite differences of the inverse dynamics.
+    fn linearize(&self, multibody: &MultiBody<9, 14>, cfg: &Config, nav: &NavState) -> LinearModel {
+        let base = Isometry3::from_parts(Translation3::from(nav.pos), nav.quat);
+        let conf = multibody.minimal_to_homogenous_configuration(&base, &nav.theta);
+        let jacs = multibody.compute_jacobians(&conf);
+        let tcm_tot = comp_tcm_tot(cfg, &jacs);
+        let mass_inv = multibody.compute_mass_matrix(&conf).try_inverse().unwrap();
+
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let bias = |zeta: &SVector<f64, 14>| {
+            inverse_dynamics(multibody, cfg, &conf, zeta, zeta, &SVector::zeros())
+        };
+        let bias_0 = bias(&zeta);
+        let mut damping = SMatrix::<f64, 14, 14>::zeros();
+        for j in 0..14 {
+            let delta = 1e-6 * (1.0 + zeta[j].abs());
+            let mut zeta_j = zeta;
+            zeta_j[j] += delta;
+            damping.set_column(j, &((bias(&zeta_j) - bias_0) / delta));
+        }
+
+        // The affine term enters as an extra input held at 1
+        let n = NUM_STATES + NUM_INPUTS + 1;
+        let mut a_c = DMatrix::<f64>::zeros(n, n);
+        a_c.view_mut((0, 14), (14, 14)).fill_with_identity();
+        a_c.view_mut((14, 14), (14, 14))
+            .copy_from(&(-mass_inv * damping));
+        a_c.view_mut((14, NUM_STATES), (14, NUM_INPUTS))
+            .copy_from(&(mass_inv * tcm_tot));
+        a_c.view_mut((14, n - 1), (14, 1))
+            .copy_from(&(-mass_inv * (bias_0 - damping * zeta)));
+        let phi = (a_c * self.cfg.prediction_step).exp();
+
+        LinearModel {
+            a: phi.view((0, 0), (NUM_STATES, NUM_STATES)).into(),
+            b: phi.view((0, NUM_STATES), (NUM_STATES, NUM_INPUTS)).into(),
+            c: phi.view((0, n - 1), (NUM_STATES, 1)).column(0).into(),
+        }
+    }
+
+    /// Solves for the thrusts and joint torques at time `t`, given the vehicle state `nav` and the reference
+    /// trajectory.
+    pub fn step(
+        &mut self,
+        t: f64,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        nav: &NavState,
+        reference_at: &dyn Fn(f64) -> Reference,
+    ) {
+        let n = self.cfg.horizon;
+        let h = self.cfg.prediction_step;
+        let model = self.linearize(multibody, cfg, nav);
+
+        // Reference velocities in the base frame and joint rates
+        let zeta_d = |t: f64| tracking_errors(nav, &reference_at(t), self.cfg.attitude_error).1;
+
+        let (mut err, _, _) = tracking_errors(nav, &reference_at(t), self.cfg.attitude_error);
+        // The attitude errors grow as half the rotation angle, while the model integrates the angular velocity
+        err.fixed_rows_mut::<3>(3).scale_mut(2.0);
+        let x0 = DVector::from_column_slice(stack![err; nav.nu_b; nav.theta_dot].as_slice());
+
+        // Predicted states X = X_free + S U, and their references
+        let mut x_free = DVector::zeros(NUM_STATES * n);
+        let mut x_ref = DVector::zeros(NUM_STATES * n);
+        let mut x = x0;
+        for k in 0..n {
+            let t_k = t + k as f64 * h;
+            x = &model.a * x + &model.c;
+            let mut errors = x.rows_mut(0, 14);
+            errors -= h * zeta_d(t_k + 0.5 * h);
+            x_free.rows_mut(NUM_STATES * k, NUM_STATES).copy_from(&x);
+            x_ref
+                .rows_mut(NUM_STATES * k + 14, 14)
+                .copy_from(&zeta_d(t_k + h));
+        }
+        let mut s = DMatrix::zeros(NUM_STATES * n, NUM_INPUTS * n);
+        let mut a_pow_b = model.b.clone();
+        for d in 0..n {
+            for j in 0..n - d {
+                s.view_mut(
+                    (NUM_STATES * (j + d), NUM_INPUTS * j),
+                    (NUM_STATES, NUM_INPUTS),
+                )
+                .copy_from(&a_pow_b);
+            }
+            a_pow_b = &model.a * a_pow_b;
+        }
+
+        // Cost sum (x_k - x_ref_k)^T Q (x_k - x_ref_k) + u_k^T R u_k, with diagonal weights
+        let q = DVector::from_fn(NUM_STATES * n, |i, _| {
+            let i = i % NUM_STATES;
+            if i < 14 {
+                self.cfg.q_pose[i]
+            } else {
+                self.cfg.q_vel[i - 14]
+            }
+        });
+        let r = DVector::from_fn(NUM_INPUTS * n, |i, _| {
+            if i % NUM_INPUTS < 12 {
+                self.cfg.r_thrust
+            } else {
+                self.cfg.r_joint_torque
+            }
+        });
+        let mut qs = s.clone();
+        for (mut row, q_i) in qs.row_iter_mut().zip(q.iter()) {
+            row *= *q_i;
+        }
+        let hessian = s.transpose() * &qs + DMatrix::from_diagonal(&r);
+        let gradient = qs.transpose() * (x_free - x_ref);
+
+        let (lower, upper): (Vec<f64>, Vec<f64>) = (0..NUM_INPUTS * n)
+            .map(|i| {
+                if i % NUM_INPUTS < 12 {
+                    (self.cfg.thrust_min, self.cfg.thrust_max)
+                } else {
+                    (-self.cfg.joint_torque_limit, self.cfg.joint_torque_limit)
+                }
+            })
+            .unzip();
+        self.solution = solve_box_qp(
+            &hessian,
+            &gradient,
+            &DVector::from_vec(lower),
+            &DVector::from_vec(upper),
+            &self.solution,
+            MAX_QP_ITER,
+        );
+        self.command = self.solution.fixed_rows::<NUM_INPUTS>(0).into();
+        self.next_time = t + self.cfg.sample_time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::comp_tcm;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+    use na::{vector, UnitQuaternion, Vector3, Vector6};
+
+    #[test]
+    fn test_mpc_returns_to_reference() {
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let mpc_cfg: MpcConfig =
+            serde_yaml::from_str("{thrust_min: -20.0, thrust_max: 20.0}").unwrap();
+        assert!(mpc_cfg.validate(0.01).is_ok() && mpc_cfg.validate(0.02).is_err());
+        let mut mpc = Mpc::new(&mpc_cfg);
+
+        // at rest, 1 m ahead of the reference in the surge direction
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let nav = NavState {
+            pos: Vector3::new(1.0, 0.0, 0.0),
+            quat: UnitQuaternion::identity(),
+            nu_b: Vector6::zeros(),
+            theta: vector![quarter, 0.0, quarter, 0.0, quarter, 0.0, quarter, 0.0],
+            theta_dot: SVector::zeros(),
+        };
+        assert!(mpc.is_due(0.0));
+        mpc.step(0.0, &multibody, &cfg, &nav, &|t| cfg.reference.at(t));
+        assert!(!mpc.is_due(0.02) && mpc.is_due(0.05));
+
+        // the thrusts stay within their bounds and push the base back
+        let u = mpc.command();
+        assert!(u
+            .fixed_rows::<12>(0)
+            .iter()
+            .all(|f| (-20.0..=20.0).contains(f)));
+        let base = Isometry3::from_parts(Translation3::from(nav.pos), nav.quat);
+        let conf = multibody.minimal_to_homogenous_configuration(&base, &nav.theta);
+        let tcm = comp_tcm::<14, 12>(&cfg, &multibody.compute_jacobians(&conf));
+        let tau = tcm * u.fixed_rows::<12>(0);
+        assert!(tau[0] < 0.0 && tau.iamax() == 0);
+    }
+}
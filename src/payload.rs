@@ -0,0 +1,357 @@
+extern crate nalgebra as na;
+
+use multibody_dynamics::math_functions::skew;
+use na::{Isometry3, Matrix3, Matrix6, Vector3, Vector6};
+use serde::Deserialize;
+
+use crate::hydrodynamics::{check_symmetric_positive_semi_definite, opt_matrix};
+use crate::LinkProperties;
+
+/// A rigid payload, e.g. a gripped object or a tool, carried by a link between `attach_time` and
+/// `detach_time`. Its mass, buoyancy, inertia and added mass are merged into those of the link. Matrices are
+/// written row by row.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PayloadConfig {
+    /// Link carrying the payload (1-based).
+    link: usize,
+    mass: f64,
+    /// Displaced volume [m^3].
+    #[serde(default)]
+    volume: f64,
+    /// Center of gravity, expressed in the link frame.
+    position: Vector3<f64>,
+    /// Center of buoyancy, expressed in the link frame. Defaults to the center of gravity.
+    #[serde(default)]
+    center_of_buoyancy: Option<Vector3<f64>>,
+    /// Rotational inertia about the center of gravity, in link axes. A point mass is assumed if omitted.
+    #[serde(default, deserialize_with = "opt_matrix")]
+    inertia: Option<Matrix3<f64>>,
+    /// Added mass about the center of buoyancy, in link axes.
+    #[serde(default, deserialize_with = "opt_matrix")]
+    added_mass: Option<Matrix6<f64>>,
+    #[serde(default)]
+    attach_time: f64,
+    /// The payload stays attached until the end of the simulation if omitted.
+    #[serde(default)]
+    detach_time: Option<f64>,
+}
+
+impl PayloadConfig {
+    pub fn validate(&self, num_links: usize) -> Result<(), String> {
+        if self.link == 0 || self.link > num_links {
+            return Err(format!(
+                "The payload link must be between 1 and {}",
+                num_links
+            ));
+        }
+        if !(self.mass >= 0.0 && self.volume >= 0.0)
+            || self.mass.is_infinite()
+            || self.volume.is_infinite()
+        {
+            return Err("The payload mass and volume must be finite and non-negative".to_string());
+        }
+        let points = [Some(self.position), self.center_of_buoyancy];
+        if points
+            .iter()
+            .flatten()
+            .any(|p| p.iter().any(|v| !v.is_finite()))
+        {
+            return Err("The payload centers of gravity and buoyancy must be finite".to_string());
+        }
+        if let Some(inertia) = &self.inertia {
+            check_symmetric_positive_semi_definite(inertia)
+                .map_err(|e| format!("The payload inertia {}", e))?;
+        }
+        if let Some(added_mass) = &self.added_mass {
+            check_symmetric_positive_semi_definite(added_mass)
+                .map_err(|e| format!("The payload added_mass {}", e))?;
+        }
+        if !self.attach_time.is_finite()
+            || self
+                .detach_time
+                .is_some_and(|t_detach| t_detach.is_nan() || t_detach <= self.attach_time)
+        {
+            return Err("The payload must be detached after it is attached".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_attached(&self, t: f64) -> bool {
+        t >= self.attach_time && self.detach_time.is_none_or(|t_detach| t < t_detach)
+    }
+
+    /// Adds the payload to the properties of its link. The rotational inertia and added mass are moved to
+    /// the link origin, and the centers of gravity and buoyancy become the mass and volume weighted means.
+    pub fn merge_into(&self, links: &mut LinkProperties) {
+        let i = self.link - 1;
+        let r_cog = self.position;
+        let r_cob = self.center_of_buoyancy.unwrap_or(self.position);
+
+        let mass = links.mass[i] + self.mass;
+        if mass > 0.0 {
+            links.pos_com[i] = (links.mass[i] * links.pos_com[i] + self.mass * r_cog) / mass;
+        }
+        let volume = links.volume[i] + self.volume;
+        if volume > 0.0 {
+            links.pos_cob[i] = (links.volume[i] * links.pos_cob[i] + self.volume * r_cob) / volume;
+        }
+        links.mass[i] = mass;
+        links.volume[i] = volume;
+
+        // parallel axis theorem
+        links.rb_mass_rotational[i] +=
+            self.inertia.unwrap_or_default() - self.mass * skew(&r_cog) * skew(&r_cog);
+        if let Some(added_mass) = &self.added_mass {
+            links.added_mass[i] += transform_to_origin(added_mass, &r_cob);
+        }
+    }
+}
+
+/// Moves a 6x6 mass matrix about the point `r` to the origin, M_o = H^T M H, where H maps the twist at the
+/// origin to the twist at `r`.
+fn transform_to_origin(mass_matrix: &Matrix6<f64>, r: &Vector3<f64>) -> Matrix6<f64> {
+    let mut h = Matrix6::identity();
+    h.fixed_view_mut::<3, 3>(0, 3).copy_from(&(-skew(r)));
+    h.transpose() * mass_matrix * h
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub enum WrenchFrame {
+    #[default]
+    Link,
+    World,
+}
+
+/// Force and moment of a tool at `time` [s].
+#[derive(Debug, Deserialize, Clone)]
+pub struct WrenchSample {
+    time: f64,
+    #[serde(default)]
+    force: Vector3<f64>,
+    #[serde(default)]
+    moment: Vector3<f64>,
+}
+
+/// External wrench from a tool in contact with its surroundings, e.g. a drill or a manipulated valve,
+/// applied at a point on a link. The force and moment are constant, or interpolated linearly between the
+/// samples of a `profile` and held beyond its first and last samples. They are ramped up after `start_time`
+/// and down before `end_time` over `ramp_time`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ToolWrenchConfig {
+    /// Link carrying the tool (1-based).
+    link: usize,
+    /// Point of application, expressed in the link frame.
+    #[serde(default)]
+    position: Vector3<f64>,
+    /// Frame in which the force and moment are given.
+    #[serde(default)]
+    frame: WrenchFrame,
+    #[serde(default)]
+    force: Vector3<f64>,
+    #[serde(default)]
+    moment: Vector3<f64>,
+    /// Samples of the force and moment in increasing time, replacing the constant force and moment if given.
+    #[serde(default)]
+    profile: Vec<WrenchSample>,
+    #[serde(default)]
+    start_time: f64,
+    /// The wrench is applied until the end of the simulation if omitted.
+    #[serde(default)]
+    end_time: Option<f64>,
+    #[serde(default)]
+    ramp_time: f64,
+}
+
+impl ToolWrenchConfig {
+    pub fn validate(&self, num_links: usize) -> Result<(), String> {
+        if self.link == 0 || self.link > num_links {
+            return Err(format!("The tool link must be between 1 and {}", num_links));
+        }
+        if self.ramp_time < 0.0 {
+            return Err("The tool ramp time must be non-negative".to_string());
+        }
+        if self
+            .end_time
+            .is_some_and(|t_end| t_end.is_nan() || t_end <= self.start_time)
+        {
+            return Err("The tool end time must be after its start time".to_string());
+        }
+        if self.profile.iter().any(|sample| !sample.time.is_finite())
+            || self.profile.windows(2).any(|w| w[1].time <= w[0].time)
+        {
+            return Err("The tool profile times must be finite and increasing".to_string());
+        }
+        Ok(())
+    }
+
+    /// Index of the link carrying the tool.
+    pub fn link(&self) -> usize {
+        self.link - 1
+    }
+
+    /// Scaling of the wrench at time `t`, rising linearly from 0 to 1 over the ramp time.
+    fn scale(&self, t: f64) -> f64 {
+        let ramp = |dt: f64| {
+            if self.ramp_time > 0.0 {
+                (dt / self.ramp_time).clamp(0.0, 1.0)
+            } else if dt >= 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let rise = ramp(t - self.start_time);
+        match self.end_time {
+            Some(t_end) if t >= t_end => 0.0,
+            Some(t_end) => rise.min(ramp(t_end - t)),
+            None => rise,
+        }
+    }
+
+    /// Force and moment at time `t` in the frame of the tool, before the ramps.
+    fn force_moment(&self, t: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let profile = &self.profile;
+        let k = profile.partition_point(|sample| sample.time <= t);
+        match k {
+            _ if profile.is_empty() => (self.force, self.moment),
+            0 => (profile[0].force, profile[0].moment),
+            _ if k == profile.len() => (profile[k - 1].force, profile[k - 1].moment),
+            _ => {
+                let (a, b) = (&profile[k - 1], &profile[k]);
+                let w = (t - a.time) / (b.time - a.time);
+                (a.force.lerp(&b.force, w), a.moment.lerp(&b.moment, w))
+            }
+        }
+    }
+
+    /// Wrench on the link at time `t`, expressed in the link frame.
+    pub fn wrench(&self, t: f64, link_pose: &Isometry3<f64>) -> Vector6<f64> {
+        let scale = self.scale(t);
+        if scale == 0.0 {
+            return Vector6::zeros();
+        }
+        let (force, moment) = self.force_moment(t);
+        let (force, moment) = match self.frame {
+            WrenchFrame::Link => (force, moment),
+            WrenchFrame::World => {
+                let rot_inv = link_pose.rotation.inverse();
+                (rot_inv * force, rot_inv * moment)
+            }
+        };
+        let moment = moment + self.position.cross(&force);
+        scale
+            * Vector6::new(
+                force[0], force[1], force[2], moment[0], moment[1], moment[2],
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multibody_dynamics::math_functions::comp_rb_mass_matrix;
+
+    #[test]
+    fn test_merged_point_mass() {
+        let mut links = LinkProperties {
+            mass: vec![2.0],
+            added_mass: vec![Matrix6::zeros()],
+            rb_mass_rotational: vec![Matrix3::identity()],
+            volume: vec![0.002],
+            pos_com: vec![Vector3::zeros()],
+            pos_cob: vec![Vector3::zeros()],
+        };
+        let payload: PayloadConfig = serde_yaml::from_str(
+            "
+            link: 1
+            mass: 1.0
+            volume: 0.001
+            position: [0.3, 0.0, 0.1]
+            added_mass:
+              - [0.5, 0.0, 0.0, 0.0, 0.0, 0.0]
+              - [0.0, 0.5, 0.0, 0.0, 0.0, 0.0]
+              - [0.0, 0.0, 0.5, 0.0, 0.0, 0.0]
+              - [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+              - [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+              - [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+            detach_time: 2.0
+            ",
+        )
+        .unwrap();
+        assert!(payload.validate(9).is_ok());
+        assert!(payload.is_attached(0.0) && !payload.is_attached(2.0));
+        let early = PayloadConfig {
+            detach_time: Some(0.0),
+            ..payload.clone()
+        };
+        let indefinite = PayloadConfig {
+            inertia: Some(Matrix3::from_diagonal(&Vector3::new(1.0, -1.0, 1.0))),
+            ..payload.clone()
+        };
+        let nan = PayloadConfig {
+            mass: f64::NAN,
+            ..payload.clone()
+        };
+        assert!([early, indefinite, nan]
+            .iter()
+            .all(|p| p.validate(9).is_err()));
+        payload.merge_into(&mut links);
+
+        let r = Vector3::new(0.3, 0.0, 0.1);
+        assert_eq!(links.mass[0], 3.0);
+        assert!((links.pos_com[0] - r / 3.0).norm() < 1e-12);
+        assert!((links.pos_cob[0] - r / 3.0).norm() < 1e-12);
+
+        // the rigid body and added mass of the payload at the origin equal those of point masses at r
+        let rb_mass = comp_rb_mass_matrix(2.0, &Vector3::zeros(), &Matrix3::identity())
+            + comp_rb_mass_matrix(1.0, &r, &(-skew(&r) * skew(&r)));
+        let merged = comp_rb_mass_matrix(
+            links.mass[0],
+            &links.pos_com[0],
+            &links.rb_mass_rotational[0],
+        );
+        assert!((merged - rb_mass).norm() < 1e-12);
+        let added_mass = comp_rb_mass_matrix(0.5, &r, &(-0.5 * skew(&r) * skew(&r)));
+        assert!((links.added_mass[0] - added_mass).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_tool_wrench_ramp() {
+        let tool: ToolWrenchConfig = serde_yaml::from_str(
+            "{link: 9, position: [0.5, 0.0, 0.0], frame: World, force: [0.0, 0.0, 10.0], start_time: 1.0, end_time: 3.0, ramp_time: 0.5}",
+        )
+        .unwrap();
+        let pose = Isometry3::rotation(Vector3::new(0.0, std::f64::consts::FRAC_PI_2, 0.0));
+        assert_eq!(tool.wrench(0.9, &pose), Vector6::zeros());
+        assert_eq!(tool.wrench(3.0, &pose), Vector6::zeros());
+
+        // pitched up by 90 degrees, the world z axis is the link -x axis
+        let wrench = tool.wrench(1.25, &pose);
+        assert!((wrench - Vector6::new(-5.0, 0.0, 0.0, 0.0, 0.0, 0.0)).norm() < 1e-12);
+        let wrench = tool.wrench(2.0, &pose);
+        assert!((wrench[0] + 10.0).abs() < 1e-12 && wrench.fixed_rows::<3>(3).norm() < 1e-12);
+
+        // a profile is interpolated between its samples and held beyond them
+        let tool: ToolWrenchConfig = serde_yaml::from_str(
+            "{link: 1, profile: [{time: 1.0, force: [10.0, 0.0, 0.0]}, {time: 2.0, moment: [0.0, 0.0, 4.0]}]}",
+        )
+        .unwrap();
+        assert!(tool.validate(9).is_ok());
+        let identity = Isometry3::identity();
+        assert_eq!(tool.wrench(0.0, &identity)[0], 10.0);
+        let wrench = tool.wrench(1.75, &identity);
+        assert!((wrench - Vector6::new(2.5, 0.0, 0.0, 0.0, 0.0, 3.0)).norm() < 1e-12);
+        assert_eq!(
+            tool.wrench(5.0, &identity),
+            Vector6::new(0.0, 0.0, 0.0, 0.0, 0.0, 4.0)
+        );
+
+        for invalid in [
+            "{link: 1, start_time: 2.0, end_time: 1.0}",
+            "{link: 1, profile: [{time: 1.0}, {time: 1.0}]}",
+        ] {
+            let tool: ToolWrenchConfig = serde_yaml::from_str(invalid).unwrap();
+            assert!(tool.validate(9).is_err());
+        }
+    }
+}
@@ -0,0 +1,345 @@
+extern crate nalgebra as na;
+
+use na::{stack, SVector, UnitQuaternion, Vector6};
+use serde::Deserialize;
+
+use crate::control::{saturate, AttitudeError};
+use crate::estimator::NavState;
+use crate::reference::Reference;
+
+fn default_k_p() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        70.0, 70.0, 70.0, 100.0, 500.0, 500.0, 125.0, 125.0, 250.0, 250.0, 250.0, 250.0, 125.0,
+        125.0,
+    ])
+}
+
+fn default_k_i() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        0.1, 0.1, 0.1, 0.1, 0.2, 0.2, 0.1, 0.1, 0.2, 0.2, 0.2, 0.2, 0.1, 0.1,
+    ])
+}
+
+fn default_k_d() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        20.0, 20.0, 20.0, 10.0, 30.0, 30.0, 10.0, 10.0, 20.0, 20.0, 20.0, 10.0, 10.0, 10.0,
+    ])
+}
+
+fn default_wrench_limits() -> Vector6<f64> {
+    Vector6::repeat(100.0)
+}
+
+fn default_joint_torque_limit() -> f64 {
+    80.0
+}
+
+/// Protection of the integral states against windup while the output is saturated, either by the limits of the
+/// controller or by the thrusters.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "type")]
+pub enum AntiWindup {
+    /// The errors are integrated regardless of saturation.
+    #[default]
+    None,
+    /// The integration of an error stops while the output in its DOF is saturated and the error would drive it
+    /// further into saturation.
+    ConditionalIntegration,
+    /// The integral states are driven back by the part of the output that is not realized,
+    /// z_dot = e + tracking_gain (tau - tau_realized) / k_i, with the tracking gain in 1/s.
+    BackCalculation { tracking_gain: f64 },
+}
+
+/// Frame in which the PID expresses the base pose and velocity errors and applies its base gains.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum ErrorFrame {
+    /// The base frame, where the base wrench is applied.
+    #[default]
+    Body,
+    /// The reference attitude, so that the gains act along the axes of the reference. The base wrench is
+    /// rotated into the base frame.
+    Reference,
+}
+
+/// PID control of the base pose and joint angles along the reference trajectory. The base errors are
+/// expressed in the chosen error frame. The integral states are integrated alongside the plant, six for the
+/// base followed by eight for the joints.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PidConfig {
+    #[serde(default = "default_k_p")]
+    k_p: SVector<f64, 14>,
+    #[serde(default = "default_k_i")]
+    k_i: SVector<f64, 14>,
+    #[serde(default = "default_k_d")]
+    k_d: SVector<f64, 14>,
+    #[serde(default = "default_wrench_limits")]
+    wrench_limits: Vector6<f64>,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+    #[serde(default)]
+    attitude_error: AttitudeError,
+    #[serde(default)]
+    error_frame: ErrorFrame,
+    #[serde(default)]
+    anti_windup: AntiWindup,
+    /// Bounds on the magnitude of the integral states. The integral states are not bounded if omitted.
+    #[serde(default)]
+    integral_limits: Option<SVector<f64, 14>>,
+    /// Times [s] at which the integral states are reset without a bump in the output.
+    #[serde(default)]
+    reset_times: Vec<f64>,
+}
+
+impl Default for PidConfig {
+    fn default() -> Self {
+        PidConfig {
+            k_p: default_k_p(),
+            k_i: default_k_i(),
+            k_d: default_k_d(),
+            wrench_limits: default_wrench_limits(),
+            joint_torque_limit: default_joint_torque_limit(),
+            attitude_error: AttitudeError::Quaternion,
+            error_frame: ErrorFrame::Body,
+            anti_windup: AntiWindup::None,
+            integral_limits: None,
+            reset_times: Vec::new(),
+        }
+    }
+}
+
+impl PidConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let AntiWindup::BackCalculation { tracking_gain } = self.anti_windup {
+            if tracking_gain <= 0.0 {
+                return Err("The back-calculation tracking gain must be positive".to_string());
+            }
+        }
+        if let Some(limits) = &self.integral_limits {
+            if limits.iter().any(|l| *l < 0.0) {
+                return Err("The integral limits must be non-negative".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Errors of the base pose and joint angles and of their velocities, and the rotation from the base frame
+    /// to the error frame.
+    fn errors(
+        &self,
+        nav: &NavState,
+        reference: &Reference,
+    ) -> (SVector<f64, 14>, SVector<f64, 14>, UnitQuaternion<f64>) {
+        let frame = match self.error_frame {
+            ErrorFrame::Body => nav.quat,
+            ErrorFrame::Reference => reference.quat,
+        };
+        let to_frame = frame.inverse() * nav.quat;
+        let err = stack![
+            frame.inverse() * (nav.pos - reference.pos);
+            self.attitude_error.error(&nav.quat, &reference.quat);
+            nav.theta - reference.theta
+        ];
+        let err_dot = stack![
+            to_frame * nav.nu_b.fixed_rows::<3>(0) - frame.inverse() * reference.vel;
+            to_frame * nav.nu_b.fixed_rows::<3>(3);
+            nav.theta_dot - reference.theta_dot
+        ];
+        (err, err_dot, to_frame)
+    }
+
+    fn output(
+        &self,
+        err: &SVector<f64, 14>,
+        err_dot: &SVector<f64, 14>,
+        integrals: &SVector<f64, 14>,
+    ) -> SVector<f64, 14> {
+        -self.k_p.component_mul(err)
+            - self.k_i.component_mul(integrals)
+            - self.k_d.component_mul(err_dot)
+    }
+
+    /// Computes the base wrench and joint torques within the limits, and the unsaturated output.
+    pub fn control(
+        &self,
+        nav: &NavState,
+        reference: &Reference,
+        integrals: &SVector<f64, 14>,
+    ) -> (SVector<f64, 14>, SVector<f64, 14>) {
+        let (err, err_dot, to_frame) = self.errors(nav, reference);
+        let tau = rotate_base(&to_frame.inverse(), &self.output(&err, &err_dot, integrals));
+        (
+            saturate(&tau, &self.wrench_limits, self.joint_torque_limit),
+            tau,
+        )
+    }
+
+    /// Computes the derivatives of the integral states, given the unsaturated output `tau` and the generalized
+    /// forces realized by the thrusters and joint motors.
+    pub fn integral_rates(
+        &self,
+        nav: &NavState,
+        reference: &Reference,
+        integrals: &SVector<f64, 14>,
+        tau: &SVector<f64, 14>,
+        tau_realized: &SVector<f64, 14>,
+    ) -> SVector<f64, 14> {
+        let (err, _, to_frame) = self.errors(nav, reference);
+        let excess = rotate_base(&to_frame, &(tau - tau_realized));
+        let mut rates = err;
+        for i in 0..14 {
+            match self.anti_windup {
+                AntiWindup::None => (),
+                // -k_i z grows into saturation when the error has the opposite sign of the excess
+                AntiWindup::ConditionalIntegration => {
+                    if excess[i].abs() > 1e-9 && excess[i] * err[i] < 0.0 {
+                        rates[i] = 0.0;
+                    }
+                }
+                AntiWindup::BackCalculation { tracking_gain } => {
+                    if self.k_i[i] != 0.0 {
+                        rates[i] += tracking_gain * excess[i] / self.k_i[i];
+                    }
+                }
+            }
+            if let Some(limits) = &self.integral_limits {
+                if integrals[i].abs() >= limits[i] && integrals[i] * rates[i] > 0.0 {
+                    rates[i] = 0.0;
+                }
+            }
+        }
+        rates
+    }
+
+    /// Integral states for which the unsaturated output equals `tau`, e.g. the saturated output, so that the
+    /// integrators can be reset without a bump in the output. DOFs without integral action are reset to zero.
+    pub fn bumpless_integrals(
+        &self,
+        nav: &NavState,
+        reference: &Reference,
+        tau: &SVector<f64, 14>,
+    ) -> SVector<f64, 14> {
+        let (err, err_dot, to_frame) = self.errors(nav, reference);
+        let proportional_derivative = self.output(&err, &err_dot, &SVector::zeros());
+        let tau = rotate_base(&to_frame, tau);
+        SVector::from_fn(|i, _| {
+            if self.k_i[i] == 0.0 {
+                return 0.0;
+            }
+            let z = (proportional_derivative[i] - tau[i]) / self.k_i[i];
+            match &self.integral_limits {
+                Some(limits) => z.clamp(-limits[i], limits[i]),
+                None => z,
+            }
+        })
+    }
+
+    /// Whether the integral states are reset in the sample interval starting at `t`.
+    pub fn is_reset_due(&self, t: f64, sample_time: f64) -> bool {
+        self.reset_times
+            .iter()
+            .any(|&reset| reset >= t && reset < t + sample_time)
+    }
+}
+
+/// Rotates the force and moment of the base wrench, leaving the joint torques.
+fn rotate_base(rotation: &UnitQuaternion<f64>, tau: &SVector<f64, 14>) -> SVector<f64, 14> {
+    stack![
+        rotation * tau.fixed_rows::<3>(0);
+        rotation * tau.fixed_rows::<3>(3);
+        tau.fixed_rows::<8>(6)
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use na::Vector3;
+
+    /// Integrates the integral states for `duration` with a constant large position error, realizing the
+    /// saturated output.
+    fn wind_up(pid: &PidConfig, duration: f64) -> (SVector<f64, 14>, NavState, Reference) {
+        let reference = ReferenceConfig::default().at(0.0);
+        let nav = NavState {
+            pos: Vector3::new(5.0, 0.0, -2.0),
+            quat: reference.quat,
+            nu_b: Vector6::zeros(),
+            theta: reference.theta,
+            theta_dot: SVector::zeros(),
+        };
+        let dt = 0.01;
+        let mut integrals = SVector::<f64, 14>::zeros();
+        for _ in 0..(duration / dt).round() as usize {
+            let (tau_realized, tau) = pid.control(&nav, &reference, &integrals);
+            integrals += dt * pid.integral_rates(&nav, &reference, &integrals, &tau, &tau_realized);
+        }
+        (integrals, nav, reference)
+    }
+
+    #[test]
+    fn test_bounded_integrals_under_saturation() {
+        // without anti-windup the integral states grow with the error
+        let (integrals, _, _) = wind_up(&PidConfig::default(), 100.0);
+        assert!((integrals[0] - 500.0).abs() < 1e-6 && (integrals[2] + 200.0).abs() < 1e-6);
+
+        let conditional: PidConfig =
+            serde_yaml::from_str("{anti_windup: {type: ConditionalIntegration}}").unwrap();
+        let (integrals, _, _) = wind_up(&conditional, 100.0);
+        assert_eq!(integrals, SVector::<f64, 14>::zeros());
+
+        // back-calculation settles where the excess balances the error, e + k_t (tau - tau_realized) / k_i = 0
+        let back_calculation: PidConfig =
+            serde_yaml::from_str("{anti_windup: {type: BackCalculation, tracking_gain: 1.0}}")
+                .unwrap();
+        let (integrals, _, _) = wind_up(&back_calculation, 100.0);
+        let (integrals_later, nav, reference) = wind_up(&back_calculation, 200.0);
+        assert!((integrals - integrals_later).norm() < 1e-6);
+        let (tau_realized, tau) = back_calculation.control(&nav, &reference, &integrals);
+        assert!((tau[0] - tau_realized[0] + 0.5).abs() < 1e-6);
+
+        let limited: PidConfig = serde_yaml::from_str(
+            "{integral_limits: [10.0, 10.0, 10.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]}",
+        )
+        .unwrap();
+        let (integrals, _, _) = wind_up(&limited, 100.0);
+        assert!(integrals[0] <= 10.0 + 0.1 && integrals[2] >= -10.0 - 0.1);
+    }
+
+    #[test]
+    fn test_bumpless_reset() {
+        let pid = PidConfig::default();
+        let (integrals, nav, reference) = wind_up(&pid, 100.0);
+        let (tau_realized, tau) = pid.control(&nav, &reference, &integrals);
+        assert!(tau[0] < -350.0);
+
+        // the reset integrators make the unsaturated output equal the saturated one, so nothing jumps
+        let reset = pid.bumpless_integrals(&nav, &reference, &tau_realized);
+        let (tau_realized_reset, tau_reset) = pid.control(&nav, &reference, &reset);
+        assert!((tau_realized_reset - tau_realized).norm() < 1e-9);
+        assert!((tau_reset - tau_realized).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_error_frames() {
+        let reference: ReferenceConfig =
+            serde_yaml::from_str("{roll_pitch_yaw: [0.0, 0.0, 1.5707963267948966]}").unwrap();
+        let reference = reference.at(0.0);
+        let nav = NavState {
+            pos: reference.pos + Vector3::new(1.0, 0.0, 0.0),
+            quat: UnitQuaternion::identity(),
+            nu_b: Vector6::zeros(),
+            theta: reference.theta,
+            theta_dot: SVector::zeros(),
+        };
+        let gains = "k_p: [70.0, 10.0, 10.0, 100.0, 500.0, 500.0, 125.0, 125.0, 250.0, 250.0, 250.0, 250.0, 125.0, 125.0]";
+
+        // the position error along the x axis of the base is along the -y axis of the reference
+        let body: PidConfig = serde_yaml::from_str(&format!("{{{}}}", gains)).unwrap();
+        let (_, tau) = body.control(&nav, &reference, &SVector::zeros());
+        assert!((tau.fixed_rows::<3>(0) - Vector3::new(-70.0, 0.0, 0.0)).norm() < 1e-9);
+        let reference_frame: PidConfig =
+            serde_yaml::from_str(&format!("{{{}, error_frame: Reference}}", gains)).unwrap();
+        let (_, tau) = reference_frame.control(&nav, &reference, &SVector::zeros());
+        assert!((tau.fixed_rows::<3>(0) - Vector3::new(-10.0, 0.0, 0.0)).norm() < 1e-9);
+    }
+}
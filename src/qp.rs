@@ -0,0 +1,93 @@
+extern crate nalgebra as na;
+
+use na::{DMatrix, DVector};
+
+/// Minimizes 1/2 x^T H x + g^T x subject to lower <= x <= upper with the projected Newton method, starting
+/// from `x0`, e.g. the solution at the previous sample time. H must be positive definite. Each iteration
+/// takes a Newton step in the variables that are not held at a bound by the gradient, and backtracks along
+/// the projection of the step onto the box.
+pub fn solve_box_qp(
+    h: &DMatrix<f64>,
+    g: &DVector<f64>,
+    lower: &DVector<f64>,
+    upper: &DVector<f64>,
+    x0: &DVector<f64>,
+    max_iter: usize,
+) -> DVector<f64> {
+    let n = g.len();
+    let project =
+        |x: &DVector<f64>| x.zip_zip_map(lower, upper, |x_i, l_i, u_i| x_i.clamp(l_i, u_i));
+    let cost = |x: &DVector<f64>| 0.5 * x.dot(&(h * x)) + g.dot(x);
+
+    let mut x = project(x0);
+    for _ in 0..max_iter {
+        let grad = h * &x + g;
+        let free: Vec<usize> = (0..n)
+            .filter(|&i| {
+                let at_lower = x[i] <= lower[i] && grad[i] > 0.0;
+                let at_upper = x[i] >= upper[i] && grad[i] < 0.0;
+                !(at_lower || at_upper)
+            })
+            .collect();
+        let grad_free = DVector::from_fn(free.len(), |k, _| grad[free[k]]);
+        if grad_free.norm() <= 1e-9 * (1.0 + g.norm()) {
+            break;
+        }
+
+        let h_free = DMatrix::from_fn(free.len(), free.len(), |r, c| h[(free[r], free[c])]);
+        let Some(chol) = h_free.cholesky() else {
+            break;
+        };
+        let step_free = -chol.solve(&grad_free);
+        let mut step = DVector::zeros(n);
+        for (k, &i) in free.iter().enumerate() {
+            step[i] = step_free[k];
+        }
+
+        // Armijo backtracking along the projection arc
+        let f = cost(&x);
+        let mut alpha = 1.0;
+        let mut x_next = project(&(&x + &step));
+        while cost(&x_next) > f + 1e-4 * grad.dot(&(&x_next - &x)) && alpha > 1e-10 {
+            alpha *= 0.5;
+            x_next = project(&(&x + alpha * &step));
+        }
+        let change = (&x_next - &x).norm();
+        x = x_next;
+        if change <= 1e-12 * (1.0 + x.norm()) {
+            break;
+        }
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_qp_kkt() {
+        let a = DMatrix::from_fn(6, 6, |r, c| ((r * 7 + c * 3) % 5) as f64 - 2.0);
+        let h = a.transpose() * &a + DMatrix::identity(6, 6);
+        let g = DVector::from_vec(vec![3.0, -8.0, 1.0, 6.0, -2.0, 0.5]);
+        let lower = DVector::repeat(6, -1.0);
+        let upper = DVector::repeat(6, 0.5);
+        let x = solve_box_qp(&h, &g, &lower, &upper, &DVector::zeros(6), 50);
+
+        // the gradient vanishes in the free variables and points out of the box at the active bounds
+        let grad = &h * &x + &g;
+        let mut num_active = 0;
+        for i in 0..6 {
+            if x[i] <= lower[i] {
+                assert!(grad[i] >= -1e-9);
+                num_active += 1;
+            } else if x[i] >= upper[i] {
+                assert!(grad[i] <= 1e-9);
+                num_active += 1;
+            } else {
+                assert!(grad[i].abs() < 1e-9);
+            }
+        }
+        assert!(num_active > 0);
+    }
+}
@@ -0,0 +1,227 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::SVector;
+use serde::Deserialize;
+
+fn default_max_depth() -> usize {
+    20
+}
+
+/// Quadrature rule as given in the config file.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "method")]
+enum QuadratureConfig {
+    Trapezoid {
+        n: usize,
+    },
+    GaussLegendre {
+        n: usize,
+    },
+    AdaptiveSimpson {
+        tol: f64,
+        #[serde(default = "default_max_depth")]
+        max_depth: usize,
+    },
+}
+
+/// Numerical integration rule for vector-valued integrands on an interval.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(try_from = "QuadratureConfig")]
+pub enum Quadrature {
+    /// Trapezoid rule with `n` partitions.
+    Trapezoid { n: usize },
+    /// Gauss–Legendre rule with precomputed nodes and weights on [-1, 1].
+    GaussLegendre { nodes: Vec<f64>, weights: Vec<f64> },
+    /// Adaptive Simpson rule, refining until the absolute error estimate is below `tol`.
+    AdaptiveSimpson { tol: f64, max_depth: usize },
+}
+
+impl Default for Quadrature {
+    fn default() -> Self {
+        Quadrature::Trapezoid { n: 9 }
+    }
+}
+
+impl TryFrom<QuadratureConfig> for Quadrature {
+    type Error = String;
+
+    fn try_from(cfg: QuadratureConfig) -> Result<Self, String> {
+        match cfg {
+            QuadratureConfig::Trapezoid { n: 0 } | QuadratureConfig::GaussLegendre { n: 0 } => {
+                Err("The quadrature rule needs at least one partition or node".to_string())
+            }
+            QuadratureConfig::AdaptiveSimpson { tol, .. } if tol <= 0.0 => {
+                Err("The adaptive Simpson tolerance must be positive".to_string())
+            }
+            QuadratureConfig::Trapezoid { n } => Ok(Quadrature::Trapezoid { n }),
+            QuadratureConfig::GaussLegendre { n } => {
+                let (nodes, weights) = gauss_legendre(n);
+                Ok(Quadrature::GaussLegendre { nodes, weights })
+            }
+            QuadratureConfig::AdaptiveSimpson { tol, max_depth } => {
+                Ok(Quadrature::AdaptiveSimpson { tol, max_depth })
+            }
+        }
+    }
+}
+
+impl Quadrature {
+    #[cfg(test)]
+    pub fn gauss_legendre(n: usize) -> Self {
+        QuadratureConfig::GaussLegendre { n }.try_into().unwrap()
+    }
+
+    /// Integrates `f` from `a` to `b`.
+    pub fn integrate<F, const N: usize>(&self, f: F, a: f64, b: f64) -> SVector<f64, N>
+    where
+        F: Fn(f64) -> SVector<f64, N>,
+    {
+        match self {
+            Quadrature::Trapezoid { n } => {
+                let dx = (b - a) / (*n as f64);
+                let inner = (1..*n).fold(SVector::zeros(), |acc, k| acc + f(a + k as f64 * dx));
+                dx * (inner + 0.5 * (f(a) + f(b)))
+            }
+            Quadrature::GaussLegendre { nodes, weights } => {
+                let (mid, half) = (0.5 * (a + b), 0.5 * (b - a));
+                half * nodes
+                    .iter()
+                    .zip(weights)
+                    .fold(SVector::zeros(), |acc, (x, w)| acc + *w * f(mid + half * x))
+            }
+            Quadrature::AdaptiveSimpson { tol, max_depth } => {
+                let (fa, fm, fb) = (f(a), f(0.5 * (a + b)), f(b));
+                let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+                adaptive_simpson(&f, a, b, fa, fm, fb, whole, *tol, *max_depth)
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn adaptive_simpson<F, const N: usize>(
+    f: &F,
+    a: f64,
+    b: f64,
+    fa: SVector<f64, N>,
+    fm: SVector<f64, N>,
+    fb: SVector<f64, N>,
+    whole: SVector<f64, N>,
+    tol: f64,
+    depth: usize,
+) -> SVector<f64, N>
+where
+    F: Fn(f64) -> SVector<f64, N>,
+{
+    let m = 0.5 * (a + b);
+    let (flm, frm) = (f(0.5 * (a + m)), f(0.5 * (m + b)));
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    let delta = left + right - whole;
+
+    if depth == 0 || delta.amax() <= 15.0 * tol {
+        left + right + delta / 15.0
+    } else {
+        adaptive_simpson(f, a, m, fa, flm, fm, left, 0.5 * tol, depth - 1)
+            + adaptive_simpson(f, m, b, fm, frm, fb, right, 0.5 * tol, depth - 1)
+    }
+}
+
+/// Computes the nodes and weights of the `n`-point Gauss–Legendre rule on [-1, 1] by Newton iteration
+/// on the Legendre polynomial P_n.
+pub fn gauss_legendre(n: usize) -> (Vec<f64>, Vec<f64>) {
+    assert!(n > 0, "The Gauss–Legendre rule needs at least one node.");
+    let mut nodes = vec![0.0; n];
+    let mut weights = vec![0.0; n];
+
+    for k in 0..n {
+        let mut x = (PI * (k as f64 + 0.75) / (n as f64 + 0.5)).cos();
+        let mut dp = 1.0;
+        for _ in 0..100 {
+            // three-term recurrence for P_n(x) and its derivative
+            let (mut p0, mut p1) = (1.0, x);
+            for j in 2..=n {
+                let p2 = ((2 * j - 1) as f64 * x * p1 - (j - 1) as f64 * p0) / j as f64;
+                p0 = p1;
+                p1 = p2;
+            }
+            dp = n as f64 * (x * p1 - p0) / (x * x - 1.0);
+            let dx = p1 / dp;
+            x -= dx;
+            if dx.abs() < 1e-15 {
+                break;
+            }
+        }
+        nodes[k] = x;
+        weights[k] = 2.0 / ((1.0 - x * x) * dp * dp);
+    }
+    (nodes, weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector4;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_gauss_legendre() {
+        let (nodes, weights) = gauss_legendre(3);
+        assert!((weights.iter().sum::<f64>() - 2.0).abs() < 1e-14);
+        assert!((nodes[0].abs() - (0.6_f64).sqrt()).abs() < 1e-14);
+        assert!((weights[1] - 8.0 / 9.0).abs() < 1e-14);
+
+        // exact for polynomials of degree 2n - 1
+        let quad = Quadrature::gauss_legendre(3);
+        let integral = quad.integrate(|x| na::Vector1::new(x.powi(5) - x.powi(2)), 0.0, 2.0);
+        assert!((integral[0] - (64.0 / 6.0 - 8.0 / 3.0)).abs() < 1e-12);
+
+        // empty rules are rejected when reading the config
+        for rule in ["{method: GaussLegendre, n: 0}", "{method: Trapezoid, n: 0}"] {
+            assert!(serde_yaml::from_str::<Quadrature>(rule).is_err());
+        }
+    }
+
+    /// Compares the accuracy and number of integrand evaluations of the quadrature rules on the
+    /// cross-flow drag integrand of a long (1.6 m) link that is both translating and rotating.
+    #[test]
+    fn test_cross_flow_drag_accuracy_and_cost() {
+        let nu = [0.0, 0.3, -0.2, 0.0, 0.4, 0.5];
+        let evals = Cell::new(0);
+        let drag_2d = |x: f64| -> Vector4<f64> {
+            evals.set(evals.get() + 1);
+            let sqrtx = ((nu[1] + x * nu[5]).powi(2) + (nu[2] - x * nu[4]).powi(2)).sqrt();
+            Vector4::new(
+                sqrtx * (nu[1] + x * nu[5]),
+                sqrtx * (nu[2] - x * nu[4]),
+                sqrtx * (-nu[2] * x + nu[4] * x.powi(2)),
+                sqrtx * (nu[1] * x + nu[5] * x.powi(2)),
+            )
+        };
+        let length = 1.6;
+        let reference = Quadrature::AdaptiveSimpson {
+            tol: 1e-14,
+            max_depth: 40,
+        }
+        .integrate(drag_2d, 0.0, length);
+
+        let run = |quad: Quadrature| -> (f64, usize) {
+            evals.set(0);
+            let integral = quad.integrate(drag_2d, 0.0, length);
+            ((integral - reference).amax(), evals.get())
+        };
+
+        let (err_trapz, cost_trapz) = run(Quadrature::Trapezoid { n: 9 });
+        let (err_gauss, cost_gauss) = run(Quadrature::gauss_legendre(5));
+        let (err_simpson, _) = run(Quadrature::AdaptiveSimpson {
+            tol: 1e-8,
+            max_depth: 20,
+        });
+
+        assert_eq!(cost_trapz, 10);
+        assert_eq!(cost_gauss, 5);
+        assert!(err_gauss < err_trapz);
+        assert!(err_simpson < 1e-6);
+    }
+}
@@ -0,0 +1,58 @@
+use std::f64::consts::PI;
+
+/// Small seeded pseudo-random number generator (SplitMix64), so that simulations are reproducible
+/// without pulling in an external dependency.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed number in [0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normally distributed number, using the Box–Muller transform.
+    pub fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let mut rng = Rng::new(42);
+        let n = 20000;
+        let samples: Vec<f64> = (0..n).map(|_| rng.uniform()).collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        assert!(samples.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!((mean - 0.5).abs() < 0.01);
+
+        let samples: Vec<f64> = (0..n).map(|_| rng.normal()).collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        assert!(mean.abs() < 0.03);
+        assert!((var - 1.0).abs() < 0.05);
+
+        // same seed, same sequence
+        assert_eq!(Rng::new(7).next_u64(), Rng::new(7).next_u64());
+    }
+}
@@ -0,0 +1,124 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use na::{vector, SVector, UnitQuaternion, Vector3};
+use serde::Deserialize;
+
+use crate::estimator::NavState;
+use crate::State;
+
+/// Reference trajectory followed by the base and joint controllers. The base moves from `position` at a
+/// constant world velocity with a constant attitude, and the joints oscillate about `joint_angles`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReferenceConfig {
+    position: Vector3<f64>,
+    roll_pitch_yaw: Vector3<f64>,
+    /// Base velocity in the world frame [m/s].
+    velocity: Vector3<f64>,
+    joint_angles: SVector<f64, 8>,
+    joint_amplitudes: SVector<f64, 8>,
+    /// Frequency [Hz] of the joint oscillations.
+    joint_frequency: f64,
+    joint_phases: SVector<f64, 8>,
+}
+
+impl Default for ReferenceConfig {
+    fn default() -> Self {
+        ReferenceConfig {
+            position: Vector3::zeros(),
+            roll_pitch_yaw: Vector3::zeros(),
+            velocity: Vector3::zeros(),
+            joint_angles: vector![PI / 4.0, 0.0, PI / 4.0, 0.0, PI / 4.0, 0.0, PI / 4.0, 0.0],
+            joint_amplitudes: SVector::zeros(),
+            joint_frequency: 0.0,
+            joint_phases: SVector::zeros(),
+        }
+    }
+}
+
+/// Desired base pose and velocity, and joint angles, rates and accelerations at a time instant.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub pos: Vector3<f64>,
+    pub quat: UnitQuaternion<f64>,
+    /// Base velocity in the world frame.
+    pub vel: Vector3<f64>,
+    pub theta: SVector<f64, 8>,
+    pub theta_dot: SVector<f64, 8>,
+    pub theta_ddot: SVector<f64, 8>,
+}
+
+impl ReferenceConfig {
+    pub fn at(&self, t: f64) -> Reference {
+        let omega = 2.0 * PI * self.joint_frequency;
+        let phase = self.joint_phases.add_scalar(omega * t);
+        let sin = phase.map(f64::sin);
+        let cos = phase.map(f64::cos);
+        Reference {
+            pos: self.position + t * self.velocity,
+            quat: UnitQuaternion::from_euler_angles(
+                self.roll_pitch_yaw[0],
+                self.roll_pitch_yaw[1],
+                self.roll_pitch_yaw[2],
+            ),
+            vel: self.velocity,
+            theta: self.joint_angles + self.joint_amplitudes.component_mul(&sin),
+            theta_dot: omega * self.joint_amplitudes.component_mul(&cos),
+            theta_ddot: -omega.powi(2) * self.joint_amplitudes.component_mul(&sin),
+        }
+    }
+
+    /// Computes the position [m], attitude [rad] and RMS joint angle [rad] errors of a simulated state.
+    pub fn tracking_errors(&self, t: f64, y: &State) -> (f64, f64, f64) {
+        let reference = self.at(t);
+        let nav = NavState::from_state(y);
+        (
+            (nav.pos - reference.pos).norm(),
+            (reference.quat.inverse() * nav.quat).angle(),
+            (nav.theta - reference.theta).norm() / 8f64.sqrt(),
+        )
+    }
+
+    /// Computes the RMS position [m], attitude [rad] and joint angle [rad] errors of the simulated states.
+    pub fn rms_tracking_errors(&self, times: &[f64], states: &[State]) -> (f64, f64, f64) {
+        let mut sum_sq = (0.0, 0.0, 0.0);
+        for (t, y) in times.iter().zip(states) {
+            let (pos_err, att_err, joint_err) = self.tracking_errors(*t, y);
+            sum_sq.0 += pos_err.powi(2);
+            sum_sq.1 += att_err.powi(2);
+            sum_sq.2 += joint_err.powi(2);
+        }
+        let n = times.len().max(1) as f64;
+        (
+            (sum_sq.0 / n).sqrt(),
+            (sum_sq.1 / n).sqrt(),
+            (sum_sq.2 / n).sqrt(),
+        )
+    }
+
+    /// Writes the time and the position, attitude and joint angle errors of the simulated states, one line per
+    /// sample, to compare the tracking of the controllers.
+    pub fn save_tracking_errors(&self, times: &[f64], states: &[State], filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let mut buf = BufWriter::new(file);
+        for (t, y) in times.iter().zip(states) {
+            let (pos_err, att_err, joint_err) = self.tracking_errors(*t, y);
+            buf.write_fmt(format_args!(
+                "{}, {}, {}, {}\n",
+                t, pos_err, att_err, joint_err
+            ))
+            .unwrap();
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+extern crate nalgebra as na;
+
+use multibody_dynamics::multibody::MultiBody;
+use na::{stack, Isometry3, SVector, Vector3, Vector6};
+use serde::Deserialize;
+
+use crate::control::{inverse_dynamics, saturate, tracking_errors, AttitudeError};
+use crate::estimator::NavState;
+use crate::reference::Reference;
+use crate::Config;
+
+/// Number of internal controller states integrated alongside the plant, enough for the adaptive estimates.
+pub const NUM_STATES: usize = 42;
+
+pub type ControllerState = SVector<f64, NUM_STATES>;
+
+fn default_lambda() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0,
+    ])
+}
+
+fn default_k_1() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        40.0, 40.0, 40.0, 10.0, 20.0, 20.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0,
+    ])
+}
+
+fn default_k_2() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        20.0, 20.0, 20.0, 5.0, 10.0, 10.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0,
+    ])
+}
+
+fn default_k_s() -> SVector<f64, 14> {
+    SVector::from_column_slice(&[
+        60.0, 60.0, 60.0, 10.0, 30.0, 30.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0,
+    ])
+}
+
+fn default_gamma() -> Vector3<f64> {
+    Vector3::new(20.0, 20.0, 20.0)
+}
+
+fn default_leakage() -> f64 {
+    0.01
+}
+
+fn default_wrench_limits() -> Vector6<f64> {
+    Vector6::repeat(100.0)
+}
+
+fn default_joint_torque_limit() -> f64 {
+    80.0
+}
+
+fn default_boundary_layer() -> f64 {
+    0.01
+}
+
+/// Sign function smoothed by a linear boundary layer of half-width `width`, so that the control is continuous
+/// across the sliding surface and the solver keeps its step size. Returns 0 on the surface.
+fn smooth_sign(x: f64, width: f64) -> f64 {
+    (x / width).clamp(-1.0, 1.0)
+}
+
+/// Checks that the convergence rates and the force limits are positive.
+fn validate_common(
+    lambda: &SVector<f64, 14>,
+    wrench_limits: &Vector6<f64>,
+    joint_torque_limit: f64,
+) -> Result<(), String> {
+    if lambda.min() <= 0.0 {
+        return Err("The sliding surface convergence rates lambda must be positive".to_string());
+    }
+    if wrench_limits.min() <= 0.0 || joint_torque_limit <= 0.0 {
+        return Err("The wrench and joint torque limits must be positive".to_string());
+    }
+    Ok(())
+}
+
+/// Reference velocities zeta_r = zeta_d - lambda e and their derivatives, and the sliding variable
+/// s = zeta - zeta_r, which vanishes when the errors decay as e_dot = -lambda e.
+fn sliding_surface(
+    lambda: &SVector<f64, 14>,
+    attitude_error: AttitudeError,
+    nav: &NavState,
+    reference: &Reference,
+) -> (SVector<f64, 14>, SVector<f64, 14>, SVector<f64, 14>) {
+    let zeta = stack![nav.nu_b; nav.theta_dot];
+    let (err, zeta_d, zeta_dot_d) = tracking_errors(nav, reference, attitude_error);
+    let zeta_r = zeta_d - lambda.component_mul(&err);
+    let zeta_r_dot = zeta_dot_d - lambda.component_mul(&(zeta - zeta_d));
+    (zeta_r, zeta_r_dot, zeta - zeta_r)
+}
+
+/// Super-twisting sliding-mode control of the base pose and joint angles along the reference trajectory. The
+/// equivalent control from the inverse dynamics of the nominal model is combined with the continuous
+/// super-twisting term -k_1 |s|^(1/2) sign(s) + v, v_dot = -k_2 sign(s), which rejects bounded model errors.
+/// The sign is smoothed within the boundary layer |s| < boundary_layer. The integrals v are the first 14
+/// controller states, and are held at the actuator limits.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SuperTwistingConfig {
+    /// Convergence rates [1/s] of the base pose and joint angle errors on the sliding surface.
+    #[serde(default = "default_lambda")]
+    lambda: SVector<f64, 14>,
+    #[serde(default)]
+    attitude_error: AttitudeError,
+    #[serde(default = "default_k_1")]
+    k_1: SVector<f64, 14>,
+    #[serde(default = "default_k_2")]
+    k_2: SVector<f64, 14>,
+    /// Half-width of the boundary layer about the sliding surface.
+    #[serde(default = "default_boundary_layer")]
+    boundary_layer: f64,
+    #[serde(default = "default_wrench_limits")]
+    wrench_limits: Vector6<f64>,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+}
+
+impl SuperTwistingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_common(&self.lambda, &self.wrench_limits, self.joint_torque_limit)?;
+        if self.k_1.min() <= 0.0 || self.k_2.min() <= 0.0 {
+            return Err("The super-twisting gains must be positive".to_string());
+        }
+        if self.boundary_layer <= 0.0 {
+            return Err("The super-twisting boundary layer must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Computes the saturated base wrench and joint torques, and the derivatives of the controller states.
+    pub fn control(
+        &self,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        conf: &[Isometry3<f64>],
+        nav: &NavState,
+        reference: &Reference,
+        states: &ControllerState,
+    ) -> (SVector<f64, 14>, ControllerState) {
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let (zeta_r, zeta_r_dot, s) =
+            sliding_surface(&self.lambda, self.attitude_error, nav, reference);
+        let v = states.fixed_rows::<14>(0);
+
+        let twisting = SVector::<f64, 14>::from_fn(|i, _| {
+            -self.k_1[i] * s[i].abs().sqrt() * smooth_sign(s[i], self.boundary_layer) + v[i]
+        });
+        let tau = inverse_dynamics(multibody, cfg, conf, &zeta, &zeta_r, &zeta_r_dot) + twisting;
+
+        let mut states_dot = ControllerState::zeros();
+        for i in 0..14 {
+            let limit = if i < 6 {
+                self.wrench_limits[i]
+            } else {
+                self.joint_torque_limit
+            };
+            let v_dot = -self.k_2[i] * smooth_sign(s[i], self.boundary_layer);
+            if v[i].abs() < limit || v[i] * v_dot < 0.0 {
+                states_dot[i] = v_dot;
+            }
+        }
+        (
+            saturate(&tau, &self.wrench_limits, self.joint_torque_limit),
+            states_dot,
+        )
+    }
+}
+
+/// Adaptive control of the base pose and joint angles along the reference trajectory. The inverse dynamics of
+/// the nominal model are corrected by online estimates of the linear and quadratic damping and a constant
+/// restoring force in each DOF, zeta_i a_1 + |zeta_i| zeta_i a_2 + a_3, which are updated from the sliding
+/// variable s with the gradient law a_dot = -gamma Y^T s - leakage a. The estimates are the controller states,
+/// three per DOF.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdaptiveConfig {
+    /// Convergence rates [1/s] of the base pose and joint angle errors on the sliding surface.
+    #[serde(default = "default_lambda")]
+    lambda: SVector<f64, 14>,
+    #[serde(default)]
+    attitude_error: AttitudeError,
+    /// Gains on the sliding variable.
+    #[serde(default = "default_k_s")]
+    k_s: SVector<f64, 14>,
+    /// Adaptation gains of the linear damping, quadratic damping and restoring force estimates.
+    #[serde(default = "default_gamma")]
+    gamma: Vector3<f64>,
+    /// Leakage [1/s] pulling the estimates towards zero, keeping them bounded under saturation.
+    #[serde(default = "default_leakage")]
+    leakage: f64,
+    #[serde(default = "default_wrench_limits")]
+    wrench_limits: Vector6<f64>,
+    #[serde(default = "default_joint_torque_limit")]
+    joint_torque_limit: f64,
+}
+
+impl AdaptiveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_common(&self.lambda, &self.wrench_limits, self.joint_torque_limit)?;
+        if self.k_s.min() <= 0.0 {
+            return Err("The adaptive gains on the sliding variable must be positive".to_string());
+        }
+        if self.gamma.min() < 0.0 || self.leakage < 0.0 {
+            return Err("The adaptation gains and the leakage must be non-negative".to_string());
+        }
+        Ok(())
+    }
+
+    /// Computes the saturated base wrench and joint torques, and the derivatives of the controller states.
+    pub fn control(
+        &self,
+        multibody: &MultiBody<9, 14>,
+        cfg: &Config,
+        conf: &[Isometry3<f64>],
+        nav: &NavState,
+        reference: &Reference,
+        states: &ControllerState,
+    ) -> (SVector<f64, 14>, ControllerState) {
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let (zeta_r, zeta_r_dot, s) =
+            sliding_surface(&self.lambda, self.attitude_error, nav, reference);
+
+        let mut tau = inverse_dynamics(multibody, cfg, conf, &zeta, &zeta_r, &zeta_r_dot)
+            - self.k_s.component_mul(&s);
+        let mut states_dot = ControllerState::zeros();
+        for i in 0..14 {
+            let regressor = Vector3::new(zeta[i], zeta[i].abs() * zeta[i], 1.0);
+            let estimates = states.fixed_rows::<3>(3 * i);
+            tau[i] += regressor.dot(&estimates);
+            states_dot.fixed_rows_mut::<3>(3 * i).copy_from(
+                &(-s[i] * self.gamma.component_mul(&regressor) - self.leakage * estimates),
+            );
+        }
+        (
+            saturate(&tau, &self.wrench_limits, self.joint_torque_limit),
+            states_dot,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::ReferenceConfig;
+    use crate::{comp_link_properties, setup_aiauv, test_config};
+    use na::Translation3;
+
+    #[test]
+    fn test_controllers_on_reference() {
+        let cfg = test_config();
+        let multibody = setup_aiauv(&cfg, &comp_link_properties(&cfg));
+        let reference: ReferenceConfig = serde_yaml::from_str(
+            "{velocity: [0.3, 0.0, 0.1], joint_amplitudes: [0.2, 0.0, 0.2, 0.0, 0.2, 0.0, 0.2, 0.0], joint_frequency: 0.2}",
+        )
+        .unwrap();
+        let reference = reference.at(0.7);
+        let nav = NavState {
+            pos: reference.pos,
+            quat: reference.quat,
+            nu_b: stack![reference.quat.inverse() * reference.vel; Vector3::zeros()],
+            theta: reference.theta,
+            theta_dot: reference.theta_dot,
+        };
+        let base = Isometry3::from_parts(Translation3::from(nav.pos), nav.quat);
+        let conf = multibody.minimal_to_homogenous_configuration(&base, &nav.theta);
+        let zeta = stack![nav.nu_b; nav.theta_dot];
+        let (_, zeta_d, zeta_dot_d) = tracking_errors(&nav, &reference, AttitudeError::Quaternion);
+        let feedforward = inverse_dynamics(&multibody, &cfg, &conf, &zeta, &zeta_d, &zeta_dot_d);
+
+        // on the reference, both reduce to the inverse dynamics and their states rest
+        let twisting: SuperTwistingConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(twisting.validate().is_ok());
+        let unsmoothed: SuperTwistingConfig =
+            serde_yaml::from_str("{boundary_layer: 0.0}").unwrap();
+        assert!(unsmoothed.validate().is_err());
+        let (tau, states_dot) = twisting.control(
+            &multibody,
+            &cfg,
+            &conf,
+            &nav,
+            &reference,
+            &ControllerState::zeros(),
+        );
+        assert!((tau - feedforward).norm() < 1e-9 && states_dot == ControllerState::zeros());
+        let adaptive: AdaptiveConfig = serde_yaml::from_str("{leakage: 0.0}").unwrap();
+        assert!(adaptive.validate().is_ok());
+        let (tau, states_dot) = adaptive.control(
+            &multibody,
+            &cfg,
+            &conf,
+            &nav,
+            &reference,
+            &ControllerState::zeros(),
+        );
+        assert!((tau - feedforward).norm() < 1e-9 && states_dot == ControllerState::zeros());
+
+        // surging faster than the reference, the restoring force estimate of the surge decreases and the
+        // super-twisting integral pushes back
+        let nav = NavState {
+            nu_b: nav.nu_b + Vector6::new(0.1, 0.0, 0.0, 0.0, 0.0, 0.0),
+            ..nav
+        };
+        let (_, states_dot) = adaptive.control(
+            &multibody,
+            &cfg,
+            &conf,
+            &nav,
+            &reference,
+            &ControllerState::zeros(),
+        );
+        assert!(states_dot[2] < 0.0 && states_dot[0] < 0.0);
+        let (_, states_dot) = twisting.control(
+            &multibody,
+            &cfg,
+            &conf,
+            &nav,
+            &reference,
+            &ControllerState::zeros(),
+        );
+        assert!(states_dot[0] < 0.0);
+    }
+}
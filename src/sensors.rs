@@ -0,0 +1,490 @@
+extern crate nalgebra as na;
+use std::collections::VecDeque;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use na::{Isometry3, Point3, Rotation3, SVector, Vector3, Vector6};
+use serde::Deserialize;
+
+use crate::random::Rng;
+
+fn default_link() -> usize {
+    1
+}
+
+/// Placement of a sensor on a link.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SensorMount {
+    /// Link the sensor is attached to (1-based, the base link is 1).
+    #[serde(default = "default_link")]
+    pub link: usize,
+    /// Position of the sensor, expressed in the link frame.
+    #[serde(default)]
+    pub position: Vector3<f64>,
+    /// [roll, pitch, yaw] of the sensor frame relative to the link frame.
+    #[serde(default)]
+    roll_pitch_yaw: Vector3<f64>,
+}
+
+impl SensorMount {
+    /// Rotation from the sensor frame to the link frame.
+    pub fn rotation(&self) -> Rotation3<f64> {
+        let rpy = self.roll_pitch_yaw;
+        Rotation3::from_euler_angles(rpy[0], rpy[1], rpy[2])
+    }
+}
+
+/// Strapdown IMU. Noise densities are given as standard deviations per sample, and the bias random walks
+/// as standard deviations per square root of a second.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImuConfig {
+    pub rate: f64,
+    #[serde(flatten)]
+    pub mount: SensorMount,
+    #[serde(default)]
+    pub gyro_noise: f64,
+    #[serde(default)]
+    pub gyro_bias_walk: f64,
+    #[serde(default)]
+    pub accel_noise: f64,
+    #[serde(default)]
+    pub accel_bias_walk: f64,
+}
+
+/// Doppler velocity log measuring the velocity over ground in the sensor frame while in bottom lock.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DvlConfig {
+    pub rate: f64,
+    #[serde(flatten)]
+    pub mount: SensorMount,
+    #[serde(default)]
+    pub noise: f64,
+    /// Probability that a ping fails to lock on to the bottom.
+    #[serde(default)]
+    dropout_probability: f64,
+}
+
+/// Pressure sensor measuring the depth below the free surface.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DepthConfig {
+    pub rate: f64,
+    #[serde(flatten)]
+    pub mount: SensorMount,
+    #[serde(default)]
+    pub surface_z: f64,
+    #[serde(default)]
+    pub noise: f64,
+}
+
+/// USBL position fix in the world frame, received `latency` seconds after the time of validity.
+#[derive(Debug, Deserialize, Clone)]
+pub struct UsblConfig {
+    pub rate: f64,
+    #[serde(flatten)]
+    pub mount: SensorMount,
+    #[serde(default)]
+    pub noise: f64,
+    #[serde(default)]
+    pub latency: f64,
+}
+
+/// Joint encoders, quantized to `resolution` [rad].
+#[derive(Debug, Deserialize, Clone)]
+pub struct EncoderConfig {
+    rate: f64,
+    #[serde(default)]
+    resolution: f64,
+    #[serde(default)]
+    noise: f64,
+}
+
+/// Sensor suite. Each sensor is sampled at its own rate, at most once per simulation sample time.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SensorConfig {
+    /// Seed of the measurement noise.
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    pub imu: Option<ImuConfig>,
+    #[serde(default)]
+    pub dvl: Option<DvlConfig>,
+    #[serde(default)]
+    pub depth: Option<DepthConfig>,
+    #[serde(default)]
+    pub usbl: Option<UsblConfig>,
+    #[serde(default)]
+    encoders: Option<EncoderConfig>,
+}
+
+impl SensorConfig {
+    /// Checks the mounts and the noise parameters, and that no sensor is sampled faster than the simulation
+    /// sample time allows.
+    pub fn validate(&self, num_links: usize, sample_time: f64) -> Result<(), String> {
+        let mounts = [
+            self.imu.as_ref().map(|s| ("imu", s.rate, Some(&s.mount))),
+            self.dvl.as_ref().map(|s| ("dvl", s.rate, Some(&s.mount))),
+            self.depth
+                .as_ref()
+                .map(|s| ("depth", s.rate, Some(&s.mount))),
+            self.usbl.as_ref().map(|s| ("usbl", s.rate, Some(&s.mount))),
+            self.encoders.as_ref().map(|s| ("encoders", s.rate, None)),
+        ];
+        for (name, rate, mount) in mounts.into_iter().flatten() {
+            if rate <= 0.0 {
+                return Err(format!("The {name} rate must be positive"));
+            }
+            if rate * sample_time > 1.0 + 1e-9 {
+                return Err(format!(
+                    "The {name} rate must not exceed the simulation rate of {} Hz",
+                    1.0 / sample_time
+                ));
+            }
+            if mount.is_some_and(|mount| mount.link == 0 || mount.link > num_links) {
+                return Err(format!(
+                    "The {name} must be mounted on a link between 1 and {num_links}"
+                ));
+            }
+        }
+        if let Some(dvl) = &self.dvl {
+            if !(0.0..=1.0).contains(&dvl.dropout_probability) {
+                return Err("The DVL dropout probability must be between 0 and 1".to_string());
+            }
+        }
+        if self.usbl.as_ref().is_some_and(|usbl| usbl.latency < 0.0) {
+            return Err("The USBL latency must be non-negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Pose, twist and twist derivative of a link, with the velocities expressed in the link frame.
+#[derive(Debug, Clone)]
+pub struct LinkKinematics {
+    pub pose: Isometry3<f64>,
+    pub twist: Vector6<f64>,
+    pub twist_dot: Vector6<f64>,
+}
+
+/// A sensor measurement and its time of validity.
+#[derive(Debug, Clone)]
+pub enum Measurement {
+    Imu {
+        time: f64,
+        angular_rate: Vector3<f64>,
+        specific_force: Vector3<f64>,
+    },
+    Dvl {
+        time: f64,
+        velocity: Vector3<f64>,
+    },
+    Depth {
+        time: f64,
+        depth: f64,
+    },
+    Usbl {
+        time: f64,
+        position: Vector3<f64>,
+    },
+    Encoders {
+        time: f64,
+        angles: SVector<f64, 8>,
+    },
+}
+
+impl Measurement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Measurement::Imu { .. } => "imu",
+            Measurement::Dvl { .. } => "dvl",
+            Measurement::Depth { .. } => "depth",
+            Measurement::Usbl { .. } => "usbl",
+            Measurement::Encoders { .. } => "encoders",
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        match self {
+            Measurement::Imu { time, .. }
+            | Measurement::Dvl { time, .. }
+            | Measurement::Depth { time, .. }
+            | Measurement::Usbl { time, .. }
+            | Measurement::Encoders { time, .. } => *time,
+        }
+    }
+
+    fn values(&self) -> Vec<f64> {
+        match self {
+            Measurement::Imu {
+                angular_rate,
+                specific_force,
+                ..
+            } => angular_rate
+                .iter()
+                .chain(specific_force.iter())
+                .copied()
+                .collect(),
+            Measurement::Dvl { velocity, .. } => velocity.as_slice().to_vec(),
+            Measurement::Depth { depth, .. } => vec![*depth],
+            Measurement::Usbl { position, .. } => position.as_slice().to_vec(),
+            Measurement::Encoders { angles, .. } => angles.as_slice().to_vec(),
+        }
+    }
+}
+
+/// Fires at a fixed rate, starting at t = 0.
+#[derive(Debug, Clone)]
+struct Schedule {
+    period: f64,
+    next: f64,
+}
+
+impl Schedule {
+    fn new(rate: f64) -> Self {
+        Schedule {
+            period: 1.0 / rate,
+            next: 0.0,
+        }
+    }
+
+    fn is_due(&mut self, t: f64) -> bool {
+        if t + 1e-9 < self.next {
+            return false;
+        }
+        while self.next <= t + 1e-9 {
+            self.next += self.period;
+        }
+        true
+    }
+}
+
+/// Runtime state of the sensor suite: sample schedules, IMU biases and USBL fixes in transit.
+#[derive(Debug, Clone)]
+pub struct Sensors {
+    config: SensorConfig,
+    rng: Rng,
+    schedules: [Option<Schedule>; 5],
+    gyro_bias: Vector3<f64>,
+    accel_bias: Vector3<f64>,
+    usbl_queue: VecDeque<(f64, Measurement)>,
+    /// Every delivered measurement, in order of arrival.
+    log: Vec<(f64, Measurement)>,
+}
+
+impl Sensors {
+    pub fn new(cfg: &SensorConfig) -> Self {
+        Sensors {
+            config: cfg.clone(),
+            rng: Rng::new(cfg.seed),
+            schedules: [
+                cfg.imu.as_ref().map(|s| Schedule::new(s.rate)),
+                cfg.dvl.as_ref().map(|s| Schedule::new(s.rate)),
+                cfg.depth.as_ref().map(|s| Schedule::new(s.rate)),
+                cfg.usbl.as_ref().map(|s| Schedule::new(s.rate)),
+                cfg.encoders.as_ref().map(|s| Schedule::new(s.rate)),
+            ],
+            gyro_bias: Vector3::zeros(),
+            accel_bias: Vector3::zeros(),
+            usbl_queue: VecDeque::new(),
+            log: Vec::new(),
+        }
+    }
+
+    fn is_due(&mut self, sensor: usize, t: f64) -> bool {
+        self.schedules[sensor]
+            .as_mut()
+            .is_some_and(|schedule| schedule.is_due(t))
+    }
+
+    /// Samples the sensors that are due at time `t`, given the kinematics of all links and the joint angles.
+    /// Returns the measurements that arrive at `t`.
+    pub fn sample(
+        &mut self,
+        t: f64,
+        links: &[LinkKinematics],
+        theta: &SVector<f64, 8>,
+        gravity: &Vector3<f64>,
+    ) -> Vec<Measurement> {
+        let mut out = Vec::new();
+        let due: [bool; 5] = std::array::from_fn(|sensor| self.is_due(sensor, t));
+        let rng = &mut self.rng;
+
+        if let (true, Some(imu)) = (due[0], &self.config.imu) {
+            let kin = &links[imu.mount.link - 1];
+            let (v, w) = (kin.twist.fixed_rows::<3>(0), kin.twist.fixed_rows::<3>(3));
+            let (v_dot, w_dot) = (
+                kin.twist_dot.fixed_rows::<3>(0),
+                kin.twist_dot.fixed_rows::<3>(3),
+            );
+            let p = imu.mount.position;
+            let accel = v_dot + w.cross(&v) + w_dot.cross(&p) + w.cross(&w.cross(&p));
+            let specific_force = accel - kin.pose.rotation.inverse() * gravity;
+            let rot = imu.mount.rotation().inverse();
+
+            let dt = 1.0 / imu.rate;
+            let (gyro_walk, accel_walk) = (
+                noise(rng, imu.gyro_bias_walk * dt.sqrt()),
+                noise(rng, imu.accel_bias_walk * dt.sqrt()),
+            );
+            self.gyro_bias += gyro_walk;
+            self.accel_bias += accel_walk;
+            out.push(Measurement::Imu {
+                time: t,
+                angular_rate: rot * w + self.gyro_bias + noise(rng, imu.gyro_noise),
+                specific_force: rot * specific_force
+                    + self.accel_bias
+                    + noise(rng, imu.accel_noise),
+            });
+        }
+
+        if let (true, Some(dvl)) = (due[1], &self.config.dvl) {
+            let kin = &links[dvl.mount.link - 1];
+            let (v, w) = (kin.twist.fixed_rows::<3>(0), kin.twist.fixed_rows::<3>(3));
+            let velocity = dvl.mount.rotation().inverse() * (v + w.cross(&dvl.mount.position));
+            if rng.uniform() >= dvl.dropout_probability {
+                out.push(Measurement::Dvl {
+                    time: t,
+                    velocity: velocity + noise(rng, dvl.noise),
+                });
+            }
+        }
+
+        if let (true, Some(depth)) = (due[2], &self.config.depth) {
+            let pos = links[depth.mount.link - 1].pose * Point3::from(depth.mount.position);
+            out.push(Measurement::Depth {
+                time: t,
+                depth: pos[2] - depth.surface_z + depth.noise * rng.normal(),
+            });
+        }
+
+        if let (true, Some(usbl)) = (due[3], &self.config.usbl) {
+            let pos = links[usbl.mount.link - 1].pose * Point3::from(usbl.mount.position);
+            let fix = Measurement::Usbl {
+                time: t,
+                position: pos.coords + noise(rng, usbl.noise),
+            };
+            self.usbl_queue.push_back((t + usbl.latency, fix));
+        }
+        while let Some((arrival, _)) = self.usbl_queue.front() {
+            if *arrival > t + 1e-9 {
+                break;
+            }
+            out.push(self.usbl_queue.pop_front().unwrap().1);
+        }
+
+        if let (true, Some(enc)) = (due[4], &self.config.encoders) {
+            let angles = theta.map(|angle| {
+                let angle = angle + enc.noise * rng.normal();
+                quantize(angle, enc.resolution)
+            });
+            out.push(Measurement::Encoders { time: t, angles });
+        }
+
+        self.log.extend(out.iter().map(|m| (t, m.clone())));
+        out
+    }
+
+    /// Writes the measurement log as rows of arrival time, sensor name, time of validity and values.
+    pub fn save(&self, filename: &Path) {
+        let file = match File::create(filename) {
+            Err(e) => {
+                println!("Could not open file. Error: {:?}", e);
+                return;
+            }
+            Ok(buf) => buf,
+        };
+        let mut buf = BufWriter::new(file);
+        for (t, m) in &self.log {
+            buf.write_fmt(format_args!("{}, {}, {}", t, m.name(), m.time()))
+                .unwrap();
+            for val in m.values() {
+                buf.write_fmt(format_args!(", {}", val)).unwrap();
+            }
+            buf.write_fmt(format_args!("\n")).unwrap();
+        }
+        if let Err(e) = buf.flush() {
+            println!("Could not write to file. Error: {:?}", e);
+        }
+    }
+}
+
+fn noise(rng: &mut Rng, std: f64) -> Vector3<f64> {
+    Vector3::from_fn(|_, _| std * rng.normal())
+}
+
+/// Rounds `value` to the nearest multiple of `resolution`. A zero resolution leaves it unchanged.
+fn quantize(value: f64, resolution: f64) -> f64 {
+    if resolution > 0.0 {
+        (value / resolution).round() * resolution
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinematics_at(z: f64) -> Vec<LinkKinematics> {
+        vec![
+            LinkKinematics {
+                pose: Isometry3::translation(0.0, 0.0, z),
+                twist: Vector6::zeros(),
+                twist_dot: Vector6::zeros(),
+            };
+            9
+        ]
+    }
+
+    #[test]
+    fn test_rates_latency_and_quantization() {
+        let cfg: SensorConfig = serde_yaml::from_str(
+            "
+            imu: {rate: 100.0, position: [0.5, 0.0, 0.0]}
+            usbl: {rate: 1.0, latency: 0.5}
+            encoders: {rate: 20.0, resolution: 0.01}
+            ",
+        )
+        .unwrap();
+        assert!(cfg.validate(9, 0.01).is_ok());
+        let zero_rate = SensorConfig {
+            encoders: serde_yaml::from_str("{rate: 0.0}").unwrap(),
+            ..cfg.clone()
+        };
+        assert!(zero_rate.validate(9, 0.01).is_err());
+        let off_vehicle = SensorConfig {
+            imu: serde_yaml::from_str("{rate: 100.0, link: 10}").unwrap(),
+            ..cfg.clone()
+        };
+        assert!(off_vehicle.validate(9, 0.01).is_err());
+        assert!(cfg.validate(9, 0.02).is_err());
+        let mut sensors = Sensors::new(&cfg);
+        let gravity = Vector3::new(0.0, 0.0, 9.81);
+        let theta = SVector::<f64, 8>::repeat(0.123456);
+
+        let mut counts = [0; 3];
+        let mut usbl_times = Vec::new();
+        for k in 0..200 {
+            let t = k as f64 * 0.01;
+            for m in sensors.sample(t, &kinematics_at(t), &theta, &gravity) {
+                match m {
+                    Measurement::Imu { specific_force, .. } => {
+                        counts[0] += 1;
+                        // at rest, the accelerometer measures the reaction to gravity
+                        assert!((specific_force - Vector3::new(0.0, 0.0, -9.81)).norm() < 1e-12);
+                    }
+                    Measurement::Usbl { time, position } => {
+                        counts[1] += 1;
+                        usbl_times.push((t, time));
+                        assert!((position[2] - time).abs() < 1e-12);
+                    }
+                    Measurement::Encoders { angles, .. } => {
+                        counts[2] += 1;
+                        assert!((angles[0] - 0.12).abs() < 1e-12);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        assert_eq!(counts, [200, 2, 40]);
+        assert!((usbl_times[1].0 - 1.5).abs() < 1e-9 && (usbl_times[1].1 - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,245 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::{Isometry3, Point3, SVector, Vector3, Vector6};
+use serde::Deserialize;
+
+/// Maximum number of lumped masses between the anchor and the vehicle.
+pub const MAX_NODES: usize = 10;
+/// Number of tether states: the positions followed by the velocities of the nodes, in the world frame.
+pub const NUM_STATES: usize = 6 * MAX_NODES;
+
+pub type TetherState = SVector<f64, NUM_STATES>;
+
+fn default_normal_drag_coeff() -> f64 {
+    1.2
+}
+
+fn default_tangential_drag_coeff() -> f64 {
+    0.01
+}
+
+fn default_added_mass_coeff() -> f64 {
+    1.0
+}
+
+/// Lumped-mass tether from a fixed anchor to a point on a link. The cable is divided into `num_segments`
+/// segments of equal unstretched length, with the mass, weight, buoyancy and drag of each segment lumped at
+/// the nodes between them. Segments carry tension only.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TetherConfig {
+    /// Fixed end of the tether in the world frame, e.g. at the surface vessel.
+    anchor: Vector3<f64>,
+    /// Link the tether is attached to (1-based).
+    link: usize,
+    /// Attachment point, expressed in the link frame.
+    #[serde(default)]
+    position: Vector3<f64>,
+    /// Unstretched length [m].
+    length: f64,
+    num_segments: usize,
+    diameter: f64,
+    /// Mass per unit length [kg/m].
+    mass_per_length: f64,
+    /// Axial stiffness EA [N].
+    axial_stiffness: f64,
+    /// Axial damping [N s], multiplying the strain rate.
+    #[serde(default)]
+    axial_damping: f64,
+    #[serde(default = "default_normal_drag_coeff")]
+    normal_drag_coeff: f64,
+    #[serde(default = "default_tangential_drag_coeff")]
+    tangential_drag_coeff: f64,
+    /// Normal added mass coefficient, applied in all directions.
+    #[serde(default = "default_added_mass_coeff")]
+    added_mass_coeff: f64,
+}
+
+impl TetherConfig {
+    pub fn validate(&self, num_links: usize) -> Result<(), String> {
+        if self.num_segments == 0 || self.num_segments > MAX_NODES + 1 {
+            return Err(format!(
+                "The tether must have between 1 and {} segments",
+                MAX_NODES + 1
+            ));
+        }
+        if self.link == 0 || self.link > num_links {
+            return Err(format!(
+                "The tether link must be between 1 and {}",
+                num_links
+            ));
+        }
+        if self.length <= 0.0 || self.axial_stiffness <= 0.0 {
+            return Err("The tether length and axial stiffness must be positive".to_string());
+        }
+        if self.diameter <= 0.0 || self.mass_per_length <= 0.0 {
+            return Err("The tether diameter and mass per length must be positive".to_string());
+        }
+        if self.axial_damping < 0.0
+            || self.normal_drag_coeff < 0.0
+            || self.tangential_drag_coeff < 0.0
+            || self.added_mass_coeff < 0.0
+        {
+            return Err(
+                "The tether damping, drag and added mass coefficients must be non-negative"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Index of the link the tether is attached to.
+    pub fn link(&self) -> usize {
+        self.link - 1
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.num_segments - 1
+    }
+
+    /// Nodes at rest on the straight line from the anchor to the attachment point, given the world pose of
+    /// the attachment link.
+    pub fn initial_state(&self, link_pose: &Isometry3<f64>) -> TetherState {
+        let attachment = link_pose * Point3::from(self.position);
+        let mut state = TetherState::zeros();
+        for k in 0..self.num_nodes() {
+            let s = (k + 1) as f64 / self.num_segments as f64;
+            let node = self.anchor + s * (attachment.coords - self.anchor);
+            state.fixed_rows_mut::<3>(3 * k).copy_from(&node);
+        }
+        state
+    }
+
+    /// Computes the wrench from the tether on the attachment link, expressed in the link frame, and the
+    /// derivative of the tether state. `flow_vel` gives the water velocity at a world position.
+    pub fn dynamics<F>(
+        &self,
+        state: &TetherState,
+        link_pose: &Isometry3<f64>,
+        link_twist: &Vector6<f64>,
+        gravity: &Vector3<f64>,
+        fluid_density: f64,
+        flow_vel: F,
+    ) -> (Vector6<f64>, TetherState)
+    where
+        F: Fn(&Vector3<f64>) -> Vector3<f64>,
+    {
+        let n = self.num_nodes();
+        let l0 = self.length / self.num_segments as f64;
+
+        // end points and nodes, from the anchor to the attachment point
+        let attachment = link_pose * Point3::from(self.position);
+        let attachment_vel = link_pose.rotation
+            * (link_twist.fixed_rows::<3>(0) + link_twist.fixed_rows::<3>(3).cross(&self.position));
+        let mut pos = vec![self.anchor];
+        let mut vel = vec![Vector3::zeros()];
+        for k in 0..n {
+            pos.push(state.fixed_rows::<3>(3 * k).into());
+            vel.push(state.fixed_rows::<3>(3 * (MAX_NODES + k)).into());
+        }
+        pos.push(attachment.coords);
+        vel.push(attachment_vel);
+
+        // tension in each segment, pulling its ends together
+        let tension: Vec<Vector3<f64>> = (0..self.num_segments)
+            .map(|j| {
+                let d = pos[j + 1] - pos[j];
+                let stretched = d.norm().max(1e-9);
+                let dir = d / stretched;
+                let strain = (stretched - l0) / l0;
+                let strain_rate = (vel[j + 1] - vel[j]).dot(&dir) / l0;
+                if strain > 0.0 {
+                    (self.axial_stiffness * strain + self.axial_damping * strain_rate).max(0.0)
+                        * dir
+                } else {
+                    Vector3::zeros()
+                }
+            })
+            .collect();
+
+        let area = 0.25 * PI * self.diameter.powi(2);
+        let mass = (self.mass_per_length + self.added_mass_coeff * fluid_density * area) * l0;
+        let weight = (self.mass_per_length - fluid_density * area) * l0 * gravity;
+
+        let mut state_dot = TetherState::zeros();
+        for k in 1..=n {
+            let tangent = (pos[k + 1] - pos[k - 1]).normalize();
+            let v_r = vel[k] - flow_vel(&pos[k]);
+            let v_t = v_r.dot(&tangent) * tangent;
+            let v_n = v_r - v_t;
+            let drag = -0.5
+                * fluid_density
+                * self.diameter
+                * l0
+                * (self.normal_drag_coeff * v_n.norm() * v_n
+                    + PI * self.tangential_drag_coeff * v_t.norm() * v_t);
+
+            let force = tension[k] - tension[k - 1] + weight + drag;
+            state_dot
+                .fixed_rows_mut::<3>(3 * (k - 1))
+                .copy_from(&vel[k]);
+            state_dot
+                .fixed_rows_mut::<3>(3 * (MAX_NODES + k - 1))
+                .copy_from(&(force / mass));
+        }
+
+        // the last segment pulls the attachment point towards the last node
+        let force = link_pose.rotation.inverse() * -tension[self.num_segments - 1];
+        let moment = self.position.cross(&force);
+        let wrench = Vector6::new(
+            force[0], force[1], force[2], moment[0], moment[1], moment[2],
+        );
+        (wrench, state_dot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hanging_tether() {
+        // a vehicle held 10.1 m below the anchor by a heavy tether with an unstretched length of 10 m
+        let cfg: TetherConfig = serde_yaml::from_str(
+            "
+            anchor: [0.0, 0.0, 0.0]
+            link: 1
+            length: 10.0
+            num_segments: 5
+            diameter: 0.02
+            mass_per_length: 1.0
+            axial_stiffness: 1.0e5
+            axial_damping: 1.0e3
+            ",
+        )
+        .unwrap();
+        assert!(cfg.validate(9).is_ok());
+        let massless = TetherConfig {
+            mass_per_length: 0.0,
+            ..cfg.clone()
+        };
+        assert!(massless.validate(9).is_err());
+        let gravity = Vector3::new(0.0, 0.0, 9.81);
+        let pose = Isometry3::translation(0.0, 0.0, 10.1);
+        let mut state = cfg.initial_state(&pose);
+
+        // settle the nodes with the vehicle held fixed
+        let dt = 1e-4;
+        let mut wrench = Vector6::zeros();
+        for _ in 0..50000 {
+            let (w, state_dot) =
+                cfg.dynamics(&state, &pose, &Vector6::zeros(), &gravity, 1026.0, |_| {
+                    Vector3::zeros()
+                });
+            state += dt * state_dot;
+            wrench = w;
+        }
+
+        // the segment tensions grow by the submerged node weight towards the anchor, and sum to the axial
+        // stiffness times the elongation over the segment length
+        let node_weight = (1.0 - 1026.0 * 0.25 * PI * 0.02f64.powi(2)) * 2.0 * 9.81;
+        let tension = 1e5 * 0.1 / 2.0 / 5.0 - 2.0 * node_weight;
+        assert!((wrench[2] + tension).abs() < 1e-3);
+        assert!(wrench[0].abs() < 1e-9 && wrench.fixed_rows::<3>(3).norm() < 1e-9);
+    }
+}
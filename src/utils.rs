@@ -6,11 +6,18 @@ use std::f64::consts::PI;
 
 use multibody_dynamics::math_functions::skew;
 use na::{
-    Matrix3, Matrix6, Quaternion, SMatrix, SVector, UnitQuaternion, Vector3, Vector4, Vector6,
+    stack, Matrix3, Matrix6, Quaternion, SMatrix, SVector, UnitQuaternion, Vector3, Vector4,
+    Vector6,
 };
 
+use crate::hull::{equivalent_radius, hull_profile, HullProfile};
+use crate::quadrature::Quadrature;
 use crate::Config;
 
+/// Cross-flow angular velocities below this fraction of the translational cross-flow velocity per link
+/// length are neglected, so the cross-flow drag of links with constant sections is computed in closed form.
+const PURE_TRANSLATION_TOL: f64 = 1e-6;
+
 /// Computes the added mass matrix of a slender body.
 pub fn slendermasss(
     length: f64,
@@ -50,8 +57,6 @@ pub fn comp_rb_mass_rotational(
     length: f64,
     mass: f64,
 ) -> Matrix3<f64> {
-    let mut I_r = Matrix3::zeros();
-
     let d = r_cog[2];
     let m_r = d.abs() / radius * mass;
     let m_c = mass - m_r;
@@ -63,14 +68,22 @@ pub fn comp_rb_mass_rotational(
             radius.powi(2) / 4.0 + length.powi(2) / 3.0,
         ));
 
-    I_r[(0, 0)] = m_r * d.powi(2);
-    I_r[(1, 1)] = m_r * (d.powi(2) + length.powi(2) / 3.0);
-    I_r[(2, 2)] = m_r * (length.powi(2) / 3.0);
+    line_mass_rotational(d, length, m_r) + I_c
+}
+
+/// Rotational inertia about the link frame origin of `mass` spread uniformly along a line from x = 0 to
+/// x = `length` at the height `d`.
+pub fn line_mass_rotational(d: f64, length: f64, mass: f64) -> Matrix3<f64> {
+    let mut I_r = Matrix3::zeros();
+
+    I_r[(0, 0)] = mass * d.powi(2);
+    I_r[(1, 1)] = mass * (d.powi(2) + length.powi(2) / 3.0);
+    I_r[(2, 2)] = mass * (length.powi(2) / 3.0);
 
-    I_r[(0, 2)] = -m_r * 0.5 * length * d;
-    I_r[(2, 0)] = -m_r * 0.5 * length * d;
+    I_r[(0, 2)] = -mass * 0.5 * length * d;
+    I_r[(2, 0)] = -mass * 0.5 * length * d;
 
-    I_r + I_c
+    I_r
 }
 
 /// Computes the thruster wrenches in the body frame of the thrusters parent link. The torque scaling factor [m] gives you the moment per thrust [N].
@@ -127,6 +140,15 @@ pub fn comp_tcm<const NUM_DOFS: usize, const NUM_THRUSTERS: usize>(
     tcm
 }
 
+/// Computes the thruster configuration matrix of the thrusts and joint motor torques, [T 0; 0 I], for the 12
+/// thrusters and 8 joint motors.
+pub fn comp_tcm_tot(cfg: &Config, jacs: &[SMatrix<f64, 6, 14>]) -> SMatrix<f64, 14, 20> {
+    stack![
+        comp_tcm::<14, 12>(cfg, jacs),
+        stack![SMatrix::<f64, 6, 8>::zeros(); SMatrix::<f64, 8, 8>::identity()]
+    ]
+}
+
 pub fn cross_flow_drag_rb(
     nu: &Vector6<f64>,
     mu: &Vector6<f64>,
@@ -134,8 +156,9 @@ pub fn cross_flow_drag_rb(
     i: usize,
 ) -> Vector6<f64> {
     let rho = cfg.fluid_density;
-    let r = cfg.radius[i];
+    let r = equivalent_radius(cfg, i);
     let l = cfg.length[i];
+    let profile = hull_profile(cfg, i);
     let drag_surge = cfg.dragcoeffs[i][0];
     let drag_roll = cfg.dragcoeffs[i][1];
     let drag_cross = cfg.dragcoeffs[i][2];
@@ -151,23 +174,45 @@ pub fn cross_flow_drag_rb(
 
     let b = l;
     let a = 0.0;
-    let n = 9;
 
     let drag_2d = &|x: f64| -> Vector4<f64> {
         let mut out = Vector4::<f64>::zeros();
         let sqrtx = ((nu[1] + x * nu[5]).powi(2) + (nu[2] - x * nu[4]).powi(2)).sqrt();
+        // half of the projected width of the strip seen by the cross-flow along y and z
+        let section = profile.section(x, cfg.radius[i], l);
+        let (b_y, b_z) = (section.half_width_y, section.half_width_z);
 
-        out[0] = sqrtx * (mu[1] + x * mu[5]);
-        out[1] = sqrtx * (mu[2] - x * mu[4]);
-        out[2] = sqrtx * (-mu[2] * x + mu[4] * x.powi(2));
-        out[3] = sqrtx * (mu[1] * x + mu[5] * x.powi(2));
+        out[0] = b_y * sqrtx * (mu[1] + x * mu[5]);
+        out[1] = b_z * sqrtx * (mu[2] - x * mu[4]);
+        out[2] = b_z * sqrtx * (-mu[2] * x + mu[4] * x.powi(2));
+        out[3] = b_y * sqrtx * (mu[1] * x + mu[5] * x.powi(2));
 
         out
     };
 
-    let drag_2d_integral = trapz_vec(drag_2d, a, b, n);
+    let omega_cross = f64::max(nu[4].hypot(nu[5]), mu[4].hypot(mu[5]));
+    let drag_2d_integral = match profile {
+        HullProfile::Cylinder | HullProfile::Rectangular { .. }
+            if omega_cross * l <= PURE_TRANSLATION_TOL * nu[1].hypot(nu[2]) =>
+        {
+            // Pure translation: the cross-flow speed and the section are constant along the link
+            let section = profile.section(0.0, cfg.radius[i], l);
+            let (b_y, b_z) = (section.half_width_y, section.half_width_z);
+            let v_cross = nu[1].hypot(nu[2]);
+            Vector4::new(
+                b_y * v_cross * mu[1] * l,
+                b_z * v_cross * mu[2] * l,
+                -b_z * v_cross * mu[2] * l.powi(2) / 2.0,
+                b_y * v_cross * mu[1] * l.powi(2) / 2.0,
+            )
+        }
+        _ => match cfg.drag_quadrature.get(i) {
+            Some(quadrature) => quadrature.integrate(drag_2d, a, b),
+            None => Quadrature::default().integrate(drag_2d, a, b),
+        },
+    };
 
-    let dragcoeff_nonlin = rho * r * drag_cross;
+    let dragcoeff_nonlin = rho * drag_cross;
 
     drag_nonlin[1] = dragcoeff_nonlin * drag_2d_integral[0];
     drag_nonlin[2] = dragcoeff_nonlin * drag_2d_integral[1];
@@ -185,6 +230,31 @@ pub fn cross_flow_drag_rb(
     -drag_lin - drag_nonlin
 }
 
+/// Computes the damping wrench on link `i` at the relative velocity `nu_r`, from the user-supplied damping
+/// matrices if given and the cross-flow drag otherwise.
+pub fn link_damping(cfg: &Config, i: usize, nu_r: &Vector6<f64>) -> Vector6<f64> {
+    match cfg.hydrodynamics.get(i) {
+        Some(hydro) if hydro.has_damping() => hydro.damping(nu_r),
+        _ => cross_flow_drag_rb(nu_r, nu_r, cfg, i),
+    }
+}
+
+/// Computes the Froude–Krylov and added mass wrench on a link from the acceleration `flow_accel` of the
+/// surrounding water, expressed in the link frame: w = (rho V [I; S(r_cob)] + M_A[:, 0..3]) a.
+pub fn froude_krylov_wrench(
+    added_mass: &Matrix6<f64>,
+    displaced_mass: f64,
+    r_cob: &Vector3<f64>,
+    flow_accel: &Vector3<f64>,
+) -> Vector6<f64> {
+    let mut wrench = added_mass.fixed_columns::<3>(0) * flow_accel;
+    let mut force = wrench.fixed_rows_mut::<3>(0);
+    force += displaced_mass * flow_accel;
+    let mut moment = wrench.fixed_rows_mut::<3>(3);
+    moment += displaced_mass * r_cob.cross(flow_accel);
+    wrench
+}
+
 pub fn discrete_quat_update(
     quat: &UnitQuaternion<f64>,
     omega: &Vector3<f64>,
@@ -219,19 +289,6 @@ where
     dx * ((1..n).map(|k| f(a + k as f64 * dx)).sum::<f64>() + (f(b) + f(a)) / 2.)
 }
 
-pub fn trapz_vec<F>(f: F, a: f64, b: f64, n: usize) -> Vector4<f64>
-where
-    F: Fn(f64) -> Vector4<f64>,
-{
-    let mut out = Vector4::<f64>::zeros();
-    let dx: f64 = (b - a) / (n as f64);
-    for i in 0..4 {
-        out[i] =
-            dx * ((1..n).map(|k| f(a + k as f64 * dx)[i]).sum::<f64>() + (f(b) + f(a))[i] / 2.);
-    }
-    out
-}
-
 pub fn trans_mat_quat_dot(quat: &UnitQuaternion<f64>) -> SMatrix<f64, 4, 3> {
     let mut out = SMatrix::<f64, 4, 3>::zeros();
     let quat_vec = quat.vector();
@@ -282,15 +339,36 @@ mod tests {
 
     #[test]
     fn test_comp_tcm() {
-        let mut cfg = Config::default();
-        cfg.thruster_dirs = vec![Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)];
-        cfg.thruster_pos_offsets = vec![Vector3::new(0.24, 0.0, 0.0), Vector3::new(0.35, 0.0, 0.0)];
-        cfg.thruster_parents = vec![1, 1];
+        let cfg = Config {
+            thruster_dirs: vec![Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)],
+            thruster_pos_offsets: vec![Vector3::new(0.24, 0.0, 0.0), Vector3::new(0.35, 0.0, 0.0)],
+            thruster_parents: vec![1, 1],
+            ..Default::default()
+        };
         let jacs = vec![SMatrix::<f64, 6, 6>::identity()];
         let tcm = comp_tcm::<6, 2>(&cfg, &jacs);
         println!("{}", tcm);
     }
 
+    #[test]
+    fn test_cross_flow_drag_pure_translation() {
+        let cfg = Config {
+            radius: vec![0.1],
+            length: vec![1.6],
+            fluid_density: 1026.0,
+            dragcoeffs: vec![Vector6::new(0.2, 0.1, 0.5, 0.1, 0.1, 0.1)],
+            drag_quadrature: vec![Quadrature::gauss_legendre(5)],
+            ..Default::default()
+        };
+        let nu = Vector6::new(0.1, 0.3, -0.2, 0.05, 0.0, 0.0);
+        let drag = cross_flow_drag_rb(&nu, &nu, &cfg, 0);
+
+        // a small rotation disables the closed-form path
+        let nu_rot = nu + Vector6::new(0.0, 0.0, 0.0, 0.0, 1e-6, 1e-6);
+        let drag_quad = cross_flow_drag_rb(&nu_rot, &nu_rot, &cfg, 0);
+        assert!((drag - drag_quad).norm() < 1e-4 * drag.norm());
+    }
+
     #[test]
     fn test_transmat() {
         let quat = UnitQuaternion::from_quaternion(Quaternion::new(
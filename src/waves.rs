@@ -0,0 +1,314 @@
+extern crate nalgebra as na;
+use std::f64::consts::PI;
+
+use na::Vector3;
+use serde::Deserialize;
+
+use crate::random::Rng;
+
+fn default_num_components() -> usize {
+    50
+}
+
+fn default_gamma() -> f64 {
+    3.3
+}
+
+/// Wave elevation model, given by a regular wave or by a wave spectrum.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum WaveSpectrum {
+    /// Regular (Airy) wave of wave `height` [m] and `period` [s].
+    Regular { height: f64, period: f64 },
+    /// Pierson–Moskowitz spectrum with significant wave height `hs` [m] and peak period `tp` [s].
+    PiersonMoskowitz {
+        hs: f64,
+        tp: f64,
+        #[serde(default = "default_num_components")]
+        num_components: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// JONSWAP spectrum with significant wave height `hs` [m], peak period `tp` [s] and peak enhancement factor `gamma`.
+    Jonswap {
+        hs: f64,
+        tp: f64,
+        #[serde(default = "default_gamma")]
+        gamma: f64,
+        #[serde(default = "default_num_components")]
+        num_components: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WaveConfig {
+    /// World z-coordinate of the mean free surface. The world z-axis points down.
+    surface_z: f64,
+    /// Water depth below the mean free surface [m]. Deep water is assumed if omitted.
+    #[serde(default)]
+    water_depth: Option<f64>,
+    /// Direction of wave propagation, measured from the world x-axis towards the y-axis [rad].
+    #[serde(default)]
+    direction: f64,
+    spectrum: WaveSpectrum,
+}
+
+impl WaveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.water_depth.is_some_and(|depth| depth <= 0.0) {
+            return Err("The water depth must be positive".to_string());
+        }
+        let (height, period) = match self.spectrum {
+            WaveSpectrum::Regular { height, period } => (height, period),
+            WaveSpectrum::PiersonMoskowitz {
+                hs,
+                tp,
+                num_components,
+                ..
+            }
+            | WaveSpectrum::Jonswap {
+                hs,
+                tp,
+                num_components,
+                ..
+            } => {
+                if num_components == 0 {
+                    return Err("The wave spectrum needs at least one component".to_string());
+                }
+                (hs, tp)
+            }
+        };
+        if height < 0.0 || period <= 0.0 {
+            return Err(
+                "The wave height must be non-negative and the wave period positive".to_string(),
+            );
+        }
+        if let WaveSpectrum::Jonswap { gamma, .. } = self.spectrum {
+            if gamma <= 0.0 {
+                return Err("The peak enhancement factor must be positive".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WaveComponent {
+    amplitude: f64,
+    omega: f64,
+    wave_number: f64,
+    phase: f64,
+}
+
+/// Long-crested wave field given by a superposition of Airy wave components.
+#[derive(Debug, Clone)]
+pub struct WaveField {
+    components: Vec<WaveComponent>,
+    surface_z: f64,
+    water_depth: Option<f64>,
+    direction: f64,
+}
+
+impl WaveField {
+    pub fn new(cfg: &WaveConfig, gravity: f64) -> Self {
+        let mut components = Vec::new();
+        let mut add_component = |amplitude: f64, omega: f64, phase: f64| {
+            components.push(WaveComponent {
+                amplitude,
+                omega,
+                wave_number: wave_number(omega, gravity, cfg.water_depth),
+                phase,
+            });
+        };
+
+        match &cfg.spectrum {
+            WaveSpectrum::Regular { height, period } => {
+                add_component(0.5 * height, 2.0 * PI / period, 0.0);
+            }
+            WaveSpectrum::PiersonMoskowitz {
+                hs,
+                tp,
+                num_components,
+                seed,
+            } => {
+                let spectrum = |w: f64| pierson_moskowitz(w, *hs, *tp);
+                discretize(spectrum, *tp, *num_components, *seed, &mut add_component);
+            }
+            WaveSpectrum::Jonswap {
+                hs,
+                tp,
+                gamma,
+                num_components,
+                seed,
+            } => {
+                let spectrum = |w: f64| jonswap(w, *hs, *tp, *gamma);
+                discretize(spectrum, *tp, *num_components, *seed, &mut add_component);
+            }
+        }
+
+        WaveField {
+            components,
+            surface_z: cfg.surface_z,
+            water_depth: cfg.water_depth,
+            direction: cfg.direction,
+        }
+    }
+
+    /// Computes the orbital velocity and acceleration of the water at the world position `pos` and time `t`,
+    /// expressed in the world frame. Points above the mean free surface get the kinematics at the surface.
+    pub fn kinematics(&self, t: f64, pos: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        let depth = (pos[2] - self.surface_z).max(0.0);
+        let (c, s) = (self.direction.cos(), self.direction.sin());
+        let horizontal = pos[0] * c + pos[1] * s;
+
+        let mut vel = Vector3::zeros();
+        let mut acc = Vector3::zeros();
+        for comp in &self.components {
+            let theta = comp.wave_number * horizontal - comp.omega * t + comp.phase;
+            let (decay_h, decay_v) = depth_decay(comp.wave_number, depth, self.water_depth);
+            let a_w = comp.amplitude * comp.omega;
+            let a_w2 = a_w * comp.omega;
+
+            let u = a_w * decay_h * theta.cos();
+            let u_dot = a_w2 * decay_h * theta.sin();
+            // vertical components are positive downwards
+            let w = -a_w * decay_v * theta.sin();
+            let w_dot = a_w2 * decay_v * theta.cos();
+
+            vel += Vector3::new(u * c, u * s, w);
+            acc += Vector3::new(u_dot * c, u_dot * s, w_dot);
+        }
+        (vel, acc)
+    }
+}
+
+/// Horizontal and vertical decay of the orbital motion at `depth` below the mean free surface.
+fn depth_decay(k: f64, depth: f64, water_depth: Option<f64>) -> (f64, f64) {
+    match water_depth {
+        Some(h) if k * h < 20.0 => {
+            let z = (h - depth).max(0.0);
+            (
+                (k * z).cosh() / (k * h).sinh(),
+                (k * z).sinh() / (k * h).sinh(),
+            )
+        }
+        _ => {
+            let decay = (-k * depth).exp();
+            (decay, decay)
+        }
+    }
+}
+
+/// Solves the dispersion relation omega^2 = g k tanh(k h) for the wave number k.
+fn wave_number(omega: f64, gravity: f64, water_depth: Option<f64>) -> f64 {
+    let k_deep = omega.powi(2) / gravity;
+    match water_depth {
+        None => k_deep,
+        Some(h) => {
+            let mut k = k_deep.max(omega / (gravity * h).sqrt());
+            for _ in 0..50 {
+                let f = gravity * k * (k * h).tanh() - omega.powi(2);
+                let df = gravity * ((k * h).tanh() + k * h / (k * h).cosh().powi(2));
+                let dk = f / df;
+                k -= dk;
+                if dk.abs() < 1e-12 * k {
+                    break;
+                }
+            }
+            k
+        }
+    }
+}
+
+pub fn pierson_moskowitz(omega: f64, hs: f64, tp: f64) -> f64 {
+    let wp = 2.0 * PI / tp;
+    5.0 / 16.0 * hs.powi(2) * wp.powi(4) * omega.powi(-5) * (-1.25 * (wp / omega).powi(4)).exp()
+}
+
+pub fn jonswap(omega: f64, hs: f64, tp: f64, gamma: f64) -> f64 {
+    let wp = 2.0 * PI / tp;
+    let sigma: f64 = if omega <= wp { 0.07 } else { 0.09 };
+    let r = (-(omega - wp).powi(2) / (2.0 * sigma.powi(2) * wp.powi(2))).exp();
+    (1.0 - 0.287 * gamma.ln()) * pierson_moskowitz(omega, hs, tp) * gamma.powf(r)
+}
+
+/// Discretizes a wave spectrum into `n` components with equal frequency spacing and random phases.
+fn discretize<S, F>(spectrum: S, tp: f64, n: usize, seed: u64, add_component: &mut F)
+where
+    S: Fn(f64) -> f64,
+    F: FnMut(f64, f64, f64),
+{
+    let wp = 2.0 * PI / tp;
+    let (w_min, w_max) = (0.5 * wp, 3.0 * wp);
+    let dw = (w_max - w_min) / n as f64;
+    let mut rng = Rng::new(seed);
+    for i in 0..n {
+        let w = w_min + (i as f64 + 0.5) * dw;
+        add_component((2.0 * spectrum(w) * dw).sqrt(), w, 2.0 * PI * rng.uniform());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spectrum_significant_wave_height() {
+        // the zeroth moment of the spectrum is hs^2 / 16
+        let (hs, tp) = (2.0, 8.0);
+        let m0_pm = crate::utils::trapz(|w| pierson_moskowitz(w, hs, tp), 0.1, 10.0, 5000);
+        let m0_j = crate::utils::trapz(|w| jonswap(w, hs, tp, 3.3), 0.1, 10.0, 5000);
+        assert!((4.0 * m0_pm.sqrt() - hs).abs() < 0.02 * hs);
+        assert!((4.0 * m0_j.sqrt() - hs).abs() < 0.05 * hs);
+    }
+
+    #[test]
+    fn test_regular_wave_kinematics() {
+        let cfg = WaveConfig {
+            surface_z: 0.0,
+            water_depth: None,
+            direction: 0.0,
+            spectrum: WaveSpectrum::Regular {
+                height: 1.0,
+                period: 6.0,
+            },
+        };
+        cfg.validate().unwrap();
+        let waves = WaveField::new(&cfg, 9.81);
+        let omega = 2.0 * PI / 6.0;
+        let k = omega.powi(2) / 9.81;
+        let depth = 2.0;
+
+        let (vel, acc) = waves.kinematics(0.0, &Vector3::new(0.0, 0.0, depth));
+        assert!((vel[0] - 0.5 * omega * (-k * depth).exp()).abs() < 1e-12);
+        assert!(vel[2].abs() < 1e-12);
+        assert!((acc[2] - 0.5 * omega.powi(2) * (-k * depth).exp()).abs() < 1e-12);
+
+        // finite depth approaches deep water for a deep sea floor
+        let shallow = WaveField::new(
+            &WaveConfig {
+                water_depth: Some(200.0),
+                ..cfg.clone()
+            },
+            9.81,
+        );
+        let (vel_shallow, _) = shallow.kinematics(0.0, &Vector3::new(0.0, 0.0, depth));
+        assert!((vel_shallow - vel).norm() < 1e-9);
+
+        let still = WaveConfig {
+            spectrum: WaveSpectrum::Regular {
+                height: 1.0,
+                period: 0.0,
+            },
+            ..cfg.clone()
+        };
+        assert!(still.validate().is_err());
+        let dry = WaveConfig {
+            water_depth: Some(0.0),
+            ..cfg
+        };
+        assert!(dry.validate().is_err());
+    }
+}